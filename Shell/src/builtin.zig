@@ -25,11 +25,13 @@ pub fn help() void {
 }
 
 pub fn shutdown() noreturn {
-    libc.syscalls.shutdown();
+    _ = libc.syscalls.shutdown();
+    unreachable;
 }
 
 pub fn reboot() noreturn {
-    libc.syscalls.reboot();
+    _ = libc.syscalls.reboot();
+    unreachable;
 }
 
 pub fn clear() void {
@@ -2,6 +2,7 @@ const libc = @import("libc");
 const File = libc.stdio.FILE;
 const Slice = libc.sys.raw.Slice;
 const spawn = libc.sys.utils.zpspwan;
+const fork = libc.sys.utils.zfork;
 const wait = libc.syscalls.wait;
 
 var serial: *File = undefined;
@@ -96,6 +97,22 @@ fn test_binary(comptime path: []const u8, args: []const Slice(u8)) !Output {
     return .{ .stdout = buffer, .status = status };
 }
 
+/// like `test_binary` but the binary replaces a fork of the TestBot with `exec`
+fn test_exec(comptime path: []const u8, args: []const Slice(u8)) !Output {
+    const test_log = try File.open("ram:/test.txt", .{ .write = true, .read = true });
+    defer test_log.close();
+
+    const pid = try fork();
+    if (pid == 0) {
+        _ = libc.sys.utils.zexec(path, args);
+        libc.exit(1);
+    }
+    const status = wait(pid);
+
+    const buffer = try test_log.reader().readUntilEOF();
+    return .{ .stdout = buffer, .status = status };
+}
+
 fn meminfo() !Output {
     const output = try test_binary("sys:/bin/meminfo", &[_]Slice(u8){Slice(u8).from("-k")});
     try output.expect(null, 0);
@@ -192,6 +209,165 @@ pub fn ls_test() Error!void {
     output.uninit();
 }
 
+/// written by the child of `fork_test`, the parent has to keep seeing the value from before the fork
+var fork_value: u64 = 1;
+
+pub fn fork_test() Error!void {
+    const pid = try fork();
+    if (pid == 0) {
+        fork_value += 1;
+        libc.exit(fork_value);
+    }
+
+    if (wait(pid) != 2) {
+        extra_info = "the child didn't see it's own write";
+        return error.UnexpectedStatus;
+    }
+
+    if (fork_value != 1) {
+        extra_info = "the write of the child is visible to the parent";
+        return error.UnexpectedError;
+    }
+}
+
+pub fn exec_test() Error!void {
+    // echo doesn't put spaces between it's arguments
+    const output = try test_exec("sys:/bin/echo", make_args(.{ "echo", "exec", " argv" }));
+    try output.expect("exec argv\n", 0);
+    output.uninit();
+}
+
+/// the status of a process killed by the kernel for faulting, see `FAULT_EXIT_CODE` in the kernel
+const FAULT_EXIT_CODE = 139;
+
+pub fn mmap_test() Error!void {
+    const mem = libc.sys.mem;
+    const page = try mem.zmmap(null, 4096, .{ .read = true, .write = true }, .{ .anonymous = true }, 0, 0);
+    const bytes: [*]volatile u8 = page;
+
+    if (bytes[0] != 0 or bytes[4095] != 0) {
+        extra_info = "anonymous memory isn't zeroed";
+        return error.UnexpectedError;
+    }
+
+    bytes[0] = 0xAA;
+    bytes[4095] = 0x55;
+    if (bytes[0] != 0xAA or bytes[4095] != 0x55) {
+        extra_info = "mapped memory didn't keep a write";
+        return error.UnexpectedError;
+    }
+
+    try mem.zmunmap(@ptrCast(page), 4096);
+
+    const pid = try fork();
+    if (pid == 0) {
+        bytes[0] = 1;
+        libc.exit(0);
+    }
+
+    if (wait(pid) != FAULT_EXIT_CODE) {
+        extra_info = "accessing unmapped memory didn't fault";
+        return error.UnexpectedStatus;
+    }
+}
+
+/// forks a child that executes `instructions` and checks that the kernel killed it for faulting
+fn expect_fault(comptime instructions: []const u8) Error!void {
+    const pid = try fork();
+    if (pid == 0) {
+        asm volatile (instructions ::: "rax", "rcx", "rdx");
+        libc.exit(0);
+    }
+
+    if (wait(pid) != FAULT_EXIT_CODE) {
+        extra_info = "executing `" ++ instructions ++ "` in userspace didn't fault";
+        return error.UnexpectedStatus;
+    }
+}
+
+pub fn cpu_fault_test() Error!void {
+    try expect_fault("ud2");
+    // privileged instructions raise a general protection fault in ring3
+    try expect_fault("hlt");
+    try expect_fault("xorl %%ecx, %%ecx; divl %%ecx");
+}
+
+fn exit_with_double(arg: usize) callconv(.C) noreturn {
+    libc.sys.utils.thread_exit(arg * 2);
+}
+
+pub fn thread_test() Error!void {
+    const utils = libc.sys.utils;
+    const first = try utils.zthread_spawn(&exit_with_double, 3);
+    const second = try utils.zthread_spawn(&exit_with_double, 5);
+
+    // joined in reverse so the first thread is likely to have exited before it is joined
+    const second_code = try utils.zthread_join(second);
+    const first_code = try utils.zthread_join(first);
+    if (first_code != 6 or second_code != 10) {
+        extra_info = "a joined thread returned the wrong exit code";
+        return error.UnexpectedStatus;
+    }
+}
+
+fn nanoseconds(tp: libc.sys.raw.TimeSpec) u64 {
+    return tp.seconds * 1_000_000_000 + tp.nanoseconds;
+}
+
+pub fn sleep_test() Error!void {
+    const time = libc.sys.time;
+    const sleep_ms = 50;
+
+    const start = time.uptime();
+    const start_clock = nanoseconds(try time.zclock_gettime(time.CLOCK_MONOTONIC));
+    time.msleep(sleep_ms);
+    const end = time.uptime();
+    const end_clock = nanoseconds(try time.zclock_gettime(time.CLOCK_MONOTONIC));
+
+    if (end < start + sleep_ms) {
+        extra_info = "uptime moved less than the sleep";
+        return error.UnexpectedError;
+    }
+
+    if (end_clock < start_clock + sleep_ms * 1_000_000) {
+        extra_info = "the monotonic clock moved less than the sleep";
+        return error.UnexpectedError;
+    }
+}
+
+/// puts `value` in xmm0 and exits with 0 if it is still there after sleeping and yielding to the other threads
+/// nothing else uses xmm0 because the TestBot is built without sse
+fn keep_xmm0(value: usize) callconv(.C) noreturn {
+    asm volatile ("movq %[value], %%xmm0"
+        :
+        : [value] "r" (value),
+        : "xmm0"
+    );
+
+    for (0..10) |_| {
+        libc.sys.time.msleep(1);
+        libc.syscalls.yield();
+    }
+
+    const kept = asm volatile ("movq %%xmm0, %[kept]"
+        : [kept] "=r" (-> usize),
+    );
+    libc.sys.utils.thread_exit(@intFromBool(kept != value));
+}
+
+pub fn fpu_test() Error!void {
+    const utils = libc.sys.utils;
+    const first = try utils.zthread_spawn(&keep_xmm0, 0x1111_1111_1111_1111);
+    const second = try utils.zthread_spawn(&keep_xmm0, 0x2222_2222_2222_2222);
+
+    const first_code = try utils.zthread_join(first);
+    const second_code = try utils.zthread_join(second);
+    if (first_code != 0 or second_code != 0) {
+        extra_info = "xmm0 changed across a context switch";
+        return error.UnexpectedStatus;
+    }
+}
+
 pub fn memory_info_test() Error!void {
     const output = try meminfo();
     if (!meminfo_output.eql(&output)) {
@@ -3,19 +3,44 @@ const printf = libc.stdio.zprintf;
 const Errno = libc.sys.errno;
 const Dir = libc.dirent.DIR;
 const eql = libc.extra.eql;
+const gmtime = libc.sys.time.zgmtime;
+
+/// prints the size and the modification time of an entry
+fn printLong(ent: libc.sys.raw.DirEntry) !void {
+    const t = gmtime(ent.modified);
+    // printf has no zero padding so each 2-digit field is printed a digit at a time
+    try printf("%lu\t%lu-%lu%lu-%lu%lu %lu%lu:%lu%lu  ", .{
+        ent.size,
+        t.year,
+        t.month / 10,
+        t.month % 10,
+        t.day / 10,
+        t.day % 10,
+        t.hours / 10,
+        t.hours % 10,
+        t.minutes / 10,
+        t.minutes % 10,
+    });
+}
+
 pub fn main() !void {
     var args = libc.sys.args();
     const cwd = try Dir.open(".");
     defer cwd.close();
 
     var raw_output = false;
+    var long_output = false;
     while (args.next()) |arg| {
         if (eql(u8, arg, "--raw")) {
             raw_output = true;
+        } else if (eql(u8, arg, "-l")) {
+            long_output = true;
         }
     }
 
     while (cwd.next()) |ent| {
+        if (long_output) try printLong(ent);
+
         if (!raw_output) {
             if (ent.kind == 1)
                 try printf("\x1B[38;2;0;100;255m%.*s\n\x1B[0m", .{ ent.name_length, &ent.name })
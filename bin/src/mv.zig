@@ -0,0 +1,17 @@
+const libc = @import("libc");
+const io = libc.sys.io;
+const printf = libc.stdio.zprintf;
+
+pub fn main() !void {
+    var args = libc.sys.args();
+    if (args.count() < 3) {
+        try printf("usage: mv <source> <destination>\n", .{});
+        return error.NotEnoughArguments;
+    }
+
+    try io.zrename(args.nth(1).?, args.nth(2).?);
+}
+
+comptime {
+    _ = libc;
+}
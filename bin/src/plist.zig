@@ -1,18 +1,62 @@
+const std = @import("std");
 const libc = @import("libc");
 const sysinfo = libc.sys.utils.zsysinfo;
 const zalloc = libc.stdlib.zalloc;
 const pcollect = libc.sys.utils.zpcollect;
 const printf = libc.stdio.zprintf;
+const eql = libc.extra.eql;
+const ProcessInfo = libc.sys.raw.ProcessInfo;
+const TICKS_PER_SECOND = libc.sys.time.TICKS_PER_SECOND;
+
+fn cpuTicks(process: ProcessInfo) u64 {
+    return process.user_ticks + process.kernel_ticks;
+}
+
+fn moreCpuTime(_: void, a: ProcessInfo, b: ProcessInfo) bool {
+    return cpuTicks(a) > cpuTicks(b);
+}
+
+fn ticksToMs(ticks: u64) u64 {
+    return ticks * 1000 / TICKS_PER_SECOND;
+}
 
 pub fn main() !void {
+    var args = libc.sys.args();
+    var top = false;
+    while (args.next()) |arg| {
+        if (eql(u8, arg, "--top")) top = true;
+    }
+
     const info = try sysinfo();
-    const processes = try zalloc(libc.sys.raw.ProcessInfo, info.processes_count);
+    const processes = try zalloc(ProcessInfo, info.processes_count);
 
     _ = try pcollect(processes);
 
-    try printf("name:  pid  ppid\n", .{});
+    if (!top) {
+        try printf("name:  pid  ppid  nice\n", .{});
+        for (processes) |process| {
+            try printf("\x1B[38;2;0;255;0m%s\x1B[0m:  %d  %d  %d\n", .{ &process.name, process.pid, process.ppid, @as(i32, process.nice) });
+        }
+        return;
+    }
+
+    // the processes using the most cpu time come first
+    std.mem.sort(ProcessInfo, processes, {}, moreCpuTime);
+
+    try printf("pid\tnice\tcpu\tuser ms\tkernel ms\tswitches\tname\n", .{});
     for (processes) |process| {
-        try printf("\x1B[38;2;0;255;0m%s\x1B[0m:  %d  %d\n", .{ &process.name, process.pid, process.ppid });
+        const alive = @max(info.uptime -| process.start_time, 1);
+        const usage = ticksToMs(cpuTicks(process)) * 100 / alive;
+
+        try printf("%lu\t%d\t%lu\t%lu\t%lu\t\t%lu\t\t\x1B[38;2;0;255;0m%s\x1B[0m\n", .{
+            process.pid,
+            @as(i32, process.nice),
+            usage,
+            ticksToMs(process.user_ticks),
+            ticksToMs(process.kernel_ticks),
+            process.context_switches,
+            &process.name,
+        });
     }
 }
 
@@ -0,0 +1,20 @@
+const std = @import("std");
+const libc = @import("libc");
+const printf = libc.stdio.zprintf;
+const setpriority = libc.sys.utils.zsetpriority;
+
+pub fn main() !void {
+    const args = libc.sys.args();
+    if (args.count() < 3) {
+        try printf("usage: renice <nice> <pid>\n", .{});
+        return error.NotEnoughArguments;
+    }
+
+    const nice = try std.fmt.parseInt(c_int, args.nth(1).?, 10);
+    const pid = try std.fmt.parseInt(u64, args.nth(2).?, 10);
+    try setpriority(pid, nice);
+}
+
+comptime {
+    _ = libc;
+}
@@ -0,0 +1,18 @@
+const libc = @import("libc");
+const io = libc.sys.io;
+const printf = libc.stdio.zprintf;
+
+pub fn main() !void {
+    var args = libc.sys.args();
+    if (args.count() < 2) {
+        try printf("expected at least the name of the file to remove\n", .{});
+        return error.NotEnoughArguments;
+    }
+
+    const path = args.nth(1).?;
+    try io.zremove(path);
+}
+
+comptime {
+    _ = libc;
+}
@@ -0,0 +1,20 @@
+const std = @import("std");
+const libc = @import("libc");
+const printf = libc.stdio.zprintf;
+const systrace = libc.sys.utils.zsystrace;
+
+pub fn main() !void {
+    const args = libc.sys.args();
+    if (args.count() < 3) {
+        try printf("usage: systrace <on|off> <pid>\n", .{});
+        return error.NotEnoughArguments;
+    }
+
+    const on = std.mem.eql(u8, args.nth(1).?, "on");
+    const pid = try std.fmt.parseInt(u64, args.nth(2).?, 10);
+    try systrace(pid, on);
+}
+
+comptime {
+    _ = libc;
+}
@@ -0,0 +1,14 @@
+const libc = @import("libc");
+const sysinfo = libc.sys.utils.zsysinfo;
+const printf = libc.stdio.zprintf;
+
+pub fn main() !void {
+    const info = try sysinfo();
+    const seconds = info.uptime / 1000;
+
+    try printf("up %luh %lum %lus, %lu cpus, %lu context switches\n", .{ seconds / 3600, seconds / 60 % 60, seconds % 60, info.cpu_count, info.context_switches });
+}
+
+comptime {
+    _ = libc;
+}
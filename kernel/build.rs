@@ -1,4 +1,412 @@
+use std::{env, fmt::Write as _, fs, path::Path};
+
+use quote::ToTokens;
+use syn::{FnArg, Item, ItemFn, Lit, LitInt, Meta, Pat, ReturnType};
+
+#[path = "../macros/src/abi.rs"]
+mod abi;
+use abi::Arg;
+
+const SYSCALLS_DIR: &str = "src/syscalls";
+/// the machine-readable description of the syscall abi
+/// it is generated into OUT_DIR and the copy checked in at `ABI_PATH` has to be updated by hand
+const ABI_PATH: &str = "syscalls.json";
+/// libc's raw syscall functions, generated from the same description as `ABI_PATH`
+const LIBC_SYSCALLS_PATH: &str = "../libc/src/sys/syscalls.zig";
+/// the registers syscall arguments are passed in, rcx is taken by the return address of `syscall`
+const SYSCALL_REGISTERS: &[&str] = &["rdi", "rsi", "rdx", "r10", "r8", "r9"];
+/// the table entry of the syscall numbers that are not used
+const INVAILD_SYSCALL: &str = "sysinvaild";
+
+struct Syscall {
+    number: usize,
+    func: ItemFn,
+}
+
+/// returns the number given to the `#[syscall]` attribute of `func` if it has one
+fn syscall_number(func: &ItemFn) -> Option<usize> {
+    let attr = func
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("syscall"))?;
+    let number: LitInt = attr.parse_args().expect("expected the syscall number");
+    Some(number.base10_parse().unwrap())
+}
+
+fn collect_syscalls(dir: &Path) -> Vec<Syscall> {
+    let mut syscalls = Vec::new();
+
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let file = syn::parse_file(&fs::read_to_string(&path).unwrap()).unwrap();
+        for item in file.items {
+            if let Item::Fn(func) = item {
+                if let Some(number) = syscall_number(&func) {
+                    syscalls.push(Syscall { number, func });
+                }
+            }
+        }
+    }
+
+    syscalls.sort_by_key(|syscall| syscall.number);
+    for pair in syscalls.windows(2) {
+        assert!(
+            pair[0].number != pair[1].number,
+            "{} and {} have the same syscall number {}",
+            pair[0].func.sig.ident,
+            pair[1].func.sig.ident,
+            pair[0].number
+        );
+    }
+    syscalls
+}
+
+/// the entries of `syscall_table` in `arch/x86_64/syscalls.rs`, indexed by the syscall number
+fn syscall_table(syscalls: &[Syscall]) -> String {
+    let mut table = String::new();
+    let mut syscalls = syscalls.iter().peekable();
+
+    let count = syscalls
+        .clone()
+        .last()
+        .map_or(0, |syscall| syscall.number + 1);
+    for number in 0..count {
+        let name = match syscalls.next_if(|syscall| syscall.number == number) {
+            Some(syscall) => syscall.func.sig.ident.to_string(),
+            None => INVAILD_SYSCALL.to_string(),
+        };
+
+        writeln!(table, "    .quad {name}").unwrap();
+    }
+
+    table
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// the type as it would be written, the token stream puts spaces between every token
+fn type_name(ty: &impl ToTokens) -> String {
+    ty.to_token_stream()
+        .to_string()
+        .replace(" :: ", "::")
+        .replace("* ", "*")
+        .replace(" < ", "<")
+        .replace(" >", ">")
+        .replace("& ", "&")
+}
+
+fn type_string(ty: &impl ToTokens) -> String {
+    json_string(&type_name(ty))
+}
+
+fn doc_lines(func: &ItemFn) -> Vec<String> {
+    func.attrs
+        .iter()
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(meta)) if meta.path.is_ident("doc") => match meta.lit {
+                Lit::Str(doc) => Some(doc.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn doc_string(func: &ItemFn) -> String {
+    json_string(&doc_lines(func).join("\n"))
+}
+
+/// describes every syscall as json, their numbers, names, arguments and return types
+/// see `macros::syscall` for how each kind of argument is passed
+fn abi_description(syscalls: &[Syscall]) -> String {
+    let mut json = String::from("{\n  \"syscalls\": [");
+
+    for (i, syscall) in syscalls.iter().enumerate() {
+        let sig = &syscall.func.sig;
+        let name = sig.ident.to_string();
+
+        let args: Vec<String> = sig
+            .inputs
+            .iter()
+            .map(|input| {
+                let FnArg::Typed(input) = input else {
+                    panic!("syscalls cannot take self");
+                };
+                let Pat::Ident(ref ident) = *input.pat else {
+                    panic!("syscall arguments must be identifiers");
+                };
+
+                let arg = abi::classify(&input.ty);
+                let ty = arg.ty().map_or("null".to_string(), type_string);
+                format!(
+                    "{{ \"name\": {}, \"kind\": \"{}\", \"type\": {} }}",
+                    json_string(&ident.ident.to_string()),
+                    arg.kind(),
+                    ty
+                )
+            })
+            .collect();
+
+        let returns = match &sig.output {
+            ReturnType::Default => "null".to_string(),
+            ReturnType::Type(_, ty) => type_string(ty),
+        };
+
+        let args = if args.is_empty() {
+            "[]".to_string()
+        } else {
+            format!("[\n        {}\n      ]", args.join(",\n        "))
+        };
+
+        if i != 0 {
+            json.push(',');
+        }
+
+        json.push_str("\n    {\n");
+        writeln!(json, "      \"number\": {},", syscall.number).unwrap();
+        writeln!(
+            json,
+            "      \"name\": {},",
+            json_string(name.strip_prefix("sys").unwrap_or(&name))
+        )
+        .unwrap();
+        writeln!(json, "      \"symbol\": {},", json_string(&name)).unwrap();
+        writeln!(json, "      \"doc\": {},", doc_string(&syscall.func)).unwrap();
+        writeln!(json, "      \"args\": {args},").unwrap();
+        writeln!(json, "      \"returns\": {returns}").unwrap();
+        json.push_str("    }");
+    }
+
+    json.push_str("\n  ]\n}\n");
+    json
+}
+
+/// the zig type of the rust type `ty` written by `type_name`, structs are expected to be in libc's `raw.zig`
+fn zig_type(ty: &str) -> String {
+    if let Some(pointee) = ty.strip_prefix("*mut ") {
+        return format!("?*{}", zig_type(pointee));
+    }
+
+    if let Some(pointee) = ty.strip_prefix("*const ") {
+        return format!("?*const {}", zig_type(pointee));
+    }
+
+    match ty {
+        "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize"
+        | "bool" => ty.to_string(),
+        "ErrorStatus" => "usize".to_string(),
+        _ => format!("raw.{}", ty.rsplit("::").next().unwrap()),
+    }
+}
+
+/// the function that makes a syscall with `count` arguments, the number goes in rax and the result comes back in it
+fn zig_syscall_fn(count: usize) -> String {
+    let mut params = String::from("number: usize");
+    let mut inputs = String::new();
+    for (i, register) in SYSCALL_REGISTERS[..count].iter().enumerate() {
+        let i = i + 1;
+        write!(params, ", arg{i}: usize").unwrap();
+        writeln!(inputs, "          [arg{i}] \"{{{register}}}\" (arg{i}),").unwrap();
+    }
+
+    let mut zig = String::new();
+    writeln!(zig, "inline fn syscall{count}({params}) usize {{").unwrap();
+    writeln!(zig, "    return asm volatile (\"syscall\"").unwrap();
+    writeln!(zig, "        : [ret] \"={{rax}}\" (-> usize),").unwrap();
+    writeln!(zig, "        : [number] \"{{rax}}\" (number),").unwrap();
+    zig.push_str(&inputs);
+    writeln!(zig, "        : \"rcx\", \"r11\"").unwrap();
+    writeln!(zig, "    );").unwrap();
+    writeln!(zig, "}}").unwrap();
+    zig
+}
+
+/// generates libc's raw syscall functions, see `macros::syscall` for how each kind of argument is passed
+/// every argument is turned into the register it is passed in by `register`
+fn libc_syscalls(syscalls: &[Syscall]) -> String {
+    let mut zig = String::from(
+        "//! the raw syscall functions, generated by the kernel's build script from it's `#[syscall]` functions
+//! do not edit, see `LIBC_SYSCALLS_PATH` in kernel/build.rs
+const std = @import(\"std\");
+const raw = @import(\"raw.zig\");
+
+// the syscalls are made with the `syscall` instruction which overwrites rcx and r11 with the return address and the flags
+// so the 4th argument is passed in r10 instead of rcx
+
+/// converts an argument of a syscall to the register it is passed in
+inline fn register(value: anytype) usize {
+    const T = @TypeOf(value);
+    return switch (@typeInfo(T)) {
+        .Int => |int| if (int.signedness == .signed) @bitCast(@as(isize, value)) else value,
+        .Bool => @intFromBool(value),
+        .Pointer, .Optional => @intFromPtr(value),
+        // packed structs such as the flags
+        .Struct => @as(std.meta.Int(.unsigned, @bitSizeOf(T)), @bitCast(value)),
+        else => @compileError(\"cannot pass a \" ++ @typeName(T) ++ \" to a syscall\"),
+    };
+}
+",
+    );
+
+    for count in 0..=SYSCALL_REGISTERS.len() {
+        zig.push('\n');
+        zig.push_str(&zig_syscall_fn(count));
+    }
+
+    let name = |syscall: &Syscall| {
+        let name = syscall.func.sig.ident.to_string();
+        name.strip_prefix("sys").unwrap_or(&name).to_string()
+    };
+    // zig doesn't let parameters shadow the declarations of the file
+    let declarations: Vec<String> = syscalls
+        .iter()
+        .map(name)
+        .chain(["std", "raw", "register"].map(String::from))
+        .collect();
+
+    for syscall in syscalls {
+        let sig = &syscall.func.sig;
+        let name = name(syscall);
+
+        let mut params = Vec::new();
+        let mut registers = Vec::new();
+        for input in &sig.inputs {
+            let FnArg::Typed(input) = input else {
+                panic!("syscalls cannot take self");
+            };
+            let Pat::Ident(ref ident) = *input.pat else {
+                panic!("syscall arguments must be identifiers");
+            };
+
+            let mut arg = ident.ident.to_string();
+            if declarations.contains(&arg) {
+                arg.push_str("_arg");
+            }
+
+            let kind = abi::classify(&input.ty);
+            let ty = kind.ty().map(|ty| zig_type(&type_name(ty)));
+            let ty = ty.as_deref().unwrap_or_default();
+            let constness = |mutable| if mutable { "" } else { "const " };
+
+            // slices and strs are passed as a pointer and a length
+            let slice = match kind {
+                Arg::Str { .. } => Some("[*]const u8".to_string()),
+                Arg::StrSlice { .. } => Some("[*]const raw.Slice(u8)".to_string()),
+                Arg::Slice { mutable, .. } => Some(format!("[*]{}{ty}", constness(mutable))),
+                _ => None,
+            };
+
+            if let Some(ptr) = slice {
+                params.push(format!("{arg}_ptr: {ptr}, {arg}_len: usize"));
+                registers.push(format!("register({arg}_ptr)"));
+                registers.push(format!("register({arg}_len)"));
+                continue;
+            }
+
+            let ty = match kind {
+                Arg::Optional(_) => format!("?*{ty}"),
+                Arg::Required { mutable, .. } => format!("*{}{ty}", constness(mutable)),
+                _ => ty.to_string(),
+            };
+            params.push(format!("{arg}: {ty}"));
+            registers.push(format!("register({arg})"));
+        }
+
+        assert!(
+            registers.len() <= SYSCALL_REGISTERS.len(),
+            "{name} takes more arguments than there are registers to pass them in"
+        );
+
+        let call = format!(
+            "syscall{}({}{}{})",
+            registers.len(),
+            syscall.number,
+            if registers.is_empty() { "" } else { ", " },
+            registers.join(", ")
+        );
+
+        let (returns, body) = match &sig.output {
+            ReturnType::Default => ("void".to_string(), format!("_ = {call};")),
+            ReturnType::Type(_, ty) => {
+                let returns = zig_type(&type_name(ty));
+                let body = if returns.starts_with('?') {
+                    format!("return @ptrFromInt({call});")
+                } else {
+                    format!("return {call};")
+                };
+                (returns, body)
+            }
+        };
+
+        zig.push('\n');
+        for line in doc_lines(&syscall.func) {
+            writeln!(zig, "/// {line}").unwrap();
+        }
+        writeln!(
+            zig,
+            "pub inline fn {name}({}) {returns} {{\n    {body}\n}}",
+            params.join(", ")
+        )
+        .unwrap();
+    }
+
+    zig
+}
+
+/// fails the build if the checked in copy at `path` of a generated file is out of date
+/// the build never writes into the source tree, the generated file in OUT_DIR has to be copied by hand
+fn check_up_to_date(root: &Path, path: &str, generated: &str) {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let generated_path = Path::new(&out_dir).join(Path::new(path).file_name().unwrap());
+    fs::write(&generated_path, generated).unwrap();
+
+    let path = root.join(path);
+    println!("cargo:rerun-if-changed={}", path.display());
+    if fs::read_to_string(&path).ok().as_deref() != Some(generated) {
+        panic!(
+            "{} is out of date, update it with `cp {} {}`",
+            path.display(),
+            generated_path.display(),
+            path.display()
+        );
+    }
+}
+
+/// generates the syscall table, the abi description and libc's syscall functions from the `#[syscall]` functions in `SYSCALLS_DIR`
+fn generate_syscalls() {
+    let root = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let root = Path::new(&root);
+    println!("cargo:rerun-if-changed={SYSCALLS_DIR}");
+    let syscalls = collect_syscalls(&root.join(SYSCALLS_DIR));
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("syscall_table.s"),
+        syscall_table(&syscalls),
+    )
+    .unwrap();
+
+    check_up_to_date(root, ABI_PATH, &abi_description(&syscalls));
+    check_up_to_date(root, LIBC_SYSCALLS_PATH, &libc_syscalls(&syscalls));
+}
+
 fn main() {
     println!("cargo:rustc-link-arg=-Tkernel/linker.ld");
     println!("cargo:rerun-if-changed=kernel/linker.ld");
+    generate_syscalls();
 }
@@ -7,8 +7,20 @@ pub use x86_64::threading;
 #[cfg(target_arch = "x86_64")]
 pub use x86_64::{init_phase1, init_phase2};
 
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::cmos;
+
 #[cfg(target_arch = "x86_64")]
 pub use x86_64::power;
 
 #[cfg(target_arch = "x86_64")]
 pub use x86_64::serial;
+
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::smp;
+
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::tsc;
+
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::usercopy;
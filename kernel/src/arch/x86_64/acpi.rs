@@ -268,7 +268,7 @@ impl SDT for MADT {
         let base = self.nth(0).0;
         let mut record = base + (*(base as *const MADTRecord)).length as usize;
 
-        for _ in 1..n - 1 {
+        for _ in 1..n {
             let next_record = record as *const MADTRecord;
             let len = (*next_record).length;
             record += len as usize;
@@ -279,6 +279,29 @@ impl SDT for MADT {
 }
 
 impl MADT {
+    /// iterates over every record of the madt
+    pub fn records(&self) -> impl Iterator<Item = *const MADTRecord> + '_ {
+        let start = self as *const Self as usize + size_of::<MADT>();
+        let end = self as *const Self as usize + self.header.len as usize;
+        let mut current = start;
+
+        core::iter::from_fn(move || {
+            if current + size_of::<MADTRecord>() > end {
+                return None;
+            }
+
+            let record = current as *const MADTRecord;
+            let length = unsafe { (*record).length } as usize;
+            // a broken record would loop forever
+            if length == 0 {
+                return None;
+            }
+
+            current += length;
+            Some(record)
+        })
+    }
+
     pub unsafe fn get_record_of_type(&self, ty: u8) -> Option<*const MADTRecord> {
         let len = self.header.len;
         let mut current_offset = 0;
@@ -0,0 +1,13 @@
+//! the cmos memory which holds the registers of the real-time clock, see `drivers::rtc`
+use super::{inb, outb};
+
+const CMOS_ADDRESS_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+/// bit 7 of the address disables nmis while the cmos is being accessed
+const NMI_DISABLE: u8 = 1 << 7;
+
+/// reads the cmos register `reg`
+pub fn read_reg(reg: u8) -> u8 {
+    outb(CMOS_ADDRESS_PORT, NMI_DISABLE | reg);
+    inb(CMOS_DATA_PORT)
+}
@@ -1,7 +1,9 @@
 #![allow(static_mut_refs)]
 use core::arch::asm;
 
-use lazy_static::lazy_static;
+use crate::{threading::STACK_SIZE, VirtAddr};
+
+use super::smp::{cpu_local, MAX_CPUS};
 
 #[repr(C, packed)]
 pub struct GDTEntry {
@@ -92,93 +94,112 @@ impl TaskStateSegment {
     }
 }
 
-macro_rules! alloc_stack {
-    () => {{
-        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+/// a stack of the interrupt stack table
+#[repr(C, align(16))]
+struct InterruptStack([u8; STACK_SIZE]);
 
-        let stack_start = unsafe { &STACK.as_ptr() };
-        let stack_end = unsafe { stack_start.add(STACK_SIZE) };
-        stack_end as u64
-    }};
-}
-lazy_static! {
-    pub static ref TSS: TaskStateSegment = {
-        use crate::threading::STACK_SIZE;
+/// the stacks the cpus switch to for the interrupts that use the interrupt stack table, 2 per cpu
+static mut INTERRUPT_STACKS: [[InterruptStack; 2]; MAX_CPUS] =
+    [const { [const { InterruptStack([0; STACK_SIZE]) }; 2] }; MAX_CPUS];
 
-        let mut tss = TaskStateSegment::new();
+/// every cpu has it's own tss because it holds the stacks the cpu switches to
+static mut TSS: [TaskStateSegment; MAX_CPUS] = [TaskStateSegment::new(); MAX_CPUS];
 
-        tss.interrupt_stack_table[0] = alloc_stack!();
-        tss.interrupt_stack_table[1] = alloc_stack!();
-        tss.privilege_stack_table[0] = crate::threading::RING0_STACK_END as u64;
+/// sets the stack the cpu switches to when an interrupt or a syscall happens in ring3
+/// changed on every context switch to the ring0 stack of the running thread
+pub fn set_ring0_stack(stack_end: VirtAddr) {
+    let local = cpu_local();
+    local.kernel_stack_top = stack_end;
+    unsafe {
+        let rsp0 = core::ptr::addr_of_mut!(TSS[local.id].privilege_stack_table) as *mut u64;
+        rsp0.write_unaligned(stack_end as u64);
+    }
+}
 
-        tss
-    };
+/// the end of the stack the cpu switches to when an interrupt or a syscall happens in ring3
+#[inline(always)]
+pub fn ring0_stack_end() -> VirtAddr {
+    cpu_local().kernel_stack_top
 }
 pub type GDTType = [GDTEntry; 7];
-//  TODO: improve this
-lazy_static! {
-    pub static ref GDT: GDTType = [
-        GDTEntry::default().into(),
+/// every cpu has it's own gdt because the tss segment points to the tss of the cpu
+static mut GDT: [GDTType; MAX_CPUS] = [const { [const { GDTEntry::default() }; 7] }; MAX_CPUS];
+
+fn create_gdt(tss: &TaskStateSegment) -> GDTType {
+    let tss = tss as *const TaskStateSegment as u64;
+
+    [
+        GDTEntry::default(),
         GDTEntry::new(
             0,
             0xFFFFF,
             ACCESS_VAILD | NON_SYSTEM | ACCESS_WRITE_READ | ACCESS_EXECUTABLE,
-            FLAG_PAGELIMIT | FLAG_LONG
+            FLAG_PAGELIMIT | FLAG_LONG,
         ), // kernel code segment
         GDTEntry::new(
             0,
             0xFFFFF,
             ACCESS_VAILD | ACCESS_WRITE_READ | NON_SYSTEM,
-            FLAG_PAGELIMIT | FLAG_LONG
+            FLAG_PAGELIMIT | FLAG_LONG,
         ), // kernel data segment
-
         GDTEntry::new(
-            (((&*TSS) as *const TaskStateSegment as u64) & 0xFFFFFFFF) as u32,
+            (tss & 0xFFFFFFFF) as u32,
             (size_of::<TaskStateSegment>() - 1) as u32,
             ACCESS_VAILD | ACCESS_TYPE_TSS,
-            FLAG_PAGELIMIT | FLAG_LONG
+            FLAG_PAGELIMIT | FLAG_LONG,
         ), // TSS segment
-        GDTEntry::new_upper_64seg(
-            &*TSS as *const TaskStateSegment as u64,
-        ),
-
+        GDTEntry::new_upper_64seg(tss),
         GDTEntry::new(
             0,
             0xFFFFF,
-            ACCESS_VAILD | NON_SYSTEM | ACCESS_DPL0 | ACCESS_DPL1 | ACCESS_WRITE_READ | ACCESS_EXECUTABLE,
-            FLAG_PAGELIMIT | FLAG_LONG
-        ), // user code segment
+            ACCESS_VAILD | NON_SYSTEM | ACCESS_DPL0 | ACCESS_DPL1 | ACCESS_WRITE_READ,
+            FLAG_PAGELIMIT | FLAG_LONG,
+        ), // user data segment
         GDTEntry::new(
             0,
             0xFFFFF,
-            ACCESS_VAILD | NON_SYSTEM | ACCESS_DPL0 | ACCESS_DPL1 | ACCESS_WRITE_READ,
-            FLAG_PAGELIMIT | FLAG_LONG
-        ) // user data segment
-    ];
+            ACCESS_VAILD
+                | NON_SYSTEM
+                | ACCESS_DPL0
+                | ACCESS_DPL1
+                | ACCESS_WRITE_READ
+                | ACCESS_EXECUTABLE,
+            FLAG_PAGELIMIT | FLAG_LONG,
+        ), // user code segment
+    ]
 }
 
 pub const KERNEL_CODE_SEG: u8 = (1 * 8) | 0;
 pub const KERNEL_DATA_SEG: u8 = (2 * 8) | 0;
 pub const TSS_SEG: u8 = 3 * 8 | 3;
 
-pub const USER_CODE_SEG: u8 = (5 * 8) | 3;
-pub const USER_DATA_SEG: u8 = (6 * 8) | 3;
+// the user data segment comes right before the user code segment because `sysret` expects them in that order
+pub const USER_DATA_SEG: u8 = (5 * 8) | 3;
+pub const USER_CODE_SEG: u8 = (6 * 8) | 3;
 
 #[repr(C, packed)]
 pub struct GDTDescriptor {
     pub limit: u16,
     pub base: usize,
 }
-lazy_static! {
-    pub static ref GDT_DESCRIPTOR: GDTDescriptor = GDTDescriptor {
-        limit: (size_of::<GDTType>() - 1) as u16,
-        base: (&*GDT) as *const GDTType as usize
-    };
-}
 
-pub fn init_gdt() {
+/// sets up the tss and the gdt of the cpu `cpu` and loads them
+pub fn init_gdt(cpu: usize) {
     unsafe {
-        asm!("lgdt [{}]", in(reg) &*GDT_DESCRIPTOR, options(nostack));
+        let tss = &mut TSS[cpu];
+        let [ref stack0, ref stack1] = INTERRUPT_STACKS[cpu];
+
+        tss.interrupt_stack_table[0] = stack0.0.as_ptr_range().end as u64;
+        tss.interrupt_stack_table[1] = stack1.0.as_ptr_range().end as u64;
+        tss.privilege_stack_table[0] = (crate::threading::ring0_stack_start(0) + STACK_SIZE) as u64;
+
+        GDT[cpu] = create_gdt(tss);
+        let descriptor = GDTDescriptor {
+            limit: (size_of::<GDTType>() - 1) as u16,
+            base: &GDT[cpu] as *const GDTType as usize,
+        };
+
+        asm!("lgdt [{}]", in(reg) &descriptor, options(nostack));
 
         asm!(
             "
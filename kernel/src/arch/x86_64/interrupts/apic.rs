@@ -1,3 +1,5 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use super::read_msr;
 use bitflags::bitflags;
 
@@ -77,6 +79,40 @@ pub fn get_local_apic_reg(local_apic_addr: VirtAddr, local_apic_reg: u16) -> Vir
     local_apic_addr + local_apic_reg as usize
 }
 
+/// the id of the local apic of the current cpu
+#[inline]
+pub fn local_apic_id() -> u8 {
+    let id = get_local_apic_reg(get_local_apic_addr(), 0x20) as *const u32;
+    // the id is in the highest byte
+    (unsafe { core::ptr::read_volatile(id) } >> 24) as u8
+}
+
+/// interrupts the target cpu with the vector in the lowest byte
+pub const IPI_FIXED: u32 = 0x4000;
+/// asserts an init of the target cpu
+pub const IPI_INIT: u32 = 0x4500;
+/// starts the target cpu in real mode at the page number in the lowest byte
+pub const IPI_STARTUP: u32 = 0x4600;
+/// set in the low register of the icr while the ipi is being sent
+const IPI_PENDING: u32 = 1 << 12;
+
+/// sends the inter-processor interrupt `command` to the cpu with the local apic id `apic_id`
+pub fn send_ipi(apic_id: u8, command: u32) {
+    let local_apic_addr = get_local_apic_addr();
+    let icr_low = get_local_apic_reg(local_apic_addr, 0x300) as *mut u32;
+    let icr_high = get_local_apic_reg(local_apic_addr, 0x310) as *mut u32;
+
+    unsafe {
+        core::ptr::write_volatile(icr_high, (apic_id as u32) << 24);
+        // writing the low register sends the ipi
+        core::ptr::write_volatile(icr_low, command);
+
+        while core::ptr::read_volatile(icr_low) & IPI_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
 // NOTES:
 // when we write the offset of the reg we want to access to ioregsel, iowin should have that reg
 // no it is not the addr of that reg it is the reg itself each reg is 32bits long
@@ -147,31 +183,91 @@ fn enable_apic_keyboard(ioapic_addr: VirtAddr, apic_id: u8) {
     }
 }
 
+/// divides the bus clock the apic timer counts with by 16
+const TIMER_DIVIDE_BY_16: u32 = 0x3;
+/// how long the apic timer is measured against the pit
+const CALIBRATION_MS: u64 = 10;
+/// the initial count of the apic timer that makes it fire every tick, measured once by the bootstrap processor
+/// the other cpus reuse it as they all share the same bus clock
+static COUNTS_PER_TICK: AtomicU32 = AtomicU32::new(0);
+
+/// measures how many times the apic timer counts in a millisecond using the pit
+fn calibrate_apic_timer(local_apic_addr: VirtAddr) -> u32 {
+    let timer = LVTEntry::new(0x20, LVTEntryFlags::DISABLED);
+
+    let addr = get_local_apic_reg(local_apic_addr, 0x320) as *mut u32;
+    let init = get_local_apic_reg(local_apic_addr, 0x380) as *mut u32;
+    let current = get_local_apic_reg(local_apic_addr, 0x390) as *const u32;
+    let divide = get_local_apic_reg(local_apic_addr, 0x3E0) as *mut u32;
+
+    unsafe {
+        core::ptr::write_volatile(addr, timer.encode_u32());
+        core::ptr::write_volatile(divide, TIMER_DIVIDE_BY_16);
+        core::ptr::write_volatile(init, u32::MAX);
+
+        super::super::pit::wait_ms(CALIBRATION_MS);
+
+        let elapsed = u32::MAX - core::ptr::read_volatile(current);
+        core::ptr::write_volatile(init, 0);
+
+        (elapsed / CALIBRATION_MS as u32).max(1)
+    }
+}
+
+/// makes the apic timer fire `crate::time::TICKS_PER_SECOND` times a second
 fn enable_apic_timer(local_apic_addr: VirtAddr) {
+    let counts_per_ms = calibrate_apic_timer(local_apic_addr) as u64;
+    let counts_per_tick = (counts_per_ms * 1000 / crate::time::TICKS_PER_SECOND).max(1);
+
+    COUNTS_PER_TICK.store(counts_per_tick as u32, Ordering::Relaxed);
+    start_apic_timer(local_apic_addr, counts_per_tick as u32);
+}
+
+/// makes the apic timer fire every `counts_per_tick` counts
+fn start_apic_timer(local_apic_addr: VirtAddr, counts_per_tick: u32) {
     let timer = LVTEntry::new(0x20, LVTEntryFlags::TIMER_PERIODIC);
 
     let addr = get_local_apic_reg(local_apic_addr, 0x320) as *mut u32;
     let init = get_local_apic_reg(local_apic_addr, 0x380) as *mut u32;
-    let divide = get_local_apic_reg(local_apic_addr, 0x3E0) as *mut u8;
+    let divide = get_local_apic_reg(local_apic_addr, 0x3E0) as *mut u32;
 
     unsafe {
         core::ptr::write_volatile(addr, timer.encode_u32());
-        core::ptr::write_volatile(divide, 0xB);
-        core::ptr::write_volatile(init, 0x100000);
+        core::ptr::write_volatile(divide, TIMER_DIVIDE_BY_16);
+        core::ptr::write_volatile(init, counts_per_tick);
     }
 }
 
+/// returns true if the interrupt being handled is the apic timer's
+/// false for `int 0x20` which is used to yield
+#[inline]
+pub fn is_timer_interrupt() -> bool {
+    // bit 0 of the in-service register for the vectors 0x20..0x40
+    let isr = get_local_apic_reg(get_local_apic_addr(), 0x110) as *const u32;
+    unsafe { core::ptr::read_volatile(isr) & 1 != 0 }
+}
+
+/// enables the local apic of the current cpu with the spurious interrupt vector 0xff
+fn enable_local_apic(local_apic_addr: VirtAddr) {
+    let sivr = get_local_apic_reg(local_apic_addr, 0xF0) as *mut u32;
+    unsafe { core::ptr::write_volatile(sivr, 0x1ff) }
+}
+
 pub fn enable_apic_interrupts() {
     let local_apic_addr = get_local_apic_addr();
-    let sivr = get_local_apic_reg(local_apic_addr, 0xF0) as *mut u32;
+    enable_local_apic(local_apic_addr);
 
-    unsafe {
-        core::ptr::write_volatile(sivr, 0x1ff);
+    let madt = MADT::get(acpi::get_sdt());
+    let ioapic_addr = get_io_apic_addr(madt);
+    enable_apic_timer(local_apic_addr);
+    // the keyboard interrupts always go to the bootstrap processor
+    enable_apic_keyboard(ioapic_addr, local_apic_id());
+}
 
-        let madt = MADT::get(acpi::get_sdt());
-        let ioapic_addr = get_io_apic_addr(madt);
-        let apic_id = *(get_local_apic_reg(local_apic_addr, 0x20) as *const u8);
-        enable_apic_timer(local_apic_addr);
-        enable_apic_keyboard(ioapic_addr, apic_id);
-    }
+/// enables the local apic and the timer of an application processor
+/// must be called after `enable_apic_interrupts` calibrated the timer on the bootstrap processor
+pub fn enable_ap_apic_interrupts() {
+    let local_apic_addr = get_local_apic_addr();
+    enable_local_apic(local_apic_addr);
+    start_apic_timer(local_apic_addr, COUNTS_PER_TICK.load(Ordering::Relaxed));
 }
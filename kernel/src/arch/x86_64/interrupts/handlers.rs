@@ -7,8 +7,11 @@ use super::idt::{GateDescriptor, IDTT};
 use super::{InterruptFrame, TrapFrame};
 
 use crate::arch::x86_64::interrupts::apic::send_eoi;
-use crate::arch::x86_64::{inb, threading};
-use crate::{drivers, serial};
+use crate::arch::x86_64::{inb, smp, threading, usercopy};
+use crate::memory::paging::USERSPACE_END;
+use crate::threading::expose;
+use crate::{drivers, scheduler_inited, serial};
+use bitflags::bitflags;
 
 const ATTR_TRAP: u8 = 0xF;
 const ATTR_INT: u8 = 0xE;
@@ -43,30 +46,83 @@ lazy_static! {
         (0, divide_by_zero_handler, ATTR_INT),
         (3, breakpoint_handler, ATTR_INT | ATTR_RING3),
         (6, invaild_opcode, ATTR_INT),
+        (7, device_not_available_handler, ATTR_INT),
         (8, dobule_fault_handler, ATTR_TRAP, 0),
         (0xC, stack_segment_fault_handler, ATTR_TRAP, 0),
-        (13, general_protection_fault_handler, ATTR_TRAP),
-        (14, page_fault_handler, ATTR_TRAP),
+        (13, general_protection_fault_handler, ATTR_INT),
+        (14, page_fault_handler, ATTR_INT),
         (0x20, threading::context_switch_stub, ATTR_INT, 1),
         (0x21, keyboard_interrupt_handler, ATTR_INT),
+        (0x22, tlb_shootdown_handler, ATTR_INT),
         (0x80, syscall_base, ATTR_INT | ATTR_RING3)
     );
 }
 
+/// swaps in the gs base of the kernel if the interrupted code was in ring3 and swaps it back out when dropped
+/// has to be the first thing created in handlers that can interrupt ring3 and return
+/// such handlers have to use interrupt gates, an interrupt that arrives before the swap would run with the gs base of ring3
+/// the alignment check flag ring3 may have set is cleared too so the handler cannot access userspace, see `usercopy`
+/// it is restored with the rest of the flags of the interrupted code by `iretq`
+struct SwapGSGuard(bool);
+
+impl SwapGSGuard {
+    #[inline(always)]
+    fn new(code_segment: u64) -> Self {
+        let from_userspace = code_segment & 3 == 3;
+        if from_userspace {
+            unsafe { asm!("swapgs", options(nostack, preserves_flags)) }
+            usercopy::deny_user_access();
+        }
+        Self(from_userspace)
+    }
+}
+
+impl Drop for SwapGSGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if self.0 {
+            unsafe { asm!("swapgs", options(nostack, preserves_flags)) }
+        }
+    }
+}
+
+/// kills the process of the current thread like a page fault would if the fault at `insturaction` was caused by ring3
+/// returns if it was caused by the kernel which has to panic
+fn userspace_fault(code_segment: u64, insturaction: u64, stack_pointer: u64) {
+    if code_segment & 3 == 3 && scheduler_inited() {
+        expose::thread_fault(
+            insturaction as usize,
+            insturaction as usize,
+            stack_pointer as usize,
+        );
+    }
+}
+
 #[no_mangle]
 extern "x86-interrupt" fn divide_by_zero_handler(frame: InterruptFrame) {
+    let _gs = SwapGSGuard::new(frame.code_segment);
+    userspace_fault(frame.code_segment, frame.insturaction, frame.stack_pointer);
     panic!("---- Divide By Zero Exception ----\n{}", frame);
 }
 
 extern "x86-interrupt" fn invaild_opcode(frame: InterruptFrame) {
+    let _gs = SwapGSGuard::new(frame.code_segment);
+    userspace_fault(frame.code_segment, frame.insturaction, frame.stack_pointer);
     panic!("---- Invaild OPCODE ----\n{}", frame);
 }
 
 #[no_mangle]
 extern "x86-interrupt" fn breakpoint_handler(frame: InterruptFrame) {
+    let _gs = SwapGSGuard::new(frame.code_segment);
     serial!("hi from interrupt, breakpoint!\n{}", frame);
 }
 
+/// raised by the first use of the x87, sse or avx registers since the last context switch
+extern "x86-interrupt" fn device_not_available_handler(frame: InterruptFrame) {
+    let _gs = SwapGSGuard::new(frame.code_segment);
+    threading::fpu::handle_device_not_available();
+}
+
 #[no_mangle]
 extern "x86-interrupt" fn dobule_fault_handler(frame: TrapFrame) {
     panic!("---- Double Fault ----\n{}", frame);
@@ -79,15 +135,60 @@ extern "x86-interrupt" fn stack_segment_fault_handler(frame: TrapFrame) {
 
 #[no_mangle]
 extern "x86-interrupt" fn general_protection_fault_handler(frame: TrapFrame) {
+    let _gs = SwapGSGuard::new(frame.code_segment);
+    userspace_fault(frame.code_segment, frame.insturaction, frame.stack_pointer);
     panic!("---- General Protection Fault ----\n{}", frame,);
 }
 
+bitflags! {
+    /// the error code pushed by the cpu on a page fault
+    #[derive(Debug, Clone, Copy)]
+    pub struct PageFaultError: u64 {
+        const PRESENT = 1;
+        const WRITE = 1 << 1;
+        const USER = 1 << 2;
+        const RESERVED_WRITE = 1 << 3;
+        const INSTRUCTION_FETCH = 1 << 4;
+    }
+}
+
 #[no_mangle]
-extern "x86-interrupt" fn page_fault_handler(frame: TrapFrame) {
+extern "x86-interrupt" fn page_fault_handler(mut frame: TrapFrame) {
+    let _gs = SwapGSGuard::new(frame.code_segment);
     let cr2: u64;
-    unsafe { asm!("mov cr2, {}", out(reg) cr2) }
+    unsafe { asm!("mov {}, cr2", out(reg) cr2) }
+
+    let addr = cr2 as usize;
+    let error = PageFaultError::from_bits_truncate(frame.error_code);
+
+    if scheduler_inited() && !error.contains(PageFaultError::RESERVED_WRITE) {
+        if addr < USERSPACE_END
+            && expose::handle_page_fault(addr, error.contains(PageFaultError::WRITE))
+        {
+            return;
+        }
+
+        // faults caused by userspace kill the process instead of the whole kernel
+        if frame.code_segment & 3 == 3 {
+            expose::thread_fault(
+                addr,
+                frame.insturaction as usize,
+                frame.stack_pointer as usize,
+            );
+        }
+    }
+
+    // the kernel expected it might fault there, such as when copying from userspace
+    if let Some(fixup) = usercopy::exception_fixup(frame.insturaction as usize) {
+        // the frame is the one on the stack that is returned to, the write would be optimized out otherwise
+        unsafe { core::ptr::write_volatile(&mut frame.insturaction, fixup as u64) };
+        return;
+    }
 
-    panic!("---- Page Fault ----\naddress: {:#x}\n{}", cr2, frame)
+    panic!(
+        "---- Page Fault ----\naddress: {:#x}\nerror: {:?}\n{}",
+        cr2, error, frame
+    )
 }
 
 #[inline]
@@ -101,7 +202,15 @@ pub fn handle_ps2_keyboard() {
     crate::__navi_key_pressed(encoded);
 }
 #[no_mangle]
-pub extern "x86-interrupt" fn keyboard_interrupt_handler() {
+pub extern "x86-interrupt" fn keyboard_interrupt_handler(frame: InterruptFrame) {
+    let _gs = SwapGSGuard::new(frame.code_segment);
     handle_ps2_keyboard();
     send_eoi();
 }
+
+/// sent by another cpu that changed the mappings of a process this cpu runs, see `smp::shootdown`
+extern "x86-interrupt" fn tlb_shootdown_handler(frame: InterruptFrame) {
+    let _gs = SwapGSGuard::new(frame.code_segment);
+    smp::flush_pending_tlb();
+    send_eoi();
+}
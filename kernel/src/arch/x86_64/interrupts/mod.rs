@@ -86,19 +86,26 @@ pub fn read_msr(msr: u32) -> PhysAddr {
     let (low, high): (u32, u32);
     unsafe {
         asm!(
-            "
-            mov ecx, {0:e}
-            rdmsr
-            mov {1:e}, eax
-            mov {2:e}, edx
-            ", 
-            in(reg) msr, out(reg) low, out(reg) high
+            "rdmsr",
+            in("ecx") msr, out("eax") low, out("edx") high,
+            options(nostack, preserves_flags)
         );
     }
 
     (high as usize) << 32 | (low as usize)
 }
 
+pub fn write_msr(msr: u32, value: usize) {
+    let (low, high) = (value as u32, (value >> 32) as u32);
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") msr, in("eax") low, in("edx") high,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
 pub fn init_idt() {
     unsafe {
         asm!("lidt [{}]", in(reg) &*IDTDesc, options(nostack));
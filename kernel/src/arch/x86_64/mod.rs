@@ -1,10 +1,15 @@
 mod acpi;
+pub mod cmos;
 pub mod gdt;
 pub mod interrupts;
+mod pit;
 pub mod power;
 pub mod serial;
+pub mod smp;
 pub(self) mod syscalls;
 pub mod threading;
+pub mod tsc;
+pub mod usercopy;
 
 use core::arch::asm;
 
@@ -13,6 +18,7 @@ use interrupts::{apic, init_idt};
 use serial::init_serial;
 
 use self::gdt::init_gdt;
+use crate::memory::paging;
 
 pub fn inb(port: u16) -> u8 {
     let value: u8;
@@ -42,6 +48,7 @@ pub fn inw(port: u16) -> u16 {
     value
 }
 
+/// enables the sse registers, `CR0.TS` is set so their first use gives them to the thread using it, see `threading::fpu`
 #[inline]
 pub fn enable_sse() {
     unsafe {
@@ -49,7 +56,7 @@ pub fn enable_sse() {
             "
             mov rax, cr0
             and ax, 0xFFFB
-            or ax, 0x2
+            or ax, 0xA
             mov cr0, rax
             mov rax, cr4
             or ax, 3 << 9
@@ -60,41 +67,100 @@ pub fn enable_sse() {
     }
 }
 
+/// enables `xsave` and the avx registers if the cpu supports them
+/// the registers are saved with `fxsave` otherwise and only the x87 and sse registers are usable
 #[inline]
-fn _enable_avx() {
+pub fn enable_avx() {
+    // cpuid 1 ecx bit 26 is the xsave support bit and bit 28 is the avx support bit
+    let features = unsafe { core::arch::x86_64::__cpuid(1) };
+    if features.ecx & (1 << 26) == 0 {
+        return;
+    }
+
+    // the x87 and sse components, and the avx component if supported
+    let mut xcr0: u64 = 0b11;
+    if features.ecx & (1 << 28) != 0 {
+        xcr0 |= 0b100;
+    }
+
+    unsafe {
+        asm!(
+            "
+            mov {cr4}, cr4
+            or {cr4}, 1 << 18
+            mov cr4, {cr4}
+        ",
+            cr4 = out(reg) _,
+            options(nostack)
+        );
+        asm!(
+            "xsetbv",
+            in("ecx") 0,
+            in("eax") xcr0 as u32,
+            in("edx") (xcr0 >> 32) as u32,
+            options(nostack)
+        );
+    }
+
+    threading::fpu::use_xsave(xcr0);
+}
+
+/// makes ring0 respect read-only pages, so kernel writes to copy-on-write pages fault too
+#[inline]
+fn enable_write_protect() {
     unsafe {
         asm!(
             "
-    push rax
-    push rcx
-    push rdx
-
-    xor rcx, rcx
-    xgetbv // Load XCR0 register
-    or eax, 7 // Set AVX, SSE, X87 bits
-    xsetbv // Save back to XCR0
-
-    pop rdx
-    pop rcx
-    pop rax
-    ret",
-            options(noreturn)
+            mov rax, cr0
+            or rax, 1 << 16
+            mov cr0, rax
+        ",
+            out("rax") _,
+            options(nostack)
         )
     }
 }
 
+const EFER: u32 = 0xC0000080;
+/// enables long mode
+const EFER_LME: usize = 1 << 8;
+/// enables no-execute pages
+const EFER_NXE: usize = 1 << 11;
+
+/// makes the cpu respect `EntryFlags::NO_EXECUTE`, which is a reserved bit otherwise
+/// if the cpu doesn't support no-execute pages the flag is left out of page table entries instead
+#[inline]
+fn enable_no_execute() {
+    // cpuid 0x80000001 edx bit 20 is the NX support bit
+    let features = unsafe { core::arch::x86_64::__cpuid(0x8000_0001) };
+    if features.edx & (1 << 20) == 0 {
+        paging::disable_no_execute();
+        return;
+    }
+
+    interrupts::write_msr(EFER, interrupts::read_msr(EFER) | EFER_NXE);
+}
+
 /// simple init less likely to panic
 /// highly required
 #[inline]
 pub fn init_phase1() {
+    enable_write_protect();
+    enable_no_execute();
+    usercopy::enable_smap();
     init_serial();
-    init_gdt();
+    init_gdt(0);
+    smp::init_cpu_local(0);
+    syscalls::enable_syscall();
     init_idt();
 }
 /// complexer init
 #[inline]
 pub fn init_phase2() {
     acpi::enable_acpi(FADT::get(get_sdt()));
+    tsc::calibrate();
     apic::enable_apic_interrupts();
     enable_sse();
+    enable_avx();
+    smp::init();
 }
@@ -0,0 +1,34 @@
+//! the legacy programmable interval timer, only used to calibrate the apic timer and the tsc
+use super::{inb, outb};
+
+const PIT_FREQUENCY: u64 = 1_193_182;
+
+const CHANNEL2_DATA_PORT: u16 = 0x42;
+const COMMAND_PORT: u16 = 0x43;
+/// bit 0 is the gate of channel 2, bit 1 enables the speaker and bit 5 is the output of channel 2
+const SPEAKER_PORT: u16 = 0x61;
+
+/// channel 2, low byte then high byte, mode 0 (interrupt on terminal count)
+const CHANNEL2_ONE_SHOT: u8 = 0b1011_0000;
+
+/// busy waits for `ms` milliseconds using channel 2 of the pit, `ms` must be less than 55
+pub fn wait_ms(ms: u64) {
+    let count = PIT_FREQUENCY * ms / 1000;
+    assert!(count <= u16::MAX as u64, "pit wait is too long");
+
+    // the speaker is disabled and the gate is kept low while the count is written
+    let speaker = inb(SPEAKER_PORT) & !0b11;
+    outb(SPEAKER_PORT, speaker);
+
+    outb(COMMAND_PORT, CHANNEL2_ONE_SHOT);
+    outb(CHANNEL2_DATA_PORT, count as u8);
+    outb(CHANNEL2_DATA_PORT, (count >> 8) as u8);
+
+    // raising the gate starts the count, the output goes high when it reaches 0
+    outb(SPEAKER_PORT, speaker | 1);
+    while inb(SPEAKER_PORT) & (1 << 5) == 0 {
+        core::hint::spin_loop();
+    }
+
+    outb(SPEAKER_PORT, speaker);
+}
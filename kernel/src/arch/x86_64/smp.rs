@@ -0,0 +1,437 @@
+//! brings up the application processors, every cpu other than the bootstrap processor (cpu 0)
+//! each application processor starts in real mode at `AP_TRAMPOLINE` which takes it to long mode
+//! then it sets up it's own gdt, tss, idt and local apic timer and starts running threads
+use core::{
+    arch::{asm, global_asm},
+    mem::offset_of,
+    ptr::{addr_of, null_mut},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use alloc::vec;
+use spin::{Mutex, MutexGuard};
+
+use crate::{
+    hddm,
+    memory::paging::{self, EntryFlags, PageTable, PAGE_SIZE},
+    serial,
+    threading::{ring0_stack_start, CPUState, STACK_SIZE},
+    PhysAddr, VirtAddr,
+};
+
+use super::{
+    acpi::{self, MADTRecord, MADT},
+    gdt::init_gdt,
+    interrupts::{
+        apic::{self, IPI_FIXED, IPI_INIT, IPI_STARTUP},
+        init_idt, write_msr,
+    },
+    pit,
+    threading::{GS_BASE, KERNEL_GS_BASE},
+};
+
+/// the maximum number of cpus that can be used, the rest are never started
+pub const MAX_CPUS: usize = 8;
+
+/// the physical address the application processors start at, it has to be page aligned and below 1MiB
+pub const AP_TRAMPOLINE: PhysAddr = 0x8000;
+/// the trampoline is followed by the pml4, the pdpt and the pd it uses to enter long mode
+/// the frames are reserved by the frame allocator
+pub const AP_TRAMPOLINE_SIZE: usize = PAGE_SIZE * 4;
+
+/// the size of the stack an application processor runs on until it starts running threads
+const AP_STACK_SIZE: usize = PAGE_SIZE * 4;
+/// how long to wait for an application processor to start
+const AP_START_TIMEOUT_MS: u64 = 100;
+
+/// the vector of the ipi that asks a cpu to flush it's TLB, see `shootdown`
+pub const TLB_SHOOTDOWN_VECTOR: u8 = 0x22;
+
+/// the local apic record type in the madt
+const MADT_LOCAL_APIC: u8 = 0;
+/// set in the flags of a local apic record if the processor can be used
+const LOCAL_APIC_ENABLED: u32 = 1;
+
+#[repr(C, packed)]
+struct MADTLocalApic {
+    _header: MADTRecord,
+    _processor_id: u8,
+    apic_id: u8,
+    flags: u32,
+}
+
+/// the data of a cpu, while the cpu is in the kernel the gs base points to it
+/// while it is in ring3 the gs base is the thread's and the block is kept in the kernel gs base until `swapgs`
+#[repr(C)]
+pub struct CPULocal {
+    /// points to the block itself so it can be reached with a single gs relative load
+    #[allow(dead_code)]
+    this: *mut CPULocal,
+    pub id: usize,
+    /// the id of the local apic of the cpu, ipis are sent to it
+    pub apic_id: u8,
+    /// set by another cpu that changed the mappings of a process this cpu runs, see `shootdown`
+    tlb_flush_pending: AtomicBool,
+    /// the top of the stack the cpu switches to when an interrupt or a syscall happens in ring3
+    pub kernel_stack_top: VirtAddr,
+    /// where the `syscall` entry keeps the user stack pointer until it is pushed to the kernel stack
+    pub user_stack: VirtAddr,
+    /// what the cpu is running, only used by the scheduler
+    pub scheduler: CPUState,
+}
+
+impl CPULocal {
+    const fn new() -> Self {
+        Self {
+            this: null_mut(),
+            id: 0,
+            apic_id: 0,
+            tlb_flush_pending: AtomicBool::new(false),
+            kernel_stack_top: 0,
+            user_stack: 0,
+            scheduler: CPUState::new(),
+        }
+    }
+}
+
+static mut CPU_LOCALS: [CPULocal; MAX_CPUS] = [const { CPULocal::new() }; MAX_CPUS];
+
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(1);
+/// the id of the application processor that is being started, they are started one at a time
+static STARTING_CPU: AtomicUsize = AtomicUsize::new(0);
+/// set by the application processor that is being started once it no longer uses the trampoline
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// makes the gs base point to the block of the cpu `cpu`
+/// must be called after the gdt is loaded because loading the gs selector clears the gs base
+pub fn init_cpu_local(cpu: usize) {
+    unsafe {
+        let local = &mut CPU_LOCALS[cpu];
+        local.this = local;
+        local.id = cpu;
+        local.kernel_stack_top = ring0_stack_start(0) + STACK_SIZE;
+
+        write_msr(GS_BASE, local as *mut CPULocal as usize);
+        write_msr(KERNEL_GS_BASE, 0);
+    }
+}
+
+/// the block of the current cpu
+#[inline(always)]
+pub fn cpu_local() -> &'static mut CPULocal {
+    let this: *mut CPULocal;
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) this, options(nostack, readonly, preserves_flags));
+        &mut *this
+    }
+}
+
+/// the blocks of the running cpus
+pub fn cpu_locals() -> &'static [CPULocal] {
+    unsafe { &CPU_LOCALS[..cpu_count()] }
+}
+
+/// the id of the current cpu, from 0 to `cpu_count()`
+#[inline(always)]
+pub fn cpu_id() -> usize {
+    let id: usize;
+    unsafe {
+        asm!(
+            "mov {}, gs:[{}]",
+            out(reg) id,
+            const offset_of!(CPULocal, id),
+            options(nostack, readonly, preserves_flags)
+        );
+    }
+    id
+}
+
+/// the number of running cpus
+#[inline]
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Relaxed)
+}
+
+/// flushes the TLB of the cpus in the mask `cpus` other than the current cpu and waits until they did
+/// the page tables must already be changed, the frames that were unmapped can be released afterwards
+/// a cpu can only flush while interrupts are enabled or while it waits for a lock taken with `lock`
+pub fn shootdown(cpus: u64) {
+    let current = cpu_id();
+    let targets = cpu_locals()
+        .iter()
+        .filter(|local| local.id != current && cpus & (1 << local.id) != 0);
+
+    for local in targets.clone() {
+        local.tlb_flush_pending.store(true, Ordering::SeqCst);
+        apic::send_ipi(local.apic_id, IPI_FIXED | TLB_SHOOTDOWN_VECTOR as u32);
+    }
+
+    for local in targets {
+        // another cpu may be waiting for this one to flush at the same time
+        while local.tlb_flush_pending.load(Ordering::SeqCst) {
+            flush_pending_tlb();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// flushes the TLB of the current cpu if another cpu asked it to with `shootdown`
+pub fn flush_pending_tlb() {
+    let pending = &cpu_local().tlb_flush_pending;
+    if pending.load(Ordering::SeqCst) {
+        paging::flush_tlb();
+        pending.store(false, Ordering::SeqCst);
+    }
+}
+
+/// locks `mutex` flushing the TLB of the current cpu while it waits if another cpu asks it to
+/// locks that are taken with interrupts disabled and may be held while calling `shootdown`, or while waiting for such a lock,
+/// have to be taken with this otherwise the cpu that holds it and the cpu that waits for it wait for each other forever
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    loop {
+        if let Some(guard) = mutex.try_lock() {
+            return guard;
+        }
+
+        flush_pending_tlb();
+        core::hint::spin_loop();
+    }
+}
+
+// the data at the end is filled in by `start_ap` before every start
+global_asm!(
+    "
+.global ap_trampoline_start
+.global ap_trampoline_end
+.global ap_trampoline_cr3
+.global ap_trampoline_stack
+.global ap_trampoline_entry
+.global ap_trampoline_efer
+
+// the offsets are given names because memory operands can only reference one symbol
+.set ap_trampoline_cr3_offset, ap_trampoline_cr3 - ap_trampoline_start
+.set ap_trampoline_stack_offset, ap_trampoline_stack - ap_trampoline_start
+.set ap_trampoline_entry_offset, ap_trampoline_entry - ap_trampoline_start
+.set ap_trampoline_efer_offset, ap_trampoline_efer - ap_trampoline_start
+.set ap_trampoline_gdt_offset, ap_trampoline_gdt - ap_trampoline_start
+.set ap_trampoline_gdt_descriptor_offset, ap_trampoline_gdt_descriptor - ap_trampoline_start
+.set ap_trampoline_long_mode_offset, ap_trampoline_long_mode - ap_trampoline_start
+
+.code16
+ap_trampoline_start:
+    cli
+    cld
+    xor ax, ax
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+
+    // PAE and PGE
+    mov eax, 0xA0
+    mov cr4, eax
+
+    mov eax, dword ptr [{base} + ap_trampoline_cr3_offset]
+    mov cr3, eax
+
+    // LME and NXE if the cpu supports it
+    mov ecx, 0xC0000080
+    rdmsr
+    or eax, dword ptr [{base} + ap_trampoline_efer_offset]
+    wrmsr
+
+    lgdt [{base} + ap_trampoline_gdt_descriptor_offset]
+
+    // paging and protected mode
+    mov eax, cr0
+    or eax, 0x80000001
+    mov cr0, eax
+
+    // jmp far dword 0x08:ap_trampoline_long_mode
+    .byte 0x66, 0xEA
+    .long {base} + ap_trampoline_long_mode_offset
+    .word 0x08
+
+.code64
+ap_trampoline_long_mode:
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    xor ax, ax
+    mov fs, ax
+    mov gs, ax
+
+    mov rsp, qword ptr [{base} + ap_trampoline_stack_offset]
+    xor rbp, rbp
+    mov rax, qword ptr [{base} + ap_trampoline_entry_offset]
+    call rax
+    ud2
+
+.align 16
+ap_trampoline_gdt:
+    .quad 0
+    .quad 0x00AF9A000000FFFF
+    .quad 0x00CF92000000FFFF
+ap_trampoline_gdt_descriptor:
+    .word ap_trampoline_gdt_descriptor - ap_trampoline_gdt - 1
+    .long {base} + ap_trampoline_gdt_offset
+
+.align 8
+ap_trampoline_cr3:
+    .quad 0
+ap_trampoline_stack:
+    .quad 0
+ap_trampoline_entry:
+    .quad 0
+ap_trampoline_efer:
+    .quad 0
+ap_trampoline_end:
+",
+    base = const AP_TRAMPOLINE,
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_end: u8;
+    static ap_trampoline_cr3: u8;
+    static ap_trampoline_stack: u8;
+    static ap_trampoline_entry: u8;
+    static ap_trampoline_efer: u8;
+}
+
+/// the copy of the trampoline variable `var` at `AP_TRAMPOLINE`
+unsafe fn trampoline_var(var: *const u8) -> *mut u64 {
+    let offset = var as usize - addr_of!(ap_trampoline_start) as usize;
+    ((AP_TRAMPOLINE + offset) | hddm()) as *mut u64
+}
+
+unsafe fn trampoline_table(index: usize) -> &'static mut PageTable {
+    &mut *(((AP_TRAMPOLINE + PAGE_SIZE * index) | hddm()) as *mut PageTable)
+}
+
+/// copies the trampoline to `AP_TRAMPOLINE` and sets up the page table it enters long mode with
+/// the page table identity maps the first 2MiB where the trampoline is and shares the higher half with the kernel
+unsafe fn install_trampoline() {
+    let start = addr_of!(ap_trampoline_start);
+    let size = addr_of!(ap_trampoline_end) as usize - start as usize;
+    let dest = (AP_TRAMPOLINE | hddm()) as *mut u8;
+    core::ptr::copy_nonoverlapping(start, dest, size);
+
+    let pml4 = trampoline_table(1);
+    let pdpt = trampoline_table(2);
+    let pd = trampoline_table(3);
+    pml4.zeroize();
+    pdpt.zeroize();
+    pd.zeroize();
+
+    pml4.copy_higher_half();
+    let flags = EntryFlags::PRESENT | EntryFlags::WRITABLE;
+    pml4.entries[0].set(flags, AP_TRAMPOLINE + PAGE_SIZE * 2);
+    pdpt.entries[0].set(flags, AP_TRAMPOLINE + PAGE_SIZE * 3);
+    pd.entries[0].set(flags | EntryFlags::HUGE_PAGE, 0);
+
+    *trampoline_var(addr_of!(ap_trampoline_cr3)) = (AP_TRAMPOLINE + PAGE_SIZE) as u64;
+    *trampoline_var(addr_of!(ap_trampoline_entry)) = ap_main as usize as u64;
+
+    // the kernel's page tables have no-execute entries unless the cpu doesn't support them, see `enable_no_execute`
+    let efer = if paging::no_execute_enabled() {
+        super::EFER_LME | super::EFER_NXE
+    } else {
+        super::EFER_LME
+    };
+    *trampoline_var(addr_of!(ap_trampoline_efer)) = efer as u64;
+}
+
+/// starts the application processor with the local apic id `apic_id` as the cpu `cpu`
+/// returns false if it didn't start in time
+unsafe fn start_ap(apic_id: u8, cpu: usize) -> bool {
+    let stack = vec![0u8; AP_STACK_SIZE].leak();
+    let stack_end = stack.as_ptr_range().end as usize & !0xF;
+    *trampoline_var(addr_of!(ap_trampoline_stack)) = stack_end as u64;
+
+    CPU_LOCALS[cpu].apic_id = apic_id;
+    STARTING_CPU.store(cpu, Ordering::Relaxed);
+    AP_STARTED.store(false, Ordering::Release);
+
+    apic::send_ipi(apic_id, IPI_INIT);
+    pit::wait_ms(10);
+
+    // a second startup ipi is sent if the first one was missed, it is ignored otherwise
+    let page = (AP_TRAMPOLINE / PAGE_SIZE) as u32;
+    apic::send_ipi(apic_id, IPI_STARTUP | page);
+    pit::wait_ms(1);
+
+    if !AP_STARTED.load(Ordering::Acquire) {
+        apic::send_ipi(apic_id, IPI_STARTUP | page);
+    }
+
+    for _ in 0..AP_START_TIMEOUT_MS {
+        if AP_STARTED.load(Ordering::Acquire) {
+            return true;
+        }
+
+        pit::wait_ms(1);
+    }
+
+    false
+}
+
+/// where the application processors continue from the trampoline
+extern "C" fn ap_main() -> ! {
+    let cpu = STARTING_CPU.load(Ordering::Relaxed);
+
+    super::enable_write_protect();
+    super::enable_no_execute();
+    super::usercopy::enable_smap();
+    init_gdt(cpu);
+    init_cpu_local(cpu);
+    super::syscalls::enable_syscall();
+    init_idt();
+    apic::enable_ap_apic_interrupts();
+    super::enable_sse();
+    super::enable_avx();
+
+    AP_STARTED.store(true, Ordering::Release);
+    unsafe { crate::threading::Scheduler::start_cpu() }
+}
+
+/// starts every usable application processor in the madt up to `MAX_CPUS` cpus
+/// the application processors wait for the scheduler to be initialized before running threads
+pub fn init() {
+    let madt = MADT::get(acpi::get_sdt());
+    let bsp = apic::local_apic_id();
+
+    let processors = madt
+        .records()
+        .filter(|record| unsafe { (**record).entry_type } == MADT_LOCAL_APIC)
+        .map(|record| unsafe {
+            let record = record as *const MADTLocalApic;
+            ((*record).apic_id, (*record).flags)
+        });
+
+    unsafe {
+        CPU_LOCALS[0].apic_id = bsp;
+        install_trampoline();
+    }
+
+    for (apic_id, flags) in processors {
+        if apic_id == bsp || flags & LOCAL_APIC_ENABLED == 0 {
+            continue;
+        }
+
+        let cpu = cpu_count();
+        if cpu >= MAX_CPUS {
+            break;
+        }
+
+        if !unsafe { start_ap(apic_id, cpu) } {
+            serial!("smp: cpu with apic id {} didn't start\n", apic_id);
+            // it may still start later so it's id cannot be given to another cpu
+            break;
+        }
+
+        CPU_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+
+    serial!("smp: {} cpus running\n", cpu_count());
+}
@@ -1,9 +1,13 @@
 // TODO: figure out errors
 // for now errors are a big mess
-use super::interrupts::InterruptFrame;
+use super::gdt::{KERNEL_CODE_SEG, USER_CODE_SEG, USER_DATA_SEG};
+use super::interrupts::{read_msr, write_msr, InterruptFrame};
+use super::smp::CPULocal;
+use crate::memory::paging::USERSPACE_END;
 use crate::utils::errors::ErrorStatus;
 use core::arch::global_asm;
-/// used sometimes for debugging syscalls
+use core::mem::offset_of;
+/// the registers pushed by `syscall_base` and `syscall_entry`, also used to resume a process from a syscall
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -25,31 +29,11 @@ pub struct SyscallContext {
     pub frame: InterruptFrame,
 }
 global_asm!(
+    ".section .rodata",
+    "syscall_table:",
+    // generated by the build script from the `#[syscall]` functions, see `crate::syscalls`
+    include_str!(concat!(env!("OUT_DIR"), "/syscall_table.s")),
     "
-.section .rodata
-syscall_table:
-    .quad sysexit
-    .quad sysyield
-    .quad sysopen
-    .quad syswrite
-    .quad sysread
-    .quad sysclose
-    .quad syscreate
-    .quad syscreatedir
-    .quad sysdiriter_open
-    .quad sysdiriter_close
-    .quad sysdiriter_next
-    .quad syswait
-    .quad sysfstat
-    .quad sysspawn
-    .quad syschdir
-    .quad sysgetcwd
-    .quad sysinfo
-    .quad syspcollect
-    .quad syssbrk
-    .quad syspspawn
-    .quad sysshutdown
-    .quad sysreboot
 syscall_table_end:
 
 SYSCALL_TABLE_INFO:
@@ -58,6 +42,15 @@ SYSCALL_TABLE_INFO:
 .global syscall_base
 
 syscall_base:
+    // the cpu local block is in the kernel gs base if the syscall was made from ring3
+    test byte ptr [rsp + 8], 3
+    jz 1f
+    swapgs
+    // userspace can set the alignment check flag which would make it's memory accessible, see `UserAccess`
+    cmp byte ptr [rip + {smap_enabled}], 0
+    je 1f
+    clac
+1:
     cmp rax, [SYSCALL_TABLE_INFO]
     jge unsupported
     push rbx
@@ -89,13 +82,118 @@ syscall_base:
     pop rdx
     pop rcx
     pop rbx
-    iretq
+    jmp syscall_return
 unsupported:
-    mov rax, {0}
+    mov rax, {err}
+syscall_return:
+    test byte ptr [rsp + 8], 3
+    jz 2f
+    swapgs
+2:
     iretq
-", const ErrorStatus::InvaildSyscall as u64
+
+.global syscall_entry
+
+// the `syscall` instruction only comes from ring3 with interrupts disabled by SFMASK
+// it pushes the same frame as `int 0x80` so the rest of the kernel cannot tell them apart
+// the 4th argument is in r10 because rcx holds the return address
+syscall_entry:
+    swapgs
+    mov gs:[{user_stack}], rsp
+    mov rsp, gs:[{kernel_stack_top}]
+
+    push {user_ss}
+    push gs:[{user_stack}]
+    push r11 // rflags
+    push {user_cs}
+    push rcx // rip
+    // the user stack is saved in the cpu local block until it is pushed, another thread could overwrite it before
+    sti
+
+    cmp rax, [SYSCALL_TABLE_INFO]
+    jge syscall_entry_unsupported
+    push rbx
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push rbp
+    push r8
+    push r9
+    push r10
+    push r11
+    push r12
+    push r13
+    push r14
+    push r15
+    mov rcx, r10
+    call [syscall_table + rax * 8]
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rbp
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    pop rbx
+    jmp syscall_entry_return
+syscall_entry_unsupported:
+    mov rax, {err}
+syscall_entry_return:
+    // an interrupt must not arrive once the gs base of ring3 is swapped back in
+    cli
+    // sysret faults in ring0 on the user stack if the rip isn't canonical
+    // anything outside of userspace returns with iretq instead
+    mov r11, {userspace_end}
+    mov rcx, [rsp + 24] // rsp
+    cmp rcx, r11
+    jae syscall_return
+    mov rcx, [rsp]      // rip
+    cmp rcx, r11
+    jae syscall_return
+    mov r11, [rsp + 16] // rflags
+    mov rsp, [rsp + 24] // rsp
+    swapgs
+    sysretq
+",
+    err = const ErrorStatus::InvaildSyscall as u64,
+    user_stack = const offset_of!(CPULocal, user_stack),
+    kernel_stack_top = const offset_of!(CPULocal, kernel_stack_top),
+    user_ss = const USER_DATA_SEG,
+    user_cs = const USER_CODE_SEG,
+    userspace_end = const USERSPACE_END,
+    smap_enabled = sym super::usercopy::SMAP_ENABLED,
 );
 
 extern "x86-interrupt" {
     pub fn syscall_base();
 }
+
+extern "C" {
+    fn syscall_entry();
+}
+
+const STAR: u32 = 0xC0000081;
+const LSTAR: u32 = 0xC0000082;
+const SFMASK: u32 = 0xC0000084;
+/// the flags cleared on `syscall`: trap, interrupt, direction, iopl, nested task and alignment check
+const SYSCALL_FLAGS_MASK: usize = 0x47700;
+
+/// makes the `syscall` instruction enter `syscall_entry` on the current cpu, `int 0x80` keeps working
+/// `sysret` returns to the segment 16 bytes after the base in STAR and takes the stack segment 8 bytes after it
+pub fn enable_syscall() {
+    let sysret_base = (USER_DATA_SEG - 8) as usize;
+    let star = (sysret_base << 48) | ((KERNEL_CODE_SEG as usize) << 32);
+
+    write_msr(STAR, star);
+    write_msr(LSTAR, syscall_entry as usize);
+    write_msr(SFMASK, SYSCALL_FLAGS_MASK);
+    // EFER.SCE
+    write_msr(super::EFER, read_msr(super::EFER) | 1);
+}
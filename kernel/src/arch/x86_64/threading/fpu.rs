@@ -0,0 +1,162 @@
+//! lazy saving and restoring of the x87, sse and avx registers of the threads
+//! every context switch sets `CR0.TS` so the first use of the registers by the next thread raises a device not available exception
+//! which restores the registers of that thread, the registers are only saved on a context switch if the thread used them
+use core::{
+    arch::{asm, x86_64::__cpuid_count},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::boxed::Box;
+
+use super::without_interrupts;
+use crate::{scheduler, scheduler_inited};
+
+/// the largest state `xsave` can save with the components the kernel enables
+const AREA_SIZE: usize = 1024;
+/// `xsave` and `fxsave` need a 64 byte and a 16 byte aligned area
+const AREA_ALIGN: usize = 64;
+
+/// the offsets of the x87 control word and of MXCSR in the area
+const FCW_OFFSET: usize = 0;
+const MXCSR_OFFSET: usize = 24;
+/// every x87 exception masked
+const DEFAULT_FCW: u16 = 0x37F;
+/// every sse exception masked
+const DEFAULT_MXCSR: u32 = 0x1F80;
+
+const CR0_TS: u64 = 1 << 3;
+
+/// the components `xsave` saves, 0 if the cpu doesn't support it and `fxsave` is used instead
+static XSAVE_MASK: AtomicU64 = AtomicU64::new(0);
+
+/// makes the registers be saved with `xsave` including the components in `xcr0`
+/// called once `xcr0` is set
+pub fn use_xsave(xcr0: u64) {
+    // cpuid 0xD ebx is the size of the area needed for the components enabled in xcr0
+    let size = unsafe { __cpuid_count(0xD, 0) }.ebx as usize;
+    assert!(
+        size <= AREA_SIZE,
+        "the xsave area is too large ({size} bytes)"
+    );
+
+    XSAVE_MASK.store(xcr0, Ordering::Relaxed);
+}
+
+/// the saved registers of a thread
+pub struct FPUState {
+    /// the heap doesn't respect alignment so the area starts at the first aligned byte
+    buffer: [u8; AREA_SIZE + AREA_ALIGN],
+}
+
+impl FPUState {
+    /// a new state as the registers are after a reset with every exception masked
+    pub fn new() -> Box<Self> {
+        let mut state = Box::new(Self {
+            buffer: [0; AREA_SIZE + AREA_ALIGN],
+        });
+
+        unsafe {
+            let area = state.area();
+            area.add(FCW_OFFSET)
+                .cast::<u16>()
+                .write_unaligned(DEFAULT_FCW);
+            area.add(MXCSR_OFFSET)
+                .cast::<u32>()
+                .write_unaligned(DEFAULT_MXCSR);
+        }
+        state
+    }
+
+    #[inline(always)]
+    fn area(&mut self) -> *mut u8 {
+        let offset = self.buffer.as_ptr().align_offset(AREA_ALIGN);
+        unsafe { self.buffer.as_mut_ptr().add(offset) }
+    }
+
+    /// saves the registers of the cpu, they must be in use
+    unsafe fn save(&mut self) {
+        let area = self.area();
+        match XSAVE_MASK.load(Ordering::Relaxed) {
+            0 => asm!("fxsave64 [{}]", in(reg) area, options(nostack)),
+            mask => asm!(
+                "xsave64 [{}]",
+                in(reg) area,
+                in("eax") mask as u32,
+                in("edx") (mask >> 32) as u32,
+                options(nostack)
+            ),
+        }
+    }
+
+    /// loads the registers of the cpu, they must be in use
+    unsafe fn restore(&mut self) {
+        let area = self.area();
+        match XSAVE_MASK.load(Ordering::Relaxed) {
+            0 => asm!("fxrstor64 [{}]", in(reg) area, options(nostack)),
+            mask => asm!(
+                "xrstor64 [{}]",
+                in(reg) area,
+                in("eax") mask as u32,
+                in("edx") (mask >> 32) as u32,
+                options(nostack)
+            ),
+        }
+    }
+
+    /// a copy of the state
+    pub fn duplicate(&mut self) -> Box<Self> {
+        let mut copy = Self::new();
+        unsafe { core::ptr::copy_nonoverlapping(self.area(), copy.area(), AREA_SIZE) };
+        copy
+    }
+}
+
+#[inline(always)]
+fn read_cr0() -> u64 {
+    let cr0: u64;
+    unsafe { asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags)) };
+    cr0
+}
+
+/// returns true if the registers were used since the last context switch of the current cpu
+#[inline(always)]
+pub fn in_use() -> bool {
+    read_cr0() & CR0_TS == 0
+}
+
+/// makes the next use of the registers on the current cpu raise a device not available exception
+#[inline(always)]
+pub fn release() {
+    unsafe { asm!("mov cr0, {}", in(reg) read_cr0() | CR0_TS, options(nomem, nostack)) };
+}
+
+/// saves the registers to `state` if they are in use and releases them, called when the current thread is switched away from
+pub fn save(state: &mut Option<Box<FPUState>>) {
+    without_interrupts(|| {
+        if in_use() {
+            unsafe { state.get_or_insert_with(FPUState::new).save() };
+            release();
+        }
+    })
+}
+
+/// discards `state` so the next use of the registers starts from a reset state
+pub fn reset(state: &mut Option<Box<FPUState>>) {
+    without_interrupts(|| {
+        *state = None;
+        release();
+    })
+}
+
+/// handles a device not available exception, gives the registers to the current thread restoring it's saved state
+/// threads that never used the registers start with a reset state
+pub fn handle_device_not_available() {
+    unsafe { asm!("clts", options(nomem, nostack)) };
+
+    if !scheduler_inited() {
+        return;
+    }
+
+    let thread = scheduler().current_thread();
+    unsafe { thread.fpu.get_or_insert_with(FPUState::new).restore() };
+}
@@ -1,9 +1,21 @@
-use core::arch::global_asm;
+pub mod fpu;
+
+use core::arch::{asm, global_asm};
 
 use bitflags::bitflags;
 
+use super::interrupts::{read_msr, write_msr};
+use super::syscalls::SyscallContext;
+use crate::memory::paging::USERSPACE_END;
+use crate::utils::errors::ErrorStatus;
 use crate::{scheduler, scheduler_inited};
 
+pub const FS_BASE: u32 = 0xC0000100;
+pub const GS_BASE: u32 = 0xC0000101;
+/// swapped with the gs base by `swapgs`, holds the gs base of the current thread while the cpu is in the kernel
+/// and the `CPULocal` block of the cpu while it is in ring3
+pub const KERNEL_GS_BASE: u32 = 0xC0000102;
+
 bitflags! {
     #[derive(Default, Debug, Clone, Copy)]
     #[repr(C)]
@@ -35,6 +47,7 @@ bitflags! {
     }
 }
 
+/// the registers of a thread, the x87, sse and avx registers are saved separately by `fpu`
 #[derive(Debug, Clone, Copy, Default)]
 #[repr(C, packed)]
 pub struct CPUStatus {
@@ -51,36 +64,21 @@ pub struct CPUStatus {
     r12: u64,
     r11: u64,
     r10: u64,
-    r9: u64,
-    r8: u64,
+    pub r9: u64,
+    pub r8: u64,
 
     rbp: u64,
     pub rdi: u64,
     pub rsi: u64,
 
-    rdx: u64,
-    rcx: u64,
+    pub rdx: u64,
+    pub rcx: u64,
     rbx: u64,
     pub cr3: u64,
     rax: u64,
 
-    // ffi-safe alternative for u128
-    xmm15: [u8; 16],
-    xmm14: [u8; 16],
-    xmm13: [u8; 16],
-    xmm12: [u8; 16],
-    xmm11: [u8; 16],
-    xmm10: [u8; 16],
-    xmm9: [u8; 16],
-    xmm8: [u8; 16],
-    xmm7: [u8; 16],
-    xmm6: [u8; 16],
-    xmm5: [u8; 16],
-    xmm4: [u8; 16],
-    xmm3: [u8; 16],
-    xmm2: [u8; 16],
-    xmm1: [u8; 16],
-    xmm0: [u8; 16],
+    pub fs_base: u64,
+    pub gs_base: u64,
 }
 
 impl CPUStatus {
@@ -91,6 +89,107 @@ impl CPUStatus {
     pub fn stack_at(&self) -> usize {
         self.rsp as usize
     }
+
+    /// returns true if the status was captured while running in ring3
+    pub fn is_userspace(&self) -> bool {
+        self.cs & 3 == 3
+    }
+
+    /// captures the userspace state the current process entered it's current syscall with
+    /// resuming from the returned status returns from the syscall with `rax` as the result
+    /// returns None if the current process isn't a userspace process because only those switch to the ring0 stack
+    pub fn from_current_syscall(rax: u64) -> Option<Self> {
+        if !is_userspace_syscall() {
+            return None;
+        }
+
+        let context = current_syscall_context();
+        let frame = &context.frame;
+
+        Some(Self {
+            rsp: frame.stack_pointer,
+            rflags: frame.flags,
+            ss: frame.stack_segment,
+            cs: frame.code_segment,
+            rip: frame.insturaction,
+
+            r15: context.r15,
+            r14: context.r14,
+            r13: context.r13,
+            r12: context.r12,
+            r11: context.r11,
+            r10: context.r10,
+            r9: context.r9,
+            r8: context.r8,
+
+            rbp: context.rbp,
+            rdi: context.rdi,
+            rsi: context.rsi,
+
+            rdx: context.rdx,
+            rcx: context.rcx,
+            rbx: context.rbx,
+            cr3: 0,
+            rax,
+            fs_base: read_msr(FS_BASE) as u64,
+            gs_base: read_msr(KERNEL_GS_BASE) as u64,
+            ..Default::default()
+        })
+    }
+}
+
+/// the context the current thread entered it's current syscall with
+/// only vaild if `is_userspace_syscall` is true
+#[inline(always)]
+fn current_syscall_context() -> &'static SyscallContext {
+    // the syscall context is the first thing pushed to the ring0 stack of the current thread
+    let stack_end = super::gdt::ring0_stack_end();
+    unsafe { &*((stack_end - size_of::<SyscallContext>()) as *const SyscallContext) }
+}
+
+/// returns true if the current syscall was made from userspace
+pub fn is_userspace_syscall() -> bool {
+    current_syscall_context().frame.code_segment & 3 == 3
+}
+
+/// runs `f` with interrupts disabled, they are enabled again afterwards if they were enabled before
+#[inline(always)]
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let flags: u64;
+    unsafe { asm!("pushfq", "pop {}", "cli", out(reg) flags) };
+
+    let results = f();
+
+    if RFLAGS::from_bits_retain(flags).contains(RFLAGS::INTERRUPT_FLAG) {
+        unsafe { asm!("sti") };
+    }
+    results
+}
+
+pub const ARCH_SET_GS: usize = 0x1001;
+pub const ARCH_SET_FS: usize = 0x1002;
+pub const ARCH_GET_FS: usize = 0x1003;
+pub const ARCH_GET_GS: usize = 0x1004;
+
+/// sets or gets the fs or gs base of the current process depending on `code` like linux's arch_prctl
+/// returns the base for the get codes and `addr` for the set codes
+/// the bases are saved with the rest of the context on every context switch
+pub fn arch_prctl(code: usize, addr: usize) -> Result<usize, ErrorStatus> {
+    let msr = match code {
+        ARCH_SET_FS | ARCH_GET_FS => FS_BASE,
+        // the gs base of the thread is in the kernel gs base until the cpu returns to ring3
+        ARCH_SET_GS | ARCH_GET_GS => KERNEL_GS_BASE,
+        _ => return Err(ErrorStatus::OperationNotSupported),
+    };
+
+    match code {
+        ARCH_GET_FS | ARCH_GET_GS => Ok(read_msr(msr)),
+        _ if addr >= USERSPACE_END => Err(ErrorStatus::InvaildPtr),
+        _ => {
+            write_msr(msr, addr);
+            Ok(addr)
+        }
+    }
 }
 
 global_asm!(
@@ -99,6 +198,16 @@ global_asm!(
 .global context_switch_stub
 
 restore_cpu_status:
+    // restore the fs and gs bases, the gs base is swapped in before returning to ring3
+    mov ecx, 0xC0000100
+    mov eax, [rdi + 0xA8]
+    mov edx, [rdi + 0xAC]
+    wrmsr
+    mov ecx, 0xC0000102
+    mov eax, [rdi + 0xB0]
+    mov edx, [rdi + 0xB4]
+    wrmsr
+
     // push the iretq frame
     push [rdi + 16]     // push ss
     push [rdi]          // push rsp
@@ -126,51 +235,29 @@ restore_cpu_status:
     push [rdi + 0x70] // rdi
     push [rdi + 0xA0] // rax
 
-    lea rax, [rdi + 0xA8]
-    movdqu xmm15, [rax+0x00]
-    movdqu xmm14, [rax+0x10]
-    movdqu xmm13, [rax+0x20]
-    movdqu xmm12, [rax+0x30]
-    movdqu xmm11, [rax+0x40]
-    movdqu xmm10, [rax+0x50]
-    movdqu xmm9, [rax+0x60]
-    movdqu xmm8, [rax+0x70]
-    movdqu xmm7, [rax+0x80]
-    movdqu xmm6, [rax+0x90]
-    movdqu xmm5, [rax+0xA0]
-    movdqu xmm4, [rax+0xB0]
-    movdqu xmm3, [rax+0xC0]
-    movdqu xmm2, [rax+0xD0]
-    movdqu xmm1, [rax+0xE0]
-    movdqu xmm0, [rax+0xF0]
-
     mov rax, [rdi + 0x98]
     mov cr3, rax
     
     pop rax
     pop rdi
 
+    test byte ptr [rsp + 8], 3
+    jz 2f
+    swapgs
+2:
     iretq
 
 context_switch_stub:
-    sub rsp, 16*16      // allocate space for xmm registers
-    movdqu [rsp+0x00], xmm0
-    movdqu [rsp+0x10], xmm1
-    movdqu [rsp+0x20], xmm2
-    movdqu [rsp+0x30], xmm3
-    movdqu [rsp+0x40], xmm4
-    movdqu [rsp+0x50], xmm5
-    movdqu [rsp+0x60], xmm6
-    movdqu [rsp+0x70], xmm7
-    movdqu [rsp+0x80], xmm8
-    movdqu [rsp+0x90], xmm9
-    movdqu [rsp+0xA0], xmm10
-    movdqu [rsp+0xB0], xmm11
-    movdqu [rsp+0xC0], xmm12
-    movdqu [rsp+0xD0], xmm13
-    movdqu [rsp+0xE0], xmm14
-    movdqu [rsp+0xF0], xmm15
-
+    // the cpu local block is in the kernel gs base if the interrupted code was in ring3
+    test byte ptr [rsp + 8], 3
+    jz 1f
+    swapgs
+    // ring3 may have set the alignment check flag, the kernel must not run with it's memory accessible
+    cmp byte ptr [rip + {smap_enabled}], 0
+    je 1f
+    clac
+1:
+    sub rsp, 16         // allocate space for the fs and gs bases, read by context_switch
     push rax
     mov rax, cr3
     push rax
@@ -200,7 +287,8 @@ context_switch_stub:
     call context_switch
     // UNREACHABLE!!!
     ud2
-"
+",
+    smap_enabled = sym super::usercopy::SMAP_ENABLED,
 );
 
 extern "C" {
@@ -220,11 +308,22 @@ pub extern "C" fn context_switch(mut capture: CPUStatus, frame: super::interrupt
     capture.ss = frame.stack_segment;
     capture.rflags = frame.flags;
 
+    capture.fs_base = read_msr(FS_BASE) as u64;
+    capture.gs_base = read_msr(KERNEL_GS_BASE) as u64;
+
+    // every cpu has a timer but only the bootstrap processor's counts the ticks
+    if super::interrupts::apic::is_timer_interrupt() && super::smp::cpu_id() == 0 {
+        crate::time::tick();
+    }
+
     if scheduler_inited() {
         // actual context switching:
         unsafe {
             capture = scheduler().switch(capture);
         }
+
+        scheduler().after_switch();
+        super::gdt::set_ring0_stack(scheduler().current_thread().ring0_stack_end());
     }
 
     super::interrupts::apic::send_eoi();
@@ -0,0 +1,37 @@
+//! the time stamp counter, used to time intervals that are too short for the ticks of the timer
+use core::{
+    arch::x86_64::_rdtsc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::pit;
+
+/// how long the tsc is measured against the pit
+const CALIBRATION_MS: u64 = 10;
+/// how many times the tsc counts in a millisecond, measured once by the bootstrap processor
+static CYCLES_PER_MS: AtomicU64 = AtomicU64::new(0);
+
+/// measures how many times the tsc counts in a millisecond using the pit
+pub(super) fn calibrate() {
+    let start = read();
+    pit::wait_ms(CALIBRATION_MS);
+    let elapsed = read().wrapping_sub(start);
+
+    CYCLES_PER_MS.store((elapsed / CALIBRATION_MS).max(1), Ordering::Relaxed);
+}
+
+/// the current value of the tsc, use `to_nanoseconds` on the difference of two of them
+#[inline(always)]
+pub fn read() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// converts `cycles` of the tsc to nanoseconds, returns 0 before the tsc is calibrated
+pub fn to_nanoseconds(cycles: u64) -> u64 {
+    let cycles_per_ms = CYCLES_PER_MS.load(Ordering::Relaxed);
+    if cycles_per_ms == 0 {
+        return 0;
+    }
+
+    (cycles as u128 * 1_000_000 / cycles_per_ms as u128) as u64
+}
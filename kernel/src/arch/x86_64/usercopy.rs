@@ -0,0 +1,136 @@
+//! access to userspace memory from the kernel
+//! with SMAP the kernel faults on any access to a userspace page unless the alignment check flag is set
+//! the flag is only set by `UserAccess` which is only held by the copy functions
+//! the kernel never keeps refrences to userspace memory, another thread may unmap it at any time, see `utils::ffi`
+use core::{
+    arch::asm,
+    ptr::addr_of,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::VirtAddr;
+
+/// set once SMAP is enabled, `stac` and `clac` are invaild instructions without it
+pub(super) static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// makes the kernel fault on accesses to userspace outside of `UserAccess` if the cpu supports SMAP
+pub(super) fn enable_smap() {
+    // cpuid 7 ebx bit 20 is the SMAP support bit
+    let max_leaf = unsafe { core::arch::x86_64::__cpuid(0) }.eax;
+    if max_leaf < 7 || unsafe { core::arch::x86_64::__cpuid_count(7, 0) }.ebx & (1 << 20) == 0 {
+        return;
+    }
+
+    unsafe {
+        asm!(
+            "
+            mov {cr4}, cr4
+            or {cr4}, 1 << 21
+            mov cr4, {cr4}
+        ",
+            cr4 = out(reg) _,
+            options(nostack)
+        );
+    }
+
+    SMAP_ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// clears the alignment check flag so the kernel faults on accesses to userspace, see `UserAccess`
+/// called on every entry from ring3 which can set the flag itself
+#[inline(always)]
+pub(super) fn deny_user_access() {
+    if SMAP_ENABLED.load(Ordering::Relaxed) {
+        unsafe { asm!("clac", options(nomem, nostack)) };
+    }
+}
+
+/// allows the kernel to access userspace until it is dropped
+/// the kernel always runs with the alignment check flag cleared so it is cleared again when dropped
+struct UserAccess;
+
+impl UserAccess {
+    #[inline(always)]
+    fn begin() -> Self {
+        if SMAP_ENABLED.load(Ordering::Relaxed) {
+            unsafe { asm!("stac", options(nomem, nostack)) };
+        }
+
+        Self
+    }
+}
+
+impl Drop for UserAccess {
+    #[inline(always)]
+    fn drop(&mut self) {
+        deny_user_access();
+    }
+}
+
+/// an entry of the exception table, a fault at `insturaction` continues at `fixup` instead of panicking
+#[repr(C)]
+struct ExceptionEntry {
+    insturaction: VirtAddr,
+    fixup: VirtAddr,
+}
+
+extern "C" {
+    static __ex_table_start: ExceptionEntry;
+    static __ex_table_end: ExceptionEntry;
+}
+
+/// returns where to continue from if a fault at `insturaction` is expected, see `copy_from_user`
+pub fn exception_fixup(insturaction: VirtAddr) -> Option<VirtAddr> {
+    let table = unsafe {
+        let start = addr_of!(__ex_table_start);
+        let len = addr_of!(__ex_table_end).offset_from(start) as usize;
+        core::slice::from_raw_parts(start, len)
+    };
+
+    table
+        .iter()
+        .find(|entry| entry.insturaction == insturaction)
+        .map(|entry| entry.fixup)
+}
+
+/// copies `len` bytes from `src` to `dest` with userspace accessible
+/// a page fault that cannot be resolved stops the copy and false is returned instead of panicking
+unsafe fn copy_user(dest: *mut u8, src: *const u8, len: usize) -> bool {
+    let _access = UserAccess::begin();
+    let failed: u64;
+
+    asm!(
+        "
+        xor {failed:e}, {failed:e}
+    2:
+        rep movsb
+        jmp 4f
+    3:
+        mov {failed:e}, 1
+    4:
+        .pushsection .ex_table, \"a\"
+        .balign 8
+        .quad 2b, 3b
+        .popsection
+    ",
+        failed = out(reg) failed,
+        inout("rdi") dest => _,
+        inout("rsi") src => _,
+        inout("rcx") len => _,
+        options(nostack)
+    );
+
+    failed == 0
+}
+
+/// copies `dest.len()` bytes from `src` in userspace to `dest`
+/// returns false if `src` isn't mapped, the caller has to check that the caller of the syscall can access it
+pub fn copy_from_user(dest: &mut [u8], src: VirtAddr) -> bool {
+    unsafe { copy_user(dest.as_mut_ptr(), src as *const u8, dest.len()) }
+}
+
+/// copies `src` to `dest` in userspace
+/// returns false if `dest` isn't mapped, the caller has to check that the caller of the syscall can access it
+pub fn copy_to_user(dest: VirtAddr, src: &[u8]) -> bool {
+    unsafe { copy_user(dest as *mut u8, src.as_ptr(), src.len()) }
+}
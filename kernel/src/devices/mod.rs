@@ -1,3 +1,4 @@
+pub mod rtc;
 pub mod serial;
 pub mod tty;
 
@@ -8,6 +9,8 @@ use alloc::{
 use lazy_static::lazy_static;
 use spin::Mutex;
 
+use rtc::RTC;
+
 use crate::{
     arch::serial::SERIAL,
     drivers::vfs::{FSResult, InodeOps},
@@ -92,4 +95,5 @@ lazy_static! {
 pub fn init() {
     DEVICE_MANAGER.lock().add_device(&*FRAMEBUFFER_TERMINAL);
     DEVICE_MANAGER.lock().add_device(&*SERIAL);
+    DEVICE_MANAGER.lock().add_device(&RTC);
 }
@@ -0,0 +1,30 @@
+use alloc::format;
+
+use crate::drivers::{
+    rtc::{self, DateTime},
+    vfs::{FSError, FSResult},
+};
+
+use super::CharDevice;
+
+/// reading gives the current wall-clock time as `YYYY-MM-DD HH:MM:SS\n`
+pub struct Rtc;
+pub static RTC: Rtc = Rtc;
+
+impl CharDevice for Rtc {
+    fn name(&self) -> &'static str {
+        "rtc"
+    }
+
+    fn read(&self, buffer: &mut [u8]) -> FSResult<usize> {
+        let now = format!("{}\n", DateTime::from_unix(rtc::epoch()));
+        let count = now.len().min(buffer.len());
+
+        buffer[..count].copy_from_slice(&now.as_bytes()[..count]);
+        FSResult::Ok(count)
+    }
+
+    fn write(&self, _buffer: &[u8]) -> FSResult<usize> {
+        FSResult::Err(FSError::OperationNotSupported)
+    }
+}
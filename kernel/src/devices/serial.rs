@@ -20,10 +20,8 @@ impl CharDevice for Locked<Serial> {
     fn write(&self, buffer: &[u8]) -> FSResult<usize> {
         let str = unsafe { core::str::from_utf8_unchecked(buffer) };
 
-        self.try_lock()
-            .ok_or(FSError::ResourceBusy)?
-            .write_str(str)
-            .unwrap();
+        // the serial is only locked while something is being written so waiting for it is short
+        self.lock().write_str(str).unwrap();
         FSResult::Ok(buffer.len())
     }
 }
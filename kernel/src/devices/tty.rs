@@ -4,8 +4,8 @@ use alloc::string::String;
 use spin::RwLock;
 
 use crate::{
-    drivers::vfs::{FSError, FSResult},
-    terminal::TTY,
+    drivers::vfs::FSResult,
+    terminal::{STDIN_WAITERS, TTY},
     threading::expose::thread_yeild,
 };
 
@@ -17,41 +17,48 @@ impl CharDevice for RwLock<TTY<'_>> {
     }
 
     fn read(&self, buffer: &mut [u8]) -> FSResult<usize> {
-        if !self
-            .try_read()
-            .ok_or(FSError::ResourceBusy)?
-            .stdin_buffer
-            .ends_with(&['\n'])
-        {
+        // None if the tty is busy
+        let line_ready = || {
+            self.try_read()
+                .map(|reader| reader.stdin_buffer.ends_with(&['\n']))
+        };
+
+        if !self.read().stdin_buffer.ends_with(['\n']) {
             self.write().enable_input();
-            while self
-                .try_read()
-                .is_none_or(|reader| !reader.stdin_buffer.ends_with(&['\n']))
-            {
+
+            // blocks until a line is entered, a busy tty is released shortly so it is only yielded to
+            loop {
+                STDIN_WAITERS.wait_until(|| line_ready() != Some(false));
+                if line_ready() == Some(true) {
+                    break;
+                }
+
                 thread_yeild();
             }
 
             self.write().disable_input();
         }
 
-        let stdin_buffer = &mut self.write().stdin_buffer;
+        let count = {
+            let stdin_buffer = &mut self.write().stdin_buffer;
+
+            let count = if stdin_buffer.len() <= buffer.len() {
+                stdin_buffer.len()
+            } else {
+                buffer.len()
+            };
 
-        let count = if stdin_buffer.len() <= buffer.len() {
-            stdin_buffer.len()
-        } else {
-            buffer.len()
+            buffer[..count].copy_from_slice(&stdin_buffer.as_str().as_bytes()[..count]);
+            stdin_buffer.inner.drain(..count);
+            count
         };
 
-        buffer[..count].copy_from_slice(&stdin_buffer.as_str().as_bytes()[..count]);
-        stdin_buffer.inner.drain(..count);
         Ok(count)
     }
 
     fn write(&self, buffer: &[u8]) -> FSResult<usize> {
-        let _ = self
-            .try_write()
-            .ok_or(FSError::ResourceBusy)?
-            .write_str(&String::from_utf8_lossy(buffer));
+        // the tty is only locked while something is being written or input is handled so waiting for it is short
+        let _ = self.write().write_str(&String::from_utf8_lossy(buffer));
         Ok(buffer.len())
     }
 }
@@ -1,4 +1,5 @@
 pub mod framebuffer;
 pub mod keyboard;
 pub mod keymapper;
+pub mod rtc;
 pub mod vfs;
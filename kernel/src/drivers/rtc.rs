@@ -0,0 +1,205 @@
+//! the cmos real-time clock, it is only read once at boot then the wall-clock time is kept on top of
+//! the monotonic tick
+use core::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    arch::cmos::read_reg,
+    time::{self, TimeSpec},
+};
+
+const SECONDS_REG: u8 = 0x00;
+const MINUTES_REG: u8 = 0x02;
+const HOURS_REG: u8 = 0x04;
+const DAY_REG: u8 = 0x07;
+const MONTH_REG: u8 = 0x08;
+const YEAR_REG: u8 = 0x09;
+const STATUS_A_REG: u8 = 0x0A;
+const STATUS_B_REG: u8 = 0x0B;
+
+/// set in status A while the rtc is updating its registers
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// set in status B if the registers are in 24-hour mode
+const HOURS_24: u8 = 1 << 1;
+/// set in status B if the registers are binary instead of bcd
+const BINARY_MODE: u8 = 1 << 2;
+/// set in the hours register if it is PM in 12-hour mode
+const HOURS_PM: u8 = 1 << 7;
+
+/// the unix time in seconds at which the monotonic clock started
+static BOOT_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+fn update_in_progress() -> bool {
+    read_reg(STATUS_A_REG) & UPDATE_IN_PROGRESS != 0
+}
+
+const fn from_bcd(value: u8) -> u8 {
+    (value & 0x0F) + (value >> 4) * 10
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u64,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl DateTime {
+    /// reads the registers as they are without decoding them
+    fn read_raw() -> Self {
+        while update_in_progress() {
+            core::hint::spin_loop();
+        }
+
+        Self {
+            year: read_reg(YEAR_REG) as u64,
+            month: read_reg(MONTH_REG),
+            day: read_reg(DAY_REG),
+            hours: read_reg(HOURS_REG),
+            minutes: read_reg(MINUTES_REG),
+            seconds: read_reg(SECONDS_REG),
+        }
+    }
+
+    /// reads the date and time from the cmos
+    pub fn read() -> Self {
+        // the registers are read until we get the same values twice in a row, so we never
+        // get a time that is half updated
+        let mut last = Self::read_raw();
+        let mut current = Self::read_raw();
+        while last != current {
+            last = current;
+            current = Self::read_raw();
+        }
+
+        let status_b = read_reg(STATUS_B_REG);
+
+        let pm = current.hours & HOURS_PM != 0;
+        current.hours &= !HOURS_PM;
+
+        if status_b & BINARY_MODE == 0 {
+            current.year = from_bcd(current.year as u8) as u64;
+            current.month = from_bcd(current.month);
+            current.day = from_bcd(current.day);
+            current.hours = from_bcd(current.hours);
+            current.minutes = from_bcd(current.minutes);
+            current.seconds = from_bcd(current.seconds);
+        }
+
+        if status_b & HOURS_24 == 0 {
+            // 12 AM is midnight and 12 PM is noon
+            current.hours %= 12;
+            if pm {
+                current.hours += 12;
+            }
+        }
+
+        // the century register isn't always there so we assume it is the 21st century
+        current.year += 2000;
+        current
+    }
+
+    /// returns true if every field is in range and the date isn't before the unix epoch
+    /// the day isn't checked against the length of the month
+    pub const fn is_vaild(&self) -> bool {
+        self.year >= 1970
+            && matches!(self.month, 1..=12)
+            && matches!(self.day, 1..=31)
+            && self.hours < 24
+            && self.minutes < 60
+            && self.seconds < 60
+    }
+
+    /// converts the date to the seconds since the unix epoch
+    /// returns None if the date isn't vaild, see `DateTime::is_vaild`
+    pub const fn to_unix(&self) -> Option<u64> {
+        if !self.is_vaild() {
+            return None;
+        }
+
+        let month = self.month as u64;
+        let day = self.day as u64;
+        // the year is counted from march so the leap day is at the end of it
+        let year = if month <= 2 { self.year - 1 } else { self.year };
+
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        Some(
+            days * 86400
+                + self.hours as u64 * 3600
+                + self.minutes as u64 * 60
+                + self.seconds as u64,
+        )
+    }
+
+    /// converts the seconds since the unix epoch to a date
+    pub const fn from_unix(seconds: u64) -> Self {
+        let days = seconds / 86400 + 719468;
+        let time = seconds % 86400;
+
+        let era = days / 146097;
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year,
+            month: month as u8,
+            day: day as u8,
+            hours: (time / 3600) as u8,
+            minutes: (time % 3600 / 60) as u8,
+            seconds: (time % 60) as u8,
+        }
+    }
+}
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hours, self.minutes, self.seconds
+        )
+    }
+}
+
+/// reads the cmos clock and starts keeping the wall-clock time
+pub fn init() {
+    let now = DateTime::read();
+    // a cmos that was never set can hold anything, the wall-clock time starts at the epoch then
+    let Some(unix) = now.to_unix() else {
+        crate::serial!("rtc: invaild date {}, starting at the unix epoch\n", now);
+        return;
+    };
+    let boot_epoch = unix.saturating_sub(time::monotonic().seconds);
+
+    BOOT_EPOCH.store(boot_epoch, Ordering::Relaxed);
+    crate::serial!("rtc: booted at {} UTC\n", now);
+}
+
+/// the wall-clock time since the unix epoch
+pub fn now() -> TimeSpec {
+    let mut time = time::monotonic();
+    time.seconds += BOOT_EPOCH.load(Ordering::Relaxed);
+    time
+}
+
+/// the seconds since the unix epoch
+#[inline]
+pub fn epoch() -> u64 {
+    now().seconds
+}
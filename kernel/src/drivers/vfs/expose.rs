@@ -3,88 +3,157 @@
 use core::{fmt::Debug, usize};
 
 use crate::threading::{
-    expose::{add_resource, get_resource, remove_resource},
+    expose::{add_resource, remove_resource, with_resource, with_resources},
     resources::Resource,
 };
 
-use super::{FSError, FSResult, Inode, InodeType, Path, FS, VFS_STRUCT};
-/// gets a FileDescriptor from a fd (file_descriptor id) may return Err(FSError::InvaildFileDescriptor)
-macro_rules! get_fd {
-    ($ri: expr) => {{
-        let Some(crate::threading::resources::Resource::File(ref mut file_descriptor)) =
-            crate::threading::expose::get_resource($ri)
-        else {
-            return Err(FSError::NotAFile);
-        };
+use super::{
+    try_read_vfs, try_write_vfs, FSError, FSResult, FileDescriptor, Inode, InodeType, OpenFlags,
+    Path, FS, VFS,
+};
+/// runs `f` on the FileDescriptor of the resource `ri` with the resources locked
+/// may return Err(FSError::NotAFile) if `ri` isn't a file
+fn with_fd<R>(ri: usize, f: impl FnOnce(&mut FileDescriptor) -> FSResult<R>) -> FSResult<R> {
+    with_resource(ri, |resource| match resource {
+        Resource::File(file_descriptor) => f(file_descriptor),
+        _ => Err(FSError::NotAFile),
+    })
+    .unwrap_or(Err(FSError::NotAFile))
+}
 
-        file_descriptor
-    }};
+/// opens `path` creating it first if it doesn't exist, unless `exclusive` is true in which case it has to not exist
+fn open_created(vfs: &mut VFS, path: Path, exclusive: bool) -> FSResult<FileDescriptor> {
+    match vfs.create(path) {
+        Err(FSError::AlreadyExists) if !exclusive => {}
+        results => results?,
+    }
+    vfs.open(path)
 }
 
 #[no_mangle]
-pub fn open(path: Path) -> FSResult<usize> {
-    let fd = VFS_STRUCT
-        .try_read()
-        .ok_or(FSError::ResourceBusy)?
-        .open(path)?;
+/// opens `path` as a resource which can only be read from or written to if `flags` allows it
+/// returns the ri of the file
+pub fn open(path: Path, flags: OpenFlags) -> FSResult<usize> {
+    let flags = OpenFlags::from_bits(flags.bits()).ok_or(FSError::InvaildArgument)?;
+    if flags.contains(OpenFlags::TRUNCATE) && !flags.contains(OpenFlags::WRITE) {
+        return Err(FSError::InvaildArgument);
+    }
+
+    let mut fd = if flags.contains(OpenFlags::CREATE) {
+        open_created(
+            &mut **try_write_vfs()?,
+            path,
+            flags.contains(OpenFlags::EXCLUSIVE),
+        )?
+    } else {
+        try_read_vfs()?.open(path)?
+    };
+
+    if flags.contains(OpenFlags::TRUNCATE) {
+        fd.node.truncate(0)?;
+    }
+
+    fd.flags = flags;
     Ok(add_resource(Resource::File(fd)))
 }
 
 #[no_mangle]
 pub fn close(ri: usize) -> FSResult<()> {
-    let fd = get_fd!(ri);
-    VFS_STRUCT
-        .try_read()
-        .ok_or(FSError::ResourceBusy)?
-        .close(fd)?;
-
-    _ = remove_resource(ri);
-    Ok(())
+    let vfs = try_read_vfs()?;
+    // removed while still locked so another thread cannot use or close it meanwhile
+    let mut fd = with_resources(|resources| match resources.get(ri) {
+        Some(Resource::File(_)) => match resources.remove_resource(ri) {
+            Ok(Resource::File(fd)) => Ok(fd),
+            _ => unreachable!(),
+        },
+        _ => Err(FSError::NotAFile),
+    })?;
+
+    vfs.close(&mut fd)
 }
 
 #[no_mangle]
 pub fn read(ri: usize, buffer: &mut [u8]) -> FSResult<usize> {
-    let fd = get_fd!(ri);
-    VFS_STRUCT
-        .try_read()
-        .ok_or(FSError::ResourceBusy)?
-        .read(fd, buffer)
+    with_fd(ri, |fd| try_read_vfs()?.read(fd, buffer))
 }
 
 #[no_mangle]
 pub fn write(ri: usize, buffer: &[u8]) -> FSResult<usize> {
-    let fd = get_fd!(ri);
-    VFS_STRUCT
-        .try_read()
-        .ok_or(FSError::ResourceBusy)?
-        .write(fd, buffer)
+    with_fd(ri, |fd| try_read_vfs()?.write(fd, buffer))
+}
+
+/// `whence` of `seek`, the offset is from the start of the file
+pub const SEEK_SET: usize = 0;
+/// the offset is from the current position
+pub const SEEK_CUR: usize = 1;
+/// the offset is from the end of the file
+pub const SEEK_END: usize = 2;
+
+/// moves the position of the file `ri` by `offset` relative to `whence`
+/// returns the new position which can be past the end of the file but not before it's start
+pub fn seek(ri: usize, offset: isize, whence: usize) -> FSResult<usize> {
+    with_fd(ri, |fd| {
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => fd.offset,
+            SEEK_END => fd.node.size()?,
+            _ => return Err(FSError::InvaildArgument),
+        };
+
+        fd.offset = base
+            .checked_add_signed(offset)
+            .ok_or(FSError::InvaildArgument)?;
+        Ok(fd.offset)
+    })
+}
+
+/// reads from the file `ri` starting at `offset` without moving it's position
+pub fn pread(ri: usize, buffer: &mut [u8], offset: usize) -> FSResult<usize> {
+    with_fd(ri, |fd| try_read_vfs()?.read_at(fd, buffer, offset))
+}
+
+/// writes to the file `ri` starting at `offset` without moving it's position
+pub fn pwrite(ri: usize, buffer: &[u8], offset: usize) -> FSResult<usize> {
+    with_fd(ri, |fd| try_read_vfs()?.write_at(fd, buffer, offset))
 }
 
 #[no_mangle]
 pub fn create(path: Path) -> FSResult<()> {
-    VFS_STRUCT
-        .try_write()
-        .ok_or(FSError::ResourceBusy)?
-        .create(path)
+    try_write_vfs()?.create(path)
 }
 
 #[no_mangle]
 pub fn createdir(path: Path) -> FSResult<()> {
-    VFS_STRUCT
-        .try_write()
-        .ok_or(FSError::ResourceBusy)?
-        .createdir(path)
+    try_write_vfs()?.createdir(path)
+}
+
+#[no_mangle]
+pub fn remove(path: Path) -> FSResult<()> {
+    try_write_vfs()?.remove(path)
+}
+
+#[no_mangle]
+pub fn rmdir(path: Path) -> FSResult<()> {
+    try_write_vfs()?.rmdir(path)
+}
+
+#[no_mangle]
+pub fn rename(old: Path, new: Path) -> FSResult<()> {
+    try_write_vfs()?.rename(old, new)
 }
 
 pub const MAX_NAME_LEN: usize = 128;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub struct DirEntry {
     pub kind: InodeType,
     pub size: usize,
     pub name_length: usize,
     pub name: [u8; 128],
+    /// seconds since the unix epoch, 0 if unknown
+    pub created: u64,
+    pub modified: u64,
 }
 
 impl DirEntry {
@@ -94,6 +163,8 @@ impl DirEntry {
 
         let kind = inode.kind();
         let size = inode.size().unwrap_or(0);
+        let created = inode.created();
+        let modified = inode.modified();
 
         let name_length = name_slice.len();
         let mut name = [0u8; MAX_NAME_LEN];
@@ -105,6 +176,8 @@ impl DirEntry {
             size,
             name_length,
             name,
+            created,
+            modified,
         })
     }
 
@@ -117,21 +190,17 @@ impl DirEntry {
 /// opens a diriter as a resource
 /// return the ri of the diriter
 pub fn diriter_open(fd_ri: usize) -> FSResult<usize> {
-    let fd = get_fd!(fd_ri);
-    let diriter = VFS_STRUCT
-        .try_read()
-        .ok_or(FSError::ResourceBusy)?
-        .diriter_open(fd)?;
+    let diriter = with_fd(fd_ri, |fd| try_read_vfs()?.diriter_open(fd))?;
 
     Ok(add_resource(Resource::DirIter(diriter)))
 }
 
 pub fn diriter_next(dir_ri: usize, direntry: &mut DirEntry) -> FSResult<()> {
-    let Some(Resource::DirIter(diriter)) = get_resource(dir_ri) else {
-        return Err(FSError::InvaildFileDescriptorOrRes);
-    };
-
-    let next = diriter.next();
+    let next = with_resource(dir_ri, |resource| match resource {
+        Resource::DirIter(diriter) => Ok(diriter.next()),
+        _ => Err(FSError::InvaildFileDescriptorOrRes),
+    })
+    .unwrap_or(Err(FSError::InvaildFileDescriptorOrRes))?;
     if let Some(entry) = next {
         *direntry = entry.clone();
     } else {
@@ -143,12 +212,14 @@ pub fn diriter_next(dir_ri: usize, direntry: &mut DirEntry) -> FSResult<()> {
 #[no_mangle]
 /// may only Err if dir_ri is invaild
 pub fn diriter_close(dir_ri: usize) -> FSResult<()> {
-    remove_resource(dir_ri).map_err(|_| FSError::InvaildFileDescriptorOrRes)
+    remove_resource(dir_ri)
+        .map(|_| ())
+        .map_err(|_| FSError::InvaildFileDescriptorOrRes)
 }
 
 #[no_mangle]
 pub fn fstat(ri: usize, direntry: &mut DirEntry) -> FSResult<()> {
-    let fd = get_fd!(ri);
-    *direntry = DirEntry::get_from_inode(fd.node.clone())?;
+    let node = with_fd(ri, |fd| Ok(fd.node.clone()))?;
+    *direntry = DirEntry::get_from_inode(node)?;
     Ok(())
 }
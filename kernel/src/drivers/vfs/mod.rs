@@ -1,11 +1,19 @@
-// TODO: define write and read behaviour, especially write
+//! the virtual file system, every drive is a mounted `FS` and paths start with the name of their drive
+//!
+//! files are read and written at a single position kept by their `FileDescriptor`, reads and writes move it forward
+//! writes replace the bytes at the position and extend the file if they go past it's end, they never truncate it
 pub mod expose;
 
-use core::usize;
+use core::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+    usize,
+};
 
 use crate::{
     debug, limine,
-    threading::expose::getcwd,
+    threading::{expose::getcwd, waitqueue::WaitQueue},
     utils::{
         errors::{ErrorStatus, IntoErr},
         ustar::{self, TarArchiveIter},
@@ -15,16 +23,16 @@ pub mod devicefs;
 pub mod ramfs;
 
 use alloc::{
-    borrow::ToOwned,
     boxed::Box,
     collections::btree_map::BTreeMap,
     string::{String, ToString},
     sync::Arc,
     vec::Vec,
 };
+use bitflags::bitflags;
 use expose::DirEntry;
 use lazy_static::lazy_static;
-use spin::RwLock;
+use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 pub type Path<'a> = &'a str;
 
 lazy_static! {
@@ -49,25 +57,54 @@ pub fn init() {
     debug!(VFS, "done ...");
 }
 
+bitflags! {
+    /// how a file is opened, see `expose::open`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct OpenFlags: u8 {
+        /// the file can be read from
+        const READ = 1 << 0;
+        /// the file can be written to
+        const WRITE = 1 << 1;
+        /// creates the file if it doesn't exist
+        const CREATE = 1 << 2;
+        /// empties the file when it is opened, requires `WRITE`
+        const TRUNCATE = 1 << 3;
+        /// every write goes to the end of the file, `write_at` still writes where it is told to
+        const APPEND = 1 << 4;
+        /// with `CREATE` fails if the file already exists
+        const EXCLUSIVE = 1 << 5;
+    }
+}
+
 #[derive(Clone)]
 pub struct FileDescriptor {
     pub mountpoint: *mut dyn FS,
     pub node: Inode,
-    /// acts as a dir entry index for directories
-    /// acts as a byte index for files
-    pub read_pos: usize,
-    /// acts as a byte index for files
-    /// doesn't do anything for directories
-    pub write_pos: usize,
+    /// the byte index the next read or write of a file starts at, shared by both
+    /// can be past the end of the file, reading there reads nothing and writing there fills the gap with zeros
+    pub offset: usize,
+    /// checked by the `VFS` before every read and write
+    pub flags: OpenFlags,
 }
 
 impl FileDescriptor {
+    /// the file descriptor can be read from and written to until `expose::open` sets it's flags
     pub fn new<'a>(mountpoint: *mut dyn FS, node: Inode) -> Self {
         Self {
             mountpoint,
             node,
-            read_pos: 0,
-            write_pos: 0,
+            offset: 0,
+            flags: OpenFlags::READ | OpenFlags::WRITE,
+        }
+    }
+
+    /// fails with `FSError::MissingPermissions` if the file wasn't opened with all of `flags`
+    pub fn check_flags(&self, flags: OpenFlags) -> FSResult<()> {
+        if self.flags.contains(flags) {
+            Ok(())
+        } else {
+            Err(FSError::MissingPermissions)
         }
     }
 }
@@ -86,6 +123,10 @@ pub enum FSError {
     AlreadyExists,
     NotExecuteable,
     ResourceBusy,
+    InvaildArgument,
+    MissingPermissions,
+    DirectoryNotEmpty,
+    CrossDrive,
 }
 
 impl IntoErr for FSError {
@@ -101,10 +142,138 @@ impl IntoErr for FSError {
             Self::AlreadyExists => ErrorStatus::AlreadyExists,
             Self::NotExecuteable => ErrorStatus::NotExecutable,
             Self::ResourceBusy => ErrorStatus::Busy,
+            Self::InvaildArgument => ErrorStatus::InvaildArgument,
+            Self::MissingPermissions => ErrorStatus::MissingPermissions,
+            Self::DirectoryNotEmpty => ErrorStatus::DirectoryNotEmpty,
+            Self::CrossDrive => ErrorStatus::CrossDrive,
         }
     }
 }
 pub type FSResult<T> = Result<T, FSError>;
+
+/// splits `path` into the name of it's drive without the `:` and the rest of it if it starts with a drive
+fn split_drive(path: Path) -> Option<(&str, &str)> {
+    let drive = path.split(&['/', '\\']).next()?;
+    let name = drive.strip_suffix(':')?;
+
+    Some((name, &path[drive.len()..]))
+}
+
+/// adds the components of `path` to `components` removing a component for every `..`
+/// empty components and `.` are skipped, `..` at the root stays at the root
+fn push_components<'a>(components: &mut Vec<&'a str>, path: Path<'a>) {
+    for component in path.split(&['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => _ = components.pop(),
+            component => components.push(component),
+        }
+    }
+}
+
+/// turns `path` into the absolute path it refers to, relative paths are joined to the path `cwd` returns
+///
+/// the canonical form is `drive:/a/b` with no `.`, `..`, duplicate or trailing separators and only `/` as a separator
+/// `cwd` is only called for relative paths and has to return an absolute path
+/// every drive is it's own tree so `..` cannot leave the drive the path starts at
+pub fn canonicalize<'a>(path: Path, cwd: impl FnOnce() -> Path<'a>) -> FSResult<String> {
+    let mut components = Vec::new();
+
+    let (drive, path) = match split_drive(path) {
+        Some(split) => split,
+        None => {
+            let (drive, cwd) = split_drive(cwd()).ok_or(FSError::InvaildPath)?;
+            push_components(&mut components, cwd);
+            (drive, path)
+        }
+    };
+
+    if drive.is_empty() {
+        return Err(FSError::InvaildDrive);
+    }
+    push_components(&mut components, path);
+
+    let mut canonical = String::with_capacity(drive.len() + 2);
+    canonical.push_str(drive);
+    canonical.push_str(":/");
+    canonical.push_str(&components.join("/"));
+    Ok(canonical)
+}
+
+/// returns true if the canonical path `canonical` is the root of it's drive, see `canonicalize`
+/// paths such as `ram:/a/..` collapse to it and cannot be created or replaced
+fn is_drive_root(canonical: Path) -> bool {
+    canonical.ends_with(":/")
+}
+
+/// threads waiting for a resource that was busy, see `retry_busy`
+static BUSY_WAITERS: WaitQueue = WaitQueue::new();
+/// how many times a resource was released, lets waiters tell if one was released after they found it busy
+static RELEASES: AtomicUsize = AtomicUsize::new(0);
+
+/// calls `f` until it doesn't fail with `FSError::ResourceBusy`
+/// the current thread is blocked in between until a resource is released
+pub fn retry_busy<T>(mut f: impl FnMut() -> FSResult<T>) -> FSResult<T> {
+    loop {
+        let releases = RELEASES.load(Ordering::SeqCst);
+
+        match f() {
+            Err(FSError::ResourceBusy) => {
+                BUSY_WAITERS.wait_until(|| RELEASES.load(Ordering::SeqCst) != releases)
+            }
+            results => return results,
+        }
+    }
+}
+
+/// wakes up the threads waiting for a busy resource
+/// called by `ReleaseGuard` after releasing a lock that can make a resource return `FSError::ResourceBusy`
+fn release_resource() {
+    RELEASES.fetch_add(1, Ordering::SeqCst);
+    BUSY_WAITERS.wake_all();
+}
+
+/// a lock guard that wakes up the threads waiting for a busy resource once it's lock is released, see `retry_busy`
+pub struct ReleaseGuard<G>(ManuallyDrop<G>);
+
+impl<G> Deref for ReleaseGuard<G> {
+    type Target = G;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<G> DerefMut for ReleaseGuard<G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<G> Drop for ReleaseGuard<G> {
+    fn drop(&mut self) {
+        // the lock is released first so the woken threads don't find it busy again
+        unsafe { ManuallyDrop::drop(&mut self.0) };
+        release_resource();
+    }
+}
+
+/// locks the vfs for reading, waiting for a writer to finish
+pub fn read_vfs() -> ReleaseGuard<RwLockReadGuard<'static, VFS>> {
+    ReleaseGuard(ManuallyDrop::new(VFS_STRUCT.read()))
+}
+
+/// locks the vfs for reading, fails with `FSError::ResourceBusy` if it is locked for writing
+pub fn try_read_vfs() -> FSResult<ReleaseGuard<RwLockReadGuard<'static, VFS>>> {
+    let guard = VFS_STRUCT.try_read().ok_or(FSError::ResourceBusy)?;
+    Ok(ReleaseGuard(ManuallyDrop::new(guard)))
+}
+
+/// locks the vfs for writing, fails with `FSError::ResourceBusy` if it is locked
+pub fn try_write_vfs() -> FSResult<ReleaseGuard<RwLockWriteGuard<'static, VFS>>> {
+    let guard = VFS_STRUCT.try_write().ok_or(FSError::ResourceBusy)?;
+    Ok(ReleaseGuard(ManuallyDrop::new(guard)))
+}
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum InodeType {
@@ -157,11 +326,27 @@ pub trait InodeOps: Send + Sync {
         Err(FSError::OperationNotSupported)
     }
 
+    /// attempts to remove the entry `name` from self without freeing the node it points to
+    /// returns the inode id of the removed node
+    fn remove(&self, name: &str) -> FSResult<usize> {
+        _ = name;
+        Err(FSError::OperationNotSupported)
+    }
+
     fn truncate(&self, size: usize) -> FSResult<()> {
         _ = size;
         Err(FSError::OperationNotSupported)
     }
 
+    /// returns the time node was created at in seconds since the unix epoch, 0 if unknown
+    fn created(&self) -> u64 {
+        0
+    }
+    /// returns the time node was last modified at in seconds since the unix epoch, 0 if unknown
+    fn modified(&self) -> u64 {
+        0
+    }
+
     fn inodeid(&self) -> usize;
     fn kind(&self) -> InodeType;
 
@@ -198,19 +383,23 @@ impl DirIter {
     }
 
     pub fn next(&mut self) -> Option<DirEntry> {
-        let index = self.index;
-        self.index += 1;
+        loop {
+            let index = self.index;
+            self.index += 1;
 
-        if index >= self.inode_ids.len() {
-            return None;
-        }
+            if index >= self.inode_ids.len() {
+                return None;
+            }
 
-        let inode_id = self.inode_ids[index];
-        let inode = unsafe { (*self.fs).get_inode(inode_id) };
+            let inode_id = self.inode_ids[index];
+            let inode = unsafe { (*self.fs).get_inode(inode_id) };
 
-        match inode {
-            Ok(Some(inode)) => DirEntry::get_from_inode(inode).ok(),
-            _ => None,
+            match inode {
+                Ok(Some(inode)) => return DirEntry::get_from_inode(inode).ok(),
+                // removed after the iterator was opened
+                Ok(None) => continue,
+                Err(_) => return None,
+            }
         }
     }
 }
@@ -323,6 +512,30 @@ pub trait FS: Send + Sync {
         _ = buffer;
         Err(FSError::OperationNotSupported)
     }
+    /// like `read` but starts at `offset` instead of the position of `file_descriptor` and doesn't move it
+    fn read_at(
+        &self,
+        file_descriptor: &FileDescriptor,
+        buffer: &mut [u8],
+        offset: usize,
+    ) -> FSResult<usize> {
+        _ = file_descriptor;
+        _ = buffer;
+        _ = offset;
+        Err(FSError::OperationNotSupported)
+    }
+    /// like `write` but starts at `offset` instead of the position of `file_descriptor` and doesn't move it
+    fn write_at(
+        &self,
+        file_descriptor: &FileDescriptor,
+        buffer: &[u8],
+        offset: usize,
+    ) -> FSResult<usize> {
+        _ = file_descriptor;
+        _ = buffer;
+        _ = offset;
+        Err(FSError::OperationNotSupported)
+    }
     /// creates an empty file named `name` in `path`
     fn create(&mut self, path: Path) -> FSResult<()> {
         _ = path;
@@ -334,6 +547,24 @@ pub trait FS: Send + Sync {
         Err(FSError::OperationNotSupported)
     }
 
+    /// removes the file at `path`, fails if it is still open
+    fn remove(&mut self, path: Path) -> FSResult<()> {
+        _ = path;
+        Err(FSError::OperationNotSupported)
+    }
+    /// removes the empty directory at `path`, fails if it is still open
+    fn rmdir(&mut self, path: Path) -> FSResult<()> {
+        _ = path;
+        Err(FSError::OperationNotSupported)
+    }
+    /// moves the entry at `old` to `new` replacing whatever was at `new`
+    /// a directory can only replace an empty directory and a file can only replace a file
+    fn rename(&mut self, old: Path, new: Path) -> FSResult<()> {
+        _ = old;
+        _ = new;
+        Err(FSError::OperationNotSupported)
+    }
+
     /// opens an iterator of directroy entires, fd must be a directory
     fn diriter_open(&self, fd: &mut FileDescriptor) -> FSResult<DirIter> {
         fd.node.open_diriter(fd.mountpoint)
@@ -388,35 +619,21 @@ impl VFS {
         self.drivers.get(name)
     }
     /// gets the drive name from `path` then gets the drive
-    /// path must be absolute starting with DRIVE_NAME:/
-    /// also handles relative path
+    /// path can be absolute starting with DRIVE_NAME:/ or relative to the cwd
+    /// returns the drive and the canonical form of `path`, see `canonicalize`
     pub(self) fn get_from_path_mut(&mut self, path: Path) -> FSResult<(&mut Box<dyn FS>, String)> {
-        let mut spilt_path = path.split(&['/', '\\']);
-
-        let drive = spilt_path.next().ok_or(FSError::InvaildDrive)?;
-        let full_path = if !(drive.ends_with(':')) {
-            &(getcwd().to_owned() + path)
-        } else {
-            path
-        };
-
-        return self.get_from_path_checked_mut(full_path);
+        let cwd = getcwd();
+        let full_path = canonicalize(path, || &cwd)?;
+        return self.get_from_path_checked_mut(&full_path);
     }
 
     /// gets the drive name from `path` then gets the drive
-    /// path must be absolute starting with DRIVE_NAME:/
-    /// also handles relative path
+    /// path can be absolute starting with DRIVE_NAME:/ or relative to the cwd
+    /// returns the drive and the canonical form of `path`, see `canonicalize`
     pub(self) fn get_from_path(&self, path: Path) -> FSResult<(&Box<dyn FS>, String)> {
-        let mut spilt_path = path.split(&['/', '\\']);
-
-        let drive = spilt_path.next().ok_or(FSError::InvaildDrive)?;
-        let full_path = if !(drive.ends_with(':')) {
-            &(getcwd().to_owned() + path)
-        } else {
-            path
-        };
-
-        return self.get_from_path_checked(full_path);
+        let cwd = getcwd();
+        let full_path = canonicalize(path, || &cwd)?;
+        return self.get_from_path_checked(&full_path);
     }
 
     /// get_from_path but path cannot be realtive to cwd
@@ -456,7 +673,7 @@ impl VFS {
 
     /// checks if a path is a vaild dir returns Err if path has an error
     /// handles relative paths
-    /// returns the absolute path if it is a dir
+    /// returns the canonical absolute path if it is a dir
     pub fn verify_path_dir(&self, path: Path) -> FSResult<String> {
         let (mountpoint, path) = self.get_from_path(path)?;
 
@@ -504,29 +721,92 @@ impl FS for VFS {
     }
 
     fn read(&self, file_descriptor: &mut FileDescriptor, buffer: &mut [u8]) -> FSResult<usize> {
+        file_descriptor.check_flags(OpenFlags::READ)?;
         unsafe { (*file_descriptor.mountpoint).read(file_descriptor, buffer) }
     }
 
     fn write(&self, file_descriptor: &mut FileDescriptor, buffer: &[u8]) -> FSResult<usize> {
+        file_descriptor.check_flags(OpenFlags::WRITE)?;
+
+        // devices have no size, their writes don't have a position anyways
+        if file_descriptor.flags.contains(OpenFlags::APPEND) {
+            if let Ok(size) = file_descriptor.node.size() {
+                file_descriptor.offset = size;
+            }
+        }
         unsafe { (*file_descriptor.mountpoint).write(file_descriptor, buffer) }
     }
 
-    fn create(&mut self, path: Path) -> FSResult<()> {
-        let (mountpoint, path) = self.get_from_path_mut(path)?;
+    fn read_at(
+        &self,
+        file_descriptor: &FileDescriptor,
+        buffer: &mut [u8],
+        offset: usize,
+    ) -> FSResult<usize> {
+        file_descriptor.check_flags(OpenFlags::READ)?;
+        unsafe { (*file_descriptor.mountpoint).read_at(file_descriptor, buffer, offset) }
+    }
 
-        if path.ends_with('/') {
+    fn write_at(
+        &self,
+        file_descriptor: &FileDescriptor,
+        buffer: &[u8],
+        offset: usize,
+    ) -> FSResult<usize> {
+        file_descriptor.check_flags(OpenFlags::WRITE)?;
+        unsafe { (*file_descriptor.mountpoint).write_at(file_descriptor, buffer, offset) }
+    }
+
+    fn create(&mut self, path: Path) -> FSResult<()> {
+        // checked before the trailing separator is removed by `canonicalize`
+        if path.ends_with(&['/', '\\']) {
             return Err(FSError::NotAFile);
         }
 
+        let (mountpoint, path) = self.get_from_path_mut(path)?;
+        if is_drive_root(&path) {
+            return Err(FSError::InvaildPath);
+        }
+
         mountpoint.create(&path)
     }
 
     fn createdir(&mut self, path: Path) -> FSResult<()> {
         let (mountpoint, path) = self.get_from_path_mut(path)?;
+        if is_drive_root(&path) {
+            return Err(FSError::InvaildPath);
+        }
 
         mountpoint.createdir(&path)
     }
 
+    fn remove(&mut self, path: Path) -> FSResult<()> {
+        let (mountpoint, path) = self.get_from_path_mut(path)?;
+
+        mountpoint.remove(&path)
+    }
+
+    fn rmdir(&mut self, path: Path) -> FSResult<()> {
+        let (mountpoint, path) = self.get_from_path_mut(path)?;
+
+        mountpoint.rmdir(&path)
+    }
+
+    fn rename(&mut self, old: Path, new: Path) -> FSResult<()> {
+        let (_, new) = self.get_from_path(new)?;
+        if is_drive_root(&new) {
+            return Err(FSError::InvaildPath);
+        }
+        let (mountpoint, old) = self.get_from_path_mut(old)?;
+
+        // both paths are absolute now so they start with their drive
+        if old.split(&['/', '\\']).next() != new.split(&['/', '\\']).next() {
+            return Err(FSError::CrossDrive);
+        }
+
+        mountpoint.rename(&old, &new)
+    }
+
     fn close(&self, file_descriptor: &mut FileDescriptor) -> FSResult<()> {
         unsafe { (*file_descriptor.mountpoint).close(file_descriptor) }
     }
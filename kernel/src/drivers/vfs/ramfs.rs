@@ -6,6 +6,8 @@ use alloc::vec;
 use alloc::{collections::btree_map::BTreeMap, string::String, vec::Vec};
 use spin::Mutex;
 
+use crate::drivers::rtc;
+
 use super::{DirIter, InodeOf};
 use super::{FSError, FSResult, FileDescriptor, Inode, InodeOps, InodeType, Path, FS};
 
@@ -19,13 +21,19 @@ pub struct RamInode {
     name: String,
     data: RamInodeData,
     inodeid: usize,
+    created: u64,
+    modified: u64,
 }
 impl RamInode {
     fn new(name: String, data: RamInodeData, inodeid: usize) -> Mutex<Self> {
+        let now = rtc::epoch();
+
         Mutex::new(Self {
             name,
             data,
             inodeid,
+            created: now,
+            modified: now,
         })
     }
 
@@ -77,9 +85,11 @@ impl InodeOps for Mutex<RamInode> {
     }
 
     fn truncate(&self, size: usize) -> FSResult<()> {
-        match self.lock().data {
+        let mut node = self.lock();
+        match node.data {
             RamInodeData::Data(ref mut data) => {
                 data.truncate(size);
+                node.modified = rtc::epoch();
                 Ok(())
             }
             RamInodeData::HardLink(ref inode) => inode.truncate(size),
@@ -99,13 +109,22 @@ impl InodeOps for Mutex<RamInode> {
     }
 
     fn write(&self, buffer: &[u8], offset: usize) -> FSResult<usize> {
-        match self.lock().data {
+        let mut node = self.lock();
+        match node.data {
             RamInodeData::Data(ref mut data) => {
-                if data.len() < buffer.len() + offset {
-                    data.resize(buffer.len() + offset, 0);
+                let end = offset
+                    .checked_add(buffer.len())
+                    .ok_or(FSError::InvaildArgument)?;
+
+                // a write far past the end would otherwise panic when the allocation fails
+                if data.len() < end {
+                    data.try_reserve(end - data.len())
+                        .map_err(|_| FSError::InvaildArgument)?;
+                    data.resize(end, 0);
                 }
 
-                data[offset..(offset + buffer.len())].copy_from_slice(buffer);
+                data[offset..end].copy_from_slice(buffer);
+                node.modified = rtc::epoch();
                 Ok(buffer.len())
             }
             RamInodeData::HardLink(ref inode) => inode.write(buffer, offset),
@@ -113,14 +132,29 @@ impl InodeOps for Mutex<RamInode> {
         }
     }
 
+    fn remove(&self, name: &str) -> FSResult<usize> {
+        let mut dir = self.lock();
+        match dir.data {
+            RamInodeData::Children(ref mut tree) => {
+                let inodeid = tree.remove(name).ok_or(FSError::NoSuchAFileOrDirectory)?;
+                dir.modified = rtc::epoch();
+                Ok(inodeid)
+            }
+            RamInodeData::HardLink(ref inode) => inode.remove(name),
+            _ => Err(FSError::NotADirectory),
+        }
+    }
+
     fn insert(&self, name: &str, node: usize) -> FSResult<()> {
-        match self.lock().data {
+        let mut dir = self.lock();
+        match dir.data {
             RamInodeData::Children(ref mut tree) => {
                 if tree.contains_key(name) {
                     return Err(FSError::AlreadyExists);
                 }
 
                 tree.insert(name.to_string(), node);
+                dir.modified = rtc::epoch();
                 Ok(())
             }
             RamInodeData::HardLink(ref inode) => inode.insert(name, node),
@@ -143,6 +177,23 @@ impl InodeOps for Mutex<RamInode> {
     fn inodeid(&self) -> usize {
         self.lock().inodeid
     }
+
+    fn created(&self) -> u64 {
+        let node = self.lock();
+        match node.data {
+            RamInodeData::HardLink(ref inode) => inode.created(),
+            _ => node.created,
+        }
+    }
+
+    fn modified(&self) -> u64 {
+        let node = self.lock();
+        match node.data {
+            RamInodeData::HardLink(ref inode) => inode.modified(),
+            _ => node.modified,
+        }
+    }
+
     fn open_diriter(&self, fs: *mut dyn FS) -> FSResult<DirIter> {
         match self.lock().data {
             RamInodeData::Children(ref data) => Ok(DirIter::new(
@@ -157,25 +208,110 @@ impl InodeOps for Mutex<RamInode> {
 }
 
 pub struct RamFS {
-    inodes: Vec<Inode>,
+    /// indexed by the inode id, removed inodes leave a None behind so the ids of the others don't change
+    inodes: Vec<Option<InodeOf<Mutex<RamInode>>>>,
 }
 
 impl RamFS {
     pub fn new() -> Self {
         Self {
-            inodes: vec![RamInode::new_dir("/".to_string(), 0)],
+            inodes: vec![Some(RamInode::new_dir("/".to_string(), 0))],
         }
     }
 
     fn make_hardlink(&mut self, inodeid: usize, name: String) -> usize {
-        let inode = self.inodes.get_mut(inodeid).unwrap();
-        let inode = inode.clone();
+        let inode: Inode = self.inodes[inodeid].clone().unwrap();
         let inodeid = self.inodes.len();
 
         self.inodes
-            .push(RamInode::new_hardlink(name, inode, inodeid));
+            .push(Some(RamInode::new_hardlink(name, inode, inodeid)));
         inodeid
     }
+
+    /// checks that the inode `inodeid` is a `kind` which can be removed, directories have to be empty
+    /// fails with `FSError::ResourceBusy` if anything other than the inode table still references it
+    /// returns the hardlink to the parent of the inode if it is a directory
+    fn check_removable(&self, inodeid: usize, kind: InodeType) -> FSResult<Option<usize>> {
+        let inode = self.inodes[inodeid]
+            .as_ref()
+            .ok_or(FSError::NoSuchAFileOrDirectory)?;
+
+        let parent_link = match (&inode.lock().data, kind) {
+            (RamInodeData::Data(_), InodeType::File) => None,
+            (RamInodeData::Children(tree), InodeType::Directory) => {
+                if tree.keys().any(|entry| entry != "..") {
+                    return Err(FSError::DirectoryNotEmpty);
+                }
+                tree.get("..").copied()
+            }
+            (_, InodeType::Directory) => return Err(FSError::NotADirectory),
+            _ => return Err(FSError::NotAFile),
+        };
+
+        // open file descriptors keep a reference to the inode
+        // so do the hardlinks of the directories inside of it which is why it is checked after
+        if Arc::strong_count(inode) > 1 {
+            return Err(FSError::ResourceBusy);
+        }
+        Ok(parent_link)
+    }
+
+    /// frees the inode `inodeid` and the hardlink to it's parent if it has one
+    /// the inode must have already been removed from it's parent
+    fn free_inode(&mut self, inodeid: usize, parent_link: Option<usize>) {
+        self.inodes[inodeid] = None;
+        if let Some(parent_link) = parent_link {
+            self.inodes[parent_link] = None;
+        }
+    }
+
+    /// removes the file or the empty directory at `path` depending on `kind` and frees it's inode
+    fn remove_inode(&mut self, path: Path, kind: InodeType) -> FSResult<()> {
+        let (parent, name) = self.reslove_path_uncreated(path)?;
+        if matches!(name, "" | "." | "..") {
+            return Err(FSError::InvaildPath);
+        }
+
+        let inodeid = parent.get(name)?;
+        let parent_link = self.check_removable(inodeid, kind)?;
+
+        parent.remove(name)?;
+        self.free_inode(inodeid, parent_link);
+        Ok(())
+    }
+
+    /// follows the hardlinks starting from `inodeid` returning the id of the inode they lead to
+    fn follow_links(&self, mut inodeid: usize) -> usize {
+        while let Some(inode) = self.inodes[inodeid].as_ref() {
+            match inode.lock().data {
+                RamInodeData::HardLink(ref target) => inodeid = target.inodeid(),
+                _ => break,
+            }
+        }
+        inodeid
+    }
+
+    /// returns true if the directory `dir` is `ancestor` or is somewhere inside of it
+    fn is_inside(&self, dir: usize, ancestor: usize) -> bool {
+        let mut current = self.follow_links(dir);
+
+        while current != ancestor {
+            let parent_link =
+                self.inodes[current]
+                    .as_ref()
+                    .and_then(|inode| match inode.lock().data {
+                        RamInodeData::Children(ref tree) => tree.get("..").copied(),
+                        _ => None,
+                    });
+
+            // only the root has no parent
+            let Some(parent_link) = parent_link else {
+                return false;
+            };
+            current = self.follow_links(parent_link);
+        }
+        true
+    }
 }
 
 impl FS for RamFS {
@@ -185,8 +321,8 @@ impl FS for RamFS {
 
     #[inline]
     fn get_inode(&self, inode_id: usize) -> FSResult<Option<Inode>> {
-        let node = self.inodes.get(inode_id);
-        Ok(node.map(|node| node.clone()))
+        let node = self.inodes.get(inode_id).and_then(Option::as_ref);
+        Ok(node.map(|node| node.clone() as Inode))
     }
 
     fn open(&self, path: Path) -> FSResult<FileDescriptor> {
@@ -200,35 +336,43 @@ impl FS for RamFS {
     }
 
     fn read(&self, file_descriptor: &mut FileDescriptor, buffer: &mut [u8]) -> FSResult<usize> {
-        let count = buffer.len();
-        let file_size = file_descriptor.node.size()?;
+        let count = self.read_at(file_descriptor, buffer, file_descriptor.offset)?;
 
-        let count = if file_descriptor.read_pos + count > file_size {
-            file_size - file_descriptor.read_pos
-        } else {
-            count
-        };
+        file_descriptor.offset += count;
+        Ok(count)
+    }
 
-        file_descriptor
-            .node
-            .read(buffer, file_descriptor.read_pos, count)?;
+    fn write(&self, file_descriptor: &mut FileDescriptor, buffer: &[u8]) -> FSResult<usize> {
+        let count = self.write_at(file_descriptor, buffer, file_descriptor.offset)?;
 
-        file_descriptor.read_pos += count;
+        file_descriptor.offset += count;
         Ok(count)
     }
 
-    fn write(&self, file_descriptor: &mut FileDescriptor, buffer: &[u8]) -> FSResult<usize> {
-        if file_descriptor.write_pos == 0 {
-            file_descriptor.node.truncate(0)?;
-        }
+    fn read_at(
+        &self,
+        file_descriptor: &FileDescriptor,
+        buffer: &mut [u8],
+        offset: usize,
+    ) -> FSResult<usize> {
+        let file_size = file_descriptor.node.size()?;
+        let count = buffer.len().min(file_size.saturating_sub(offset));
 
-        file_descriptor
-            .node
-            .write(buffer, file_descriptor.write_pos)?;
+        // nothing is left to read at or past the end of the file
+        if count == 0 {
+            return Ok(0);
+        }
 
-        file_descriptor.write_pos += buffer.len();
+        file_descriptor.node.read(buffer, offset, count)
+    }
 
-        Ok(buffer.len())
+    fn write_at(
+        &self,
+        file_descriptor: &FileDescriptor,
+        buffer: &[u8],
+        offset: usize,
+    ) -> FSResult<usize> {
+        file_descriptor.node.write(buffer, offset)
     }
 
     fn create(&mut self, path: Path) -> FSResult<()> {
@@ -238,7 +382,7 @@ impl FS for RamFS {
         resloved.insert(name, inodeid)?;
 
         let node = RamInode::new_file(name.to_string(), &[], inodeid);
-        self.inodes.push(node);
+        self.inodes.push(Some(node));
 
         Ok(())
     }
@@ -250,11 +394,77 @@ impl FS for RamFS {
         resloved.insert(name, inodeid)?;
 
         let node = RamInode::new_dir(name.to_string(), inodeid);
-        self.inodes.push(node.clone());
+        self.inodes.push(Some(node.clone()));
 
         let inodeid = self.make_hardlink(resloved.inodeid(), "..".to_string());
         node.insert("..", inodeid)?;
 
         Ok(())
     }
+
+    fn remove(&mut self, path: Path) -> FSResult<()> {
+        self.remove_inode(path, InodeType::File)
+    }
+
+    fn rmdir(&mut self, path: Path) -> FSResult<()> {
+        self.remove_inode(path, InodeType::Directory)
+    }
+
+    fn rename(&mut self, old: Path, new: Path) -> FSResult<()> {
+        let (old_parent, old_name) = self.reslove_path_uncreated(old)?;
+        let (new_parent, new_name) = self.reslove_path_uncreated(new)?;
+        if [old_name, new_name]
+            .iter()
+            .any(|name| matches!(*name, "" | "." | ".."))
+        {
+            return Err(FSError::InvaildPath);
+        }
+
+        let inodeid = old_parent.get(old_name)?;
+        let inode = self.inodes[inodeid]
+            .clone()
+            .ok_or(FSError::NoSuchAFileOrDirectory)?;
+        let kind = inode.kind();
+        let new_parent_id = self.follow_links(new_parent.inodeid());
+
+        // a directory cannot be moved into itself
+        if kind == InodeType::Directory && self.is_inside(new_parent_id, inodeid) {
+            return Err(FSError::InvaildPath);
+        }
+
+        // everything is checked before anything is changed so a failed rename leaves both paths as they were
+        let replaced = match new_parent.get(new_name) {
+            Ok(replaced) if replaced == inodeid => return Ok(()),
+            Ok(replaced) => Some((replaced, self.check_removable(replaced, kind)?)),
+            Err(_) => None,
+        };
+
+        old_parent.remove(old_name)?;
+        if let Some((replaced, parent_link)) = replaced {
+            new_parent.remove(new_name)?;
+            self.free_inode(replaced, parent_link);
+        }
+        new_parent.insert(new_name, inodeid)?;
+
+        let parent_link = {
+            let mut node = inode.lock();
+            node.name = new_name.to_string();
+
+            match node.data {
+                RamInodeData::Children(ref tree) => tree.get("..").copied(),
+                _ => None,
+            }
+        };
+
+        // the hardlink to the parent of a directory has to point to it's new parent
+        if let Some(parent_link) = parent_link {
+            let new_parent: Inode = self.inodes[new_parent_id].clone().unwrap();
+            self.inodes[parent_link] = Some(RamInode::new_hardlink(
+                "..".to_string(),
+                new_parent,
+                parent_link,
+            ));
+        }
+        Ok(())
+    }
 }
@@ -15,8 +15,9 @@ pub struct SchedulerWrapper(pub UnsafeCell<MaybeUninit<Scheduler>>);
 unsafe impl Sync for SchedulerWrapper {}
 pub static SCHEDULER: SchedulerWrapper = SchedulerWrapper(UnsafeCell::new(MaybeUninit::zeroed()));
 
+/// returns true if the scheduler is running threads on the current cpu
 pub fn scheduler_inited() -> bool {
-    scheduler().current_process != core::ptr::null_mut()
+    scheduler().running()
 }
 #[inline(always)]
 pub fn scheduler() -> &'static mut Scheduler {
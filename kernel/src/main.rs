@@ -17,6 +17,7 @@ mod memory;
 mod syscalls;
 mod terminal;
 mod threading;
+mod time;
 mod utils;
 
 extern crate alloc;
@@ -165,6 +166,7 @@ pub extern "C" fn kinit() {
 
     // initing the arch
     arch::init_phase2();
+    drivers::rtc::init();
 
     unsafe {
         devices::init();
@@ -184,8 +186,9 @@ fn kstart() -> ! {
 #[no_mangle]
 fn kmain() -> ! {
     debug!(Scheduler, "done ...");
-    let stdin = vfs::expose::open("dev:/tty").unwrap();
-    let stdout = vfs::expose::open("dev:/tty").unwrap();
+    let tty_flags = vfs::OpenFlags::READ | vfs::OpenFlags::WRITE;
+    let stdin = vfs::expose::open("dev:/tty", tty_flags).unwrap();
+    let stdout = vfs::expose::open("dev:/tty", tty_flags).unwrap();
     serial!(
         "Hello, world!, running tests... stdin: {}, stdout: {}\n",
         stdin,
@@ -3,7 +3,7 @@ use core::{
     mem::MaybeUninit,
 };
 
-use crate::{debug, memory::frame_allocator, utils::Locked};
+use crate::{arch::threading::without_interrupts, debug, memory::frame_allocator, utils::Locked};
 
 use super::{
     align_up,
@@ -282,13 +282,14 @@ impl BuddyAllocator<'_> {
     }
 }
 
+// the heap is locked with interrupts disabled because it can be used while the scheduler's lock is held
 unsafe impl GlobalAlloc for Locked<MaybeUninit<BuddyAllocator<'static>>> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.inner.lock().assume_init_mut().allocmut(layout)
+        without_interrupts(|| self.inner.lock().assume_init_mut().allocmut(layout))
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         _ = layout;
-        self.inner.lock().assume_init_mut().deallocmut(ptr);
+        without_interrupts(|| self.inner.lock().assume_init_mut().deallocmut(ptr));
     }
 }
@@ -2,10 +2,11 @@
 
 use core::slice;
 
+use alloc::collections::btree_map::BTreeMap;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
-use crate::debug;
+use crate::{arch::threading::without_interrupts, debug};
 
 use super::{align_down, align_up, paging::PAGE_SIZE, PhysAddr};
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -116,6 +117,12 @@ impl RegionAllocator {
         }
 
         this.set_used_from(bitmap_base, bitmap_length);
+        // the application processors start from there, see `crate::arch::smp`
+        #[cfg(target_arch = "x86_64")]
+        this.set_used_from(
+            crate::arch::smp::AP_TRAMPOLINE,
+            crate::arch::smp::AP_TRAMPOLINE_SIZE,
+        );
         this
     }
 
@@ -184,21 +191,56 @@ impl RegionAllocator {
             .fold(0, |acc, x| acc + x.count_ones() as usize)
     }
 }
+/// the frames that are mapped by more than one page table (for example copy-on-write pages)
+/// maps the frame address to the number of *extra* owners the frame has
+/// kept outside of the `RegionAllocator` because the heap may allocate frames while it is locked
+static SHARED_FRAMES: Mutex<BTreeMap<PhysAddr, usize>> = Mutex::new(BTreeMap::new());
+
+// the locks below are taken with interrupts disabled because they can be taken while the scheduler's lock is held
+// a cpu interrupted while holding one of them would wait for the scheduler's lock forever otherwise
 lazy_static! {
     pub static ref REGION_ALLOCATOR: Mutex<RegionAllocator> = Mutex::new(RegionAllocator::new());
 }
 #[inline(always)]
 pub fn allocate_frame() -> Option<Frame> {
-    REGION_ALLOCATOR.lock().allocate_frame()
+    without_interrupts(|| REGION_ALLOCATOR.lock().allocate_frame())
 }
 
 #[inline(always)]
 pub fn deallocate_frame(frame: Frame) {
-    REGION_ALLOCATOR.lock().deallocate_frame(frame)
+    without_interrupts(|| REGION_ALLOCATOR.lock().deallocate_frame(frame))
+}
+
+/// adds an owner to `frame`, the frame won't be deallocated until all of it's owners release it
+pub fn share_frame(frame: Frame) {
+    without_interrupts(|| *SHARED_FRAMES.lock().entry(frame.start_address).or_insert(0) += 1)
+}
+
+/// removes an owner from `frame`, deallocates the frame if it was the last owner
+pub fn release_frame(frame: Frame) {
+    without_interrupts(|| {
+        let mut shared = SHARED_FRAMES.lock();
+
+        match shared.get_mut(&frame.start_address) {
+            Some(1) => {
+                shared.remove(&frame.start_address);
+            }
+            Some(owners) => *owners -= 1,
+            None => {
+                drop(shared);
+                deallocate_frame(frame)
+            }
+        }
+    })
+}
+
+/// returns true if `frame` has more than one owner
+pub fn is_frame_shared(frame: Frame) -> bool {
+    without_interrupts(|| SHARED_FRAMES.lock().contains_key(&frame.start_address))
 }
 
 /// returns the number of mapped frames
 #[inline(always)]
 pub fn memory_mapped() -> usize {
-    REGION_ALLOCATOR.lock().memoy_mapped()
+    without_interrupts(|| REGION_ALLOCATOR.lock().memoy_mapped())
 }
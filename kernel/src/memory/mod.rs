@@ -38,6 +38,14 @@ pub const fn align_up(address: usize, alignment: usize) -> usize {
     (address + alignment - 1) & !(alignment - 1)
 }
 
+/// like `align_up` but returns None if the aligned address overflows
+pub const fn checked_align_up(address: usize, alignment: usize) -> Option<usize> {
+    match address.checked_add(alignment - 1) {
+        Some(address) => Some(address & !(alignment - 1)),
+        None => None,
+    }
+}
+
 pub const fn align_down(x: usize, alignment: usize) -> usize {
     x & !(alignment - 1)
 }
@@ -87,24 +95,21 @@ pub fn init(heap_start: usize) {
             MapToError::FrameAllocationFailed => {
                 panic!("frame allocation failure while attempting to init the heap")
             }
+            MapToError::AlreadyMapped => {
+                panic!("the heap overlaps with already mapped memory")
+            }
         }
     }
 }
 
 #[inline(always)]
 pub fn copy_to_userspace(page_table: &mut PageTable, addr: VirtAddr, obj: &[u8]) {
-    let pages_required = ((obj.len() + PAGE_SIZE - 1) / PAGE_SIZE) + 1;
     let mut copied = 0;
-    let mut to_copy = obj.len();
 
-    for i in 0..pages_required {
+    while copied < obj.len() {
         let page = Page::containing_address(addr + copied);
-        let diff = if i == 0 { addr - page.start_address } else { 0 };
-        let will_copy = if to_copy > PAGE_SIZE {
-            PAGE_SIZE - diff
-        } else {
-            to_copy
-        };
+        let diff = addr + copied - page.start_address;
+        let will_copy = (PAGE_SIZE - diff).min(obj.len() - copied);
 
         let frame = page_table.get_frame(page).unwrap();
 
@@ -119,6 +124,31 @@ pub fn copy_to_userspace(page_table: &mut PageTable, addr: VirtAddr, obj: &[u8])
         }
 
         copied += will_copy;
-        to_copy -= will_copy;
+    }
+}
+
+/// copies `buffer.len()` bytes at `addr` in `page_table` to `buffer`, the pages must be mapped
+#[inline(always)]
+pub fn copy_from_userspace(page_table: &mut PageTable, addr: VirtAddr, buffer: &mut [u8]) {
+    let mut copied = 0;
+
+    while copied < buffer.len() {
+        let page = Page::containing_address(addr + copied);
+        let diff = addr + copied - page.start_address;
+        let will_copy = (PAGE_SIZE - diff).min(buffer.len() - copied);
+
+        let frame = page_table.get_frame(page).unwrap();
+
+        let phys_addr = frame.start_address + diff;
+        let virt_addr = phys_addr | hddm();
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                virt_addr as *const u8,
+                buffer.as_mut_ptr().byte_add(copied),
+                will_copy,
+            );
+        }
+
+        copied += will_copy;
     }
 }
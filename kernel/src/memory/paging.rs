@@ -2,14 +2,19 @@ const ENTRY_COUNT: usize = 512;
 const HIGHER_HALF_ENTRY: usize = 256;
 
 pub const PAGE_SIZE: usize = 4096;
+/// the end of the lower half of the address space, everything below belongs to userspace
+/// the last page of the lower half is never mapped so the address after a `syscall` is always canonical for `sysret`
+pub const USERSPACE_END: VirtAddr = 0x0000_7FFF_FFFF_F000;
 use crate::{
     hddm,
     memory::{translate, PhysAddr},
+    utils::errors::{ErrorStatus, IntoErr},
 };
 use bitflags::bitflags;
 use core::{
     arch::asm,
-    ops::{Index, IndexMut},
+    ops::{Index, IndexMut, Range},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use crate::memory::frame_allocator::Frame;
@@ -55,6 +60,20 @@ impl Iterator for IterPage {
     }
 }
 
+/// cleared if the cpu doesn't support no-execute pages, `EntryFlags::NO_EXECUTE` is a reserved bit then
+/// and is left out of every entry, see `disable_no_execute`
+static NO_EXECUTE: AtomicBool = AtomicBool::new(true);
+
+/// leaves `EntryFlags::NO_EXECUTE` out of the entries set from now on, for cpus that don't support it
+pub fn disable_no_execute() {
+    NO_EXECUTE.store(false, Ordering::Relaxed);
+}
+
+/// returns false if `EntryFlags::NO_EXECUTE` is left out of entries, see `disable_no_execute`
+pub fn no_execute_enabled() -> bool {
+    NO_EXECUTE.load(Ordering::Relaxed)
+}
+
 #[derive(Debug, Clone)]
 pub struct Entry(PhysAddr);
 // address of the next table or physial frame in 0x000FFFFF_FFFFF000 (the fs is the address are the fs the rest are flags or reserved)
@@ -74,14 +93,22 @@ impl Entry {
         EntryFlags::from_bits_truncate(self.0 as u64)
     }
 
-    pub const fn new(flags: EntryFlags, addr: PhysAddr) -> Self {
+    pub fn new(mut flags: EntryFlags, addr: PhysAddr) -> Self {
+        if !no_execute_enabled() {
+            flags.remove(EntryFlags::NO_EXECUTE);
+        }
         Self(addr | flags.bits() as usize)
     }
 
-    pub const fn set(&mut self, flags: EntryFlags, addr: PhysAddr) {
+    pub fn set(&mut self, flags: EntryFlags, addr: PhysAddr) {
         *self = Self::new(flags, addr)
     }
 
+    /// marks the entry as unused, doesn't deallocate anything
+    pub const fn clear(&mut self) {
+        self.0 = 0;
+    }
+
     /// deallocates an entry depending on it's level if it is 1 it should just deallocate the frame
     /// otherwise treat the frame as a page table and deallocate it
     /// &mut self becomes invaild after use
@@ -89,7 +116,7 @@ impl Entry {
         let frame = self.frame().unwrap();
 
         if level == 0 {
-            frame_allocator::deallocate_frame(frame);
+            frame_allocator::release_frame(frame);
             return;
         }
         let table = &mut *((frame.start_address | hddm()) as *mut PageTable);
@@ -110,6 +137,8 @@ bitflags! {
         const DIRTY =           1 << 6;
         const HUGE_PAGE =       1 << 7;
         const GLOBAL =          1 << 8;
+        /// available for software use, marks a read-only page that is shared copy-on-write
+        const COW =             1 << 9;
         const NO_EXECUTE =      1 << 63;
     }
 }
@@ -137,7 +166,14 @@ impl PageTable {
     /// deallocates a page table including it's entries, doesn't deallocate the higher half!
     /// unsafe because self becomes invaild after use
     pub unsafe fn free(&mut self, level: u8) {
-        for entry in &mut self.entries[0..HIGHER_HALF_ENTRY] {
+        // only the pml4 has a higher half
+        let count = if level == 4 {
+            HIGHER_HALF_ENTRY
+        } else {
+            ENTRY_COUNT
+        };
+
+        for entry in &mut self.entries[0..count] {
             if entry.0 != 0 {
                 entry.free(level - 1);
             }
@@ -180,6 +216,17 @@ pub unsafe fn current_root_table() -> &'static mut PageTable {
 #[derive(Debug)]
 pub enum MapToError {
     FrameAllocationFailed,
+    /// the address is already used by something else
+    AlreadyMapped,
+}
+
+impl IntoErr for MapToError {
+    fn into_err(self) -> ErrorStatus {
+        match self {
+            Self::FrameAllocationFailed => ErrorStatus::MMapError,
+            Self::AlreadyMapped => ErrorStatus::AlreadyExists,
+        }
+    }
 }
 
 impl Entry {
@@ -194,7 +241,7 @@ impl Entry {
         if self.is_mapped() {
             let addr = self.frame().unwrap().start_address;
 
-            self.set(flags, addr);
+            self.set(self.flags() | flags, addr);
             let virt_addr = addr | hddm();
             let entry_ptr = virt_addr as *mut PageTable;
 
@@ -246,11 +293,16 @@ impl PageTable {
     ) -> Result<(), MapToError> {
         let (level_1_index, level_2_index, level_3_index, level_4_index) =
             translate(page.start_address);
-        let level_3_table = self[level_4_index].map(flags)?;
+        // the tables are shared between pages with different flags so they only restrict the
+        // user accessibility, the actual protection is done by the last level entry
+        let table_flags =
+            EntryFlags::PRESENT | EntryFlags::WRITABLE | (flags & EntryFlags::USER_ACCESSIBLE);
 
-        let level_2_table = level_3_table[level_3_index].map(flags)?;
+        let level_3_table = self[level_4_index].map(table_flags)?;
 
-        let level_1_table = level_2_table[level_2_index].map(flags)?;
+        let level_2_table = level_3_table[level_3_index].map(table_flags)?;
+
+        let level_1_table = level_2_table[level_2_index].map(table_flags)?;
 
         let entry = &mut level_1_table[level_1_index];
 
@@ -258,23 +310,148 @@ impl PageTable {
         Ok(())
     }
 
-    /// gets the frame page points to
-    pub fn get_frame(&mut self, page: Page) -> Option<Frame> {
+    /// gets the level 1 entry that maps `page`
+    /// returns None if one of the tables leading to it is not mapped
+    pub fn get_entry(&mut self, page: Page) -> Option<&'static mut Entry> {
         let (level_1_index, level_2_index, level_3_index, level_4_index) =
             translate(page.start_address);
         let level_3_table = self[level_4_index].mapped_to()?;
         let level_2_table = level_3_table[level_3_index].mapped_to()?;
         let level_1_table = level_2_table[level_2_index].mapped_to()?;
 
-        let entry = &level_1_table[level_1_index];
+        Some(&mut level_1_table[level_1_index])
+    }
 
-        entry.frame()
+    /// gets the frame page points to
+    pub fn get_frame(&mut self, page: Page) -> Option<Frame> {
+        self.get_entry(page)?.frame()
     }
 
     /// unmap page and all of it's entries
     pub fn unmap(&mut self, page: Page) {
-        self.get_frame(page)
-            .inspect(|x| frame_allocator::deallocate_frame(*x));
+        if let Some(frame) = self.take(page) {
+            frame_allocator::release_frame(frame);
+        }
+    }
+
+    /// unmaps `page` like `unmap` without releasing it's frame, returns the frame it was mapped to
+    /// the frame has to be released once no other cpu may still have the page in it's TLB
+    pub fn take(&mut self, page: Page) -> Option<Frame> {
+        let entry = self.get_entry(page)?;
+        let frame = entry.frame();
+        entry.clear();
+        invalidate_page(page);
+        frame
+    }
+}
+
+impl PageTable {
+    /// copies the lower half mappings of this table into `dest` sharing every mapped frame copy-on-write
+    /// writable pages are marked read-only and COW in both tables, the first write to them gives
+    /// the writer it's own copy of the page, see `copy_on_write`
+    /// pages in `skip` are not copied
+    ///
+    /// `level` is the level of this table (4 for a pml4) and `base` is the first address it maps
+    /// the TLB has to be flushed afterwards if this is the current table
+    pub fn copy_cow(
+        &mut self,
+        dest: &mut PageTable,
+        level: u8,
+        base: VirtAddr,
+        skip: &Range<VirtAddr>,
+    ) -> Result<(), MapToError> {
+        let count = if level == 4 {
+            HIGHER_HALF_ENTRY
+        } else {
+            ENTRY_COUNT
+        };
+        let entry_size = PAGE_SIZE << (9 * (level as usize - 1));
+
+        for (i, entry) in self.entries[0..count].iter_mut().enumerate() {
+            let Some(frame) = entry.frame() else {
+                continue;
+            };
+
+            let addr = base + i * entry_size;
+
+            if level == 1 {
+                if skip.contains(&addr) {
+                    continue;
+                }
+
+                let mut flags = entry.flags();
+                if flags.contains(EntryFlags::WRITABLE) {
+                    flags.remove(EntryFlags::WRITABLE);
+                    flags.insert(EntryFlags::COW);
+                    entry.set(flags, frame.start_address);
+                }
+
+                frame_allocator::share_frame(frame);
+                dest[i].set(flags, frame.start_address);
+            } else {
+                let table = entry.mapped_to().unwrap();
+                let dest_table = dest[i].map(entry.flags())?;
+
+                table.copy_cow(dest_table, level - 1, addr, skip)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// gives `page` a writable frame of it's own if it is mapped copy-on-write
+    /// the frame is only copied if it is still shared with another page table
+    /// returns false if `page` isn't a copy-on-write page
+    pub fn copy_on_write(&mut self, page: Page) -> Result<bool, MapToError> {
+        let Some(entry) = self.get_entry(page) else {
+            return Ok(false);
+        };
+
+        let flags = entry.flags();
+        let (Some(frame), true) = (entry.frame(), flags.contains(EntryFlags::COW)) else {
+            return Ok(false);
+        };
+
+        let new_flags = (flags - EntryFlags::COW) | EntryFlags::WRITABLE;
+
+        if frame_allocator::is_frame_shared(frame) {
+            let new_frame =
+                frame_allocator::allocate_frame().ok_or(MapToError::FrameAllocationFailed)?;
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    (frame.start_address | hddm()) as *const u8,
+                    (new_frame.start_address | hddm()) as *mut u8,
+                    PAGE_SIZE,
+                );
+            }
+
+            entry.set(new_flags, new_frame.start_address);
+            frame_allocator::release_frame(frame);
+        } else {
+            entry.set(new_flags, frame.start_address);
+        }
+
+        invalidate_page(page);
+        Ok(true)
+    }
+}
+
+/// flushes the whole TLB of the current address space (except for global pages)
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn flush_tlb() {
+    unsafe {
+        asm!("mov {0}, cr3", "mov cr3, {0}", out(reg) _, options(nostack, preserves_flags));
+    }
+}
+
+/// flushes the TLB entry of `page` in the current address space
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn invalidate_page(page: Page) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) page.start_address, options(nostack, preserves_flags));
     }
 }
 
@@ -1,19 +1,16 @@
+use macros::syscall;
+
 use crate::{
-    drivers::vfs::{self, expose::open, FSError},
-    threading,
-    utils::{
-        errors::ErrorStatus,
-        ffi::{Optional, RequiredMut, Slice, SliceMut},
-    },
+    drivers::vfs::{self, expose::open, OpenFlags},
+    utils::errors::ErrorStatus,
 };
 
-#[no_mangle]
-extern "C" fn sysopen(path_ptr: *const u8, len: usize, dest_fd: Optional<usize>) -> ErrorStatus {
-    let path = Slice::new(path_ptr, len)?.into_str();
-
-    match open(path) {
+/// see `OpenFlags` for what `flags` does, reading or writing without `READ` or `WRITE` fails with `MissingPermissions`
+#[syscall(2)]
+fn sysopen(path: &str, flags: OpenFlags, dest_fd: Option<&mut usize>) -> ErrorStatus {
+    match open(path, flags) {
         Ok(fd) => {
-            if let Some(dest_fd) = dest_fd.into_option() {
+            if let Some(dest_fd) = dest_fd {
                 *dest_fd = fd;
             }
             ErrorStatus::None
@@ -22,56 +19,88 @@ extern "C" fn sysopen(path_ptr: *const u8, len: usize, dest_fd: Optional<usize>)
     }
 }
 
-#[no_mangle]
-extern "C" fn syswrite(fd: usize, ptr: *const u8, len: usize) -> ErrorStatus {
-    let slice = Slice::new(ptr, len)?.into_slice();
-    while let Err(err) = vfs::expose::write(fd, slice) {
-        match err {
-            FSError::ResourceBusy => {
-                threading::expose::thread_yeild();
+#[syscall(3)]
+fn syswrite(fd: usize, buffer: &[u8]) -> ErrorStatus {
+    if let Err(err) = vfs::retry_busy(|| vfs::expose::write(fd, buffer)) {
+        err.into()
+    } else {
+        ErrorStatus::None
+    }
+}
+
+#[syscall(4)]
+fn sysread(fd: usize, buffer: &mut [u8], dest_read: Option<&mut usize>) -> ErrorStatus {
+    match vfs::retry_busy(|| vfs::expose::read(fd, buffer)) {
+        Err(err) => err.into(),
+        Ok(bytes_read) => {
+            if let Some(dest_read) = dest_read {
+                *dest_read = bytes_read;
             }
-            _ => return err.into(),
+            ErrorStatus::None
         }
     }
-    ErrorStatus::None
 }
 
-#[no_mangle]
-extern "C" fn sysread(
+/// `whence` is 0 to seek from the start of the file, 1 from the current position and 2 from the end
+/// the new position is written to `dest_offset`
+#[syscall(39)]
+fn sysseek(
     fd: usize,
-    ptr: *mut u8,
-    len: usize,
-    dest_read: Optional<usize>,
+    offset: isize,
+    whence: usize,
+    dest_offset: Option<&mut usize>,
 ) -> ErrorStatus {
-    let slice = SliceMut::new(ptr, len)?.into_slice();
-
-    loop {
-        match vfs::expose::read(fd, slice) {
-            Err(FSError::ResourceBusy) => threading::expose::thread_yeild(),
-            Err(err) => return err.into(),
-            Ok(bytes_read) => {
-                if let Some(dest_read) = dest_read.into_option() {
-                    *dest_read = bytes_read;
-                }
-                return ErrorStatus::None;
+    match vfs::expose::seek(fd, offset, whence) {
+        Err(err) => err.into(),
+        Ok(new_offset) => {
+            if let Some(dest_offset) = dest_offset {
+                *dest_offset = new_offset;
             }
+            ErrorStatus::None
         }
     }
 }
 
-#[no_mangle]
-extern "C" fn sysclose(fd: usize) -> ErrorStatus {
-    if let Err(err) = vfs::expose::close(fd) {
+/// reads at `offset` without moving the position of `fd`
+#[syscall(40)]
+fn syspread(
+    fd: usize,
+    buffer: &mut [u8],
+    offset: usize,
+    dest_read: Option<&mut usize>,
+) -> ErrorStatus {
+    match vfs::retry_busy(|| vfs::expose::pread(fd, buffer, offset)) {
+        Err(err) => err.into(),
+        Ok(bytes_read) => {
+            if let Some(dest_read) = dest_read {
+                *dest_read = bytes_read;
+            }
+            ErrorStatus::None
+        }
+    }
+}
+
+/// writes at `offset` without moving the position of `fd`
+#[syscall(41)]
+fn syspwrite(fd: usize, buffer: &[u8], offset: usize) -> ErrorStatus {
+    if let Err(err) = vfs::retry_busy(|| vfs::expose::pwrite(fd, buffer, offset)) {
         err.into()
     } else {
         ErrorStatus::None
     }
 }
 
-#[no_mangle]
-extern "C" fn syscreate(path_ptr: *const u8, path_len: usize) -> ErrorStatus {
-    let path = Slice::new(path_ptr, path_len)?.into_str();
+#[syscall(5)]
+fn sysclose(fd: usize) -> ErrorStatus {
+    if let Err(err) = vfs::expose::close(fd) {
+        err.into()
+    } else {
+        ErrorStatus::None
+    }
+}
 
+#[syscall(6)]
+fn syscreate(path: &str) -> ErrorStatus {
     if let Err(err) = vfs::expose::create(path) {
         err.into()
     } else {
@@ -79,10 +108,8 @@ extern "C" fn syscreate(path_ptr: *const u8, path_len: usize) -> ErrorStatus {
     }
 }
 
-#[no_mangle]
-extern "C" fn syscreatedir(path_ptr: *const u8, path_len: usize) -> ErrorStatus {
-    let path = Slice::new(path_ptr, path_len)?.into_str();
-
+#[syscall(7)]
+fn syscreatedir(path: &str) -> ErrorStatus {
     if let Err(err) = vfs::expose::createdir(path) {
         err.into()
     } else {
@@ -90,39 +117,67 @@ extern "C" fn syscreatedir(path_ptr: *const u8, path_len: usize) -> ErrorStatus
     }
 }
 
-#[no_mangle]
-extern "C" fn sysdiriter_open(dir_ri: usize, dest_diriter: *mut usize) -> ErrorStatus {
+/// fails with `ErrorStatus::Busy` if the file is still open
+#[syscall(36)]
+fn sysremove(path: &str) -> ErrorStatus {
+    if let Err(err) = vfs::expose::remove(path) {
+        err.into()
+    } else {
+        ErrorStatus::None
+    }
+}
+
+/// the directory has to be empty and not open
+#[syscall(37)]
+fn sysrmdir(path: &str) -> ErrorStatus {
+    if let Err(err) = vfs::expose::rmdir(path) {
+        err.into()
+    } else {
+        ErrorStatus::None
+    }
+}
+
+/// moves `old` to `new` replacing `new` if it exists, both have to be on the same drive
+/// like `remove` it fails with `Busy` if `new` is replaced while it is still open
+#[syscall(38)]
+fn sysrename(old: &str, new: &str) -> ErrorStatus {
+    if let Err(err) = vfs::expose::rename(old, new) {
+        err.into()
+    } else {
+        ErrorStatus::None
+    }
+}
+
+#[syscall(8)]
+fn sysdiriter_open(dir_ri: usize, dest_diriter: &mut usize) -> ErrorStatus {
     match vfs::expose::diriter_open(dir_ri) {
         Err(err) => err.into(),
-        Ok(ri) => unsafe {
+        Ok(ri) => {
             *dest_diriter = ri;
             ErrorStatus::None
-        },
+        }
     }
 }
 
-#[no_mangle]
-extern "C" fn sysdiriter_close(diriter_ri: usize) -> ErrorStatus {
+#[syscall(9)]
+fn sysdiriter_close(diriter_ri: usize) -> ErrorStatus {
     match vfs::expose::diriter_close(diriter_ri) {
         Err(err) => err.into(),
         Ok(()) => ErrorStatus::None,
     }
 }
 
-#[no_mangle]
-extern "C" fn sysdiriter_next(
-    diriter_ri: usize,
-    direntry: RequiredMut<vfs::expose::DirEntry>,
-) -> ErrorStatus {
-    match vfs::expose::diriter_next(diriter_ri, direntry.get()?) {
+#[syscall(10)]
+fn sysdiriter_next(diriter_ri: usize, direntry: &mut vfs::expose::DirEntry) -> ErrorStatus {
+    match vfs::expose::diriter_next(diriter_ri, direntry) {
         Err(err) => err.into(),
         Ok(()) => ErrorStatus::None,
     }
 }
 
-#[no_mangle]
-extern "C" fn sysfstat(ri: usize, direntry: RequiredMut<vfs::expose::DirEntry>) -> ErrorStatus {
-    if let Err(err) = vfs::expose::fstat(ri, direntry.get()?) {
+#[syscall(12)]
+fn sysfstat(ri: usize, direntry: &mut vfs::expose::DirEntry) -> ErrorStatus {
+    if let Err(err) = vfs::expose::fstat(ri, direntry) {
         err.into()
     } else {
         ErrorStatus::None
@@ -1,9 +1,18 @@
-// TODO: make a proc-macro that generates the syscalls from rust functions
-// for example it should generate a pointer and a length from a slice argument checking if it is vaild and
-// returning invaild ptr if it is not
-// it should also support optional pointer-arguments using Option<T>
-// and we should do something about functions that takes a struct
+//! the syscalls, each one is a rust function marked with `#[syscall(number)]`, see `macros::syscall`
+//! the build script generates the syscall table from the marked functions in this directory
+//! and describes their abi in `kernel/syscalls.json`, the build warns if that copy is out of date
+// TODO: do something about functions that takes a struct
+use crate::utils::errors::ErrorStatus;
+
 mod io;
 mod power;
 mod processes;
+mod time;
+mod trace;
 mod utils;
+
+/// the syscall table entry of the numbers no syscall uses
+#[no_mangle]
+extern "C" fn sysinvaild() -> ErrorStatus {
+    ErrorStatus::InvaildSyscall
+}
@@ -1,16 +1,18 @@
+use macros::syscall;
+
 use crate::{
     arch::power::{reboot, shutdown},
     utils::errors::ErrorStatus,
 };
 
-#[no_mangle]
-extern "C" fn sysshutdown() -> ErrorStatus {
+#[syscall(20)]
+fn sysshutdown() -> ErrorStatus {
     shutdown();
     ErrorStatus::None
 }
 
-#[no_mangle]
-extern "C" fn sysreboot() -> ErrorStatus {
+#[syscall(21)]
+fn sysreboot() -> ErrorStatus {
     reboot();
     ErrorStatus::None
 }
@@ -1,13 +1,16 @@
+use alloc::{string::String, vec::Vec};
+use macros::syscall;
+
 use crate::{
     threading::{self, expose::SpawnFlags, processes::ProcessInfo},
     utils::{
-        errors::ErrorStatus,
-        ffi::{Optional, Required, Slice, SliceMut},
+        errors::{ErrorStatus, ErrorStatusResult},
+        ffi::Slice,
     },
 };
 
-#[no_mangle]
-extern "C" fn syswait(pid: u64) -> usize {
+#[syscall(11)]
+fn syswait(pid: u64) -> usize {
     threading::expose::wait(pid)
 }
 
@@ -15,31 +18,29 @@ extern "C" fn syswait(pid: u64) -> usize {
 #[repr(C)]
 pub struct SpawnConfig {
     pub name: Slice<u8>,
-    pub argv: SliceMut<Slice<u8>>,
+    pub argv: Slice<Slice<u8>>,
     pub flags: SpawnFlags,
 }
 
 impl SpawnConfig {
-    pub fn as_rust<'a>(&'a self) -> (&'a str, &'a [&'a str], SpawnFlags) {
-        (self.name.into_str(), self.argv.into_str_slice(), self.flags)
+    /// copies the name and argv out of userspace memory
+    pub fn as_rust(&self) -> ErrorStatusResult<(String, Vec<String>, SpawnFlags)> {
+        let name = self.name.validate()?.read_str()?;
+        let argv = self.argv.validate()?.read_str_slice()?;
+        ErrorStatusResult::ok((name, argv, self.flags))
     }
 }
 
-// if dest_pid is null we will just ignore it
-#[no_mangle]
-extern "C" fn sysspawn(
-    elf_ptr: *const u8,
-    elf_len: usize,
-    config: Required<SpawnConfig>,
-    dest_pid: Optional<u64>,
-) -> ErrorStatus {
-    let config = config.get()?;
-    let (name, argv, flags) = config.as_rust();
-    let elf_bytes = Slice::new(elf_ptr, elf_len)?.into_slice();
-    match threading::expose::spawn(&name, elf_bytes, argv, flags) {
+/// if dest_pid is null we will just ignore it
+#[syscall(13)]
+fn sysspawn(elf: &[u8], config: &SpawnConfig, dest_pid: Option<&mut u64>) -> ErrorStatus {
+    let (name, argv, flags) = config.as_rust()?;
+    let argv: Vec<&str> = argv.iter().map(|arg| arg.as_str()).collect();
+
+    match threading::expose::spawn(&name, elf, &argv, flags) {
         Err(err) => err.into(),
         Ok(pid) => {
-            if let Some(dest_pid) = dest_pid.into_option() {
+            if let Some(dest_pid) = dest_pid {
                 *dest_pid = pid
             }
             ErrorStatus::None
@@ -47,21 +48,15 @@ extern "C" fn sysspawn(
     }
 }
 
-#[no_mangle]
-extern "C" fn syspspawn(
-    path_ptr: *const u8,
-    path_len: usize,
-    config: Required<SpawnConfig>,
-    dest_pid: Optional<u64>,
-) -> ErrorStatus {
-    let config = config.get()?;
-    let path = Slice::new(path_ptr, path_len)?.into_str();
-    let (name, argv, flags) = config.as_rust();
-
-    match threading::expose::pspawn(name, path, argv, flags) {
+#[syscall(19)]
+fn syspspawn(path: &str, config: &SpawnConfig, dest_pid: Option<&mut u64>) -> ErrorStatus {
+    let (name, argv, flags) = config.as_rust()?;
+    let argv: Vec<&str> = argv.iter().map(|arg| arg.as_str()).collect();
+
+    match threading::expose::pspawn(&name, path, &argv, flags) {
         Err(err) => err.into(),
         Ok(pid) => {
-            if let Some(dest_pid) = dest_pid.into_option() {
+            if let Some(dest_pid) = dest_pid {
                 *dest_pid = pid;
             }
             ErrorStatus::None
@@ -69,13 +64,97 @@ extern "C" fn syspspawn(
     }
 }
 
-#[no_mangle]
-extern "C" fn syspcollect(ptr: *mut ProcessInfo, len: usize) -> ErrorStatus {
-    let slice = SliceMut::new(ptr, len)?.into_slice();
-
-    if let Err(()) = threading::expose::pcollect(slice) {
+#[syscall(17)]
+fn syspcollect(infos: &mut [ProcessInfo]) -> ErrorStatus {
+    if let Err(()) = threading::expose::pcollect(infos) {
         ErrorStatus::Generic
     } else {
         ErrorStatus::None
     }
 }
+
+/// `nice` is clamped to the range of vaild nice values
+#[syscall(34)]
+fn syssetpriority(pid: u64, nice: isize) -> ErrorStatus {
+    match threading::expose::setpriority(pid, nice) {
+        Err(err) => err,
+        Ok(()) => ErrorStatus::None,
+    }
+}
+
+/// logs the syscalls of `pid` to serial if `on` isn't 0 and stops logging them otherwise
+/// `pid` has to be the caller or one of it's descendants
+#[syscall(35)]
+fn syssystrace(pid: u64, on: usize) -> ErrorStatus {
+    match threading::expose::systrace(pid, on != 0) {
+        Err(err) => err,
+        Ok(()) => ErrorStatus::None,
+    }
+}
+
+/// the child sees `dest_pid` as it was before the fork, so it can tell itself apart by initializing it to 0
+#[syscall(22)]
+fn sysfork(dest_pid: Option<&mut u64>) -> ErrorStatus {
+    match threading::expose::fork() {
+        Err(err) => err,
+        Ok(pid) => {
+            if let Some(dest_pid) = dest_pid {
+                *dest_pid = pid;
+            }
+            ErrorStatus::None
+        }
+    }
+}
+
+/// `addr` is the base to set for the set codes, the base is written to `dest` for the get codes
+#[cfg(target_arch = "x86_64")]
+#[syscall(26)]
+fn sysarch_prctl(code: usize, addr: usize, dest: Option<&mut usize>) -> ErrorStatus {
+    match crate::arch::threading::arch_prctl(code, addr) {
+        Err(err) => err,
+        Ok(base) => {
+            if let Some(dest) = dest {
+                *dest = base;
+            }
+            ErrorStatus::None
+        }
+    }
+}
+
+/// only returns if it fails
+#[syscall(23)]
+fn sysexec(path: String, argv: Vec<String>) -> ErrorStatus {
+    threading::expose::exec(path, argv)
+}
+
+/// the thread starts executing `entry` with `arg` as it's only argument, `entry` must never return
+#[syscall(27)]
+fn systhread_spawn(entry: usize, arg: usize, dest_tid: Option<&mut u64>) -> ErrorStatus {
+    match threading::expose::thread_spawn(entry, arg) {
+        Err(err) => err,
+        Ok(tid) => {
+            if let Some(dest_tid) = dest_tid {
+                *dest_tid = tid;
+            }
+            ErrorStatus::None
+        }
+    }
+}
+
+#[syscall(28)]
+fn systhread_exit(code: usize) {
+    threading::expose::thread_exit(code)
+}
+
+#[syscall(29)]
+fn systhread_join(tid: u64, dest_code: Option<&mut usize>) -> ErrorStatus {
+    match threading::expose::thread_join(tid) {
+        Err(err) => err,
+        Ok(code) => {
+            if let Some(dest_code) = dest_code {
+                *dest_code = code;
+            }
+            ErrorStatus::None
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use macros::syscall;
+
+use crate::{
+    drivers::rtc,
+    threading,
+    time::{self, TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME},
+    utils::errors::ErrorStatus,
+};
+
+#[syscall(30)]
+fn syssleep(ms: u64) {
+    threading::expose::sleep(ms)
+}
+
+/// returns the milliseconds since boot
+#[syscall(31)]
+fn sysuptime() -> u64 {
+    time::uptime()
+}
+
+/// returns the seconds since the unix epoch
+#[syscall(33)]
+fn systime() -> u64 {
+    rtc::epoch()
+}
+
+#[syscall(32)]
+fn sysclock_gettime(clock: usize, dest: &mut TimeSpec) -> ErrorStatus {
+    *dest = match clock {
+        CLOCK_REALTIME => rtc::now(),
+        CLOCK_MONOTONIC => time::monotonic(),
+        _ => return ErrorStatus::OperationNotSupported,
+    };
+
+    ErrorStatus::None
+}
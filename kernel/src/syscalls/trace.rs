@@ -0,0 +1,110 @@
+//! logging of the syscalls made by traced processes, see `processes::syssystrace`
+//!
+//! the generated wrapper of every syscall logs a line to serial with it's arguments when it is called
+//! and another one with it's result and how long it took when it returns
+use core::fmt::{self, Debug};
+
+use crate::{
+    arch::{tsc, usercopy::copy_from_user},
+    scheduler, serial,
+    threading::expose::is_user_range_mapped,
+};
+
+/// the longest str argument that is logged in full
+const MAX_STR_LEN: usize = 64;
+
+/// a syscall of a traced process that didn't return yet
+pub struct Trace {
+    number: u16,
+    name: &'static str,
+    start: u64,
+}
+
+impl Trace {
+    /// logs the call if the current process is traced, returns None otherwise
+    #[inline(always)]
+    pub fn begin(number: u16, name: &'static str, args: fmt::Arguments) -> Option<Self> {
+        let process = scheduler().current_process();
+        if !process.traced {
+            return None;
+        }
+
+        let tid = scheduler().current_thread().tid;
+        serial!(
+            "[trace] {}:{} {}#{}({})\n",
+            process.pid,
+            tid,
+            name,
+            number,
+            args
+        );
+
+        Some(Self {
+            number,
+            name,
+            start: tsc::read(),
+        })
+    }
+
+    /// logs the result of the call, syscalls which don't return such as `exit` never get here
+    pub fn end(self, result: &impl Debug) {
+        let elapsed = tsc::to_nanoseconds(tsc::read().wrapping_sub(self.start));
+        let pid = scheduler().current_process().pid;
+        let tid = scheduler().current_thread().tid;
+
+        serial!(
+            "[trace] {}:{} {}#{} = {:?} in {}ns\n",
+            pid,
+            tid,
+            self.name,
+            self.number,
+            result,
+            elapsed
+        );
+    }
+}
+
+/// a `&str` argument, shown as a str if it is readable and as a pointer otherwise
+/// it is logged before the arguments are checked so it is only copied if it's pages are already mapped
+pub struct Str(pub *const u8, pub usize);
+
+impl Debug for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = [0u8; MAX_STR_LEN];
+        let bytes = &mut buffer[..self.1.min(MAX_STR_LEN)];
+
+        if !(is_user_range_mapped(self.0 as usize, bytes.len())
+            && copy_from_user(bytes, self.0 as usize))
+        {
+            return Buffer(self.0 as usize, self.1).fmt(f);
+        }
+
+        match core::str::from_utf8(bytes) {
+            Ok(s) if self.1 > MAX_STR_LEN => write!(f, "{s:?}..."),
+            Ok(s) => write!(f, "{s:?}"),
+            Err(_) => Buffer(self.0 as usize, self.1).fmt(f),
+        }
+    }
+}
+
+/// a pointer and a length, the values it points to are not shown
+pub struct Buffer(pub usize, pub usize);
+
+impl Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}[{}]", self.0, self.1)
+    }
+}
+
+/// a pointer to a single value, the value is not shown
+pub struct Pointer(pub usize);
+
+impl Debug for Pointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            write!(f, "null")
+        } else {
+            write!(f, "{:#x}", self.0)
+        }
+    }
+}
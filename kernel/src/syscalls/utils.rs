@@ -1,28 +1,26 @@
+use macros::syscall;
+
 use crate::{
-    threading,
-    utils::{
+    threading::{
         self,
-        errors::ErrorStatus,
-        expose::SysInfo,
-        ffi::{Optional, RequiredMut, Slice, SliceMut},
+        vmas::{MMapFlags, MMapProt},
     },
+    utils::{self, errors::ErrorStatus, expose::SysInfo},
 };
 
 /// for now
-#[no_mangle]
-extern "C" fn sysexit(code: usize) {
-    threading::expose::thread_exit(code);
+#[syscall(0)]
+fn sysexit(code: usize) {
+    threading::expose::process_exit(code);
 }
 
-#[no_mangle]
-extern "C" fn sysyield() {
+#[syscall(1)]
+fn sysyield() {
     threading::expose::thread_yeild()
 }
 
-#[no_mangle]
-extern "C" fn syschdir(path_ptr: *const u8, path_len: usize) -> ErrorStatus {
-    let path = Slice::new(path_ptr, path_len)?.into_str();
-
+#[syscall(14)]
+fn syschdir(path: &str) -> ErrorStatus {
     if let Err(err) = threading::expose::chdir(path) {
         err.into()
     } else {
@@ -30,33 +28,75 @@ extern "C" fn syschdir(path_ptr: *const u8, path_len: usize) -> ErrorStatus {
     }
 }
 
-#[no_mangle]
-extern "C" fn sysgetcwd(path_ptr: *mut u8, len: usize, dest_len: Optional<usize>) -> ErrorStatus {
-    let path = SliceMut::new(path_ptr, len)?.into_slice();
-    let got = threading::expose::getcwd().as_bytes();
+#[syscall(15)]
+fn sysgetcwd(path: &mut [u8], dest_len: Option<&mut usize>) -> ErrorStatus {
+    let cwd = threading::expose::getcwd();
+    let got = cwd.as_bytes();
 
-    if got.len() > len {
+    if got.len() > path.len() {
         return ErrorStatus::Generic;
     }
 
     path[..got.len()].copy_from_slice(&got);
 
-    if let Some(dest_len) = dest_len.into_option() {
+    if let Some(dest_len) = dest_len {
         *dest_len = got.len();
     }
 
     ErrorStatus::None
 }
 
-// on fail returns null for unknown reasons
-#[no_mangle]
-extern "C" fn syssbrk(amount: isize) -> *mut u8 {
+/// on fail returns null for unknown reasons
+#[syscall(18)]
+fn syssbrk(amount: isize) -> *mut u8 {
     threading::expose::sbrk(amount)
 }
 
-#[no_mangle]
-extern "C" fn sysinfo(ptr: RequiredMut<SysInfo>) -> ErrorStatus {
-    utils::expose::info(ptr.get()?);
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct MMapConfig {
+    /// where to map the memory, 0 means anywhere
+    pub addr: usize,
+    pub len: usize,
+    pub prot: MMapProt,
+    pub flags: MMapFlags,
+    /// the file resource to map, ignored for anonymous mappings
+    pub ri: usize,
+    pub offset: usize,
+}
+
+#[syscall(24)]
+fn sysmmap(config: &MMapConfig, dest_addr: Option<&mut *mut u8>) -> ErrorStatus {
+    match threading::expose::mmap(
+        config.addr,
+        config.len,
+        config.prot,
+        config.flags,
+        config.ri,
+        config.offset,
+    ) {
+        Err(err) => err,
+        Ok(addr) => {
+            if let Some(dest_addr) = dest_addr {
+                *dest_addr = addr as *mut u8;
+            }
+            ErrorStatus::None
+        }
+    }
+}
+
+#[syscall(25)]
+fn sysmunmap(addr: *mut u8, len: usize) -> ErrorStatus {
+    if let Err(err) = threading::expose::munmap(addr as usize, len) {
+        err
+    } else {
+        ErrorStatus::None
+    }
+}
+
+#[syscall(16)]
+fn sysinfo(info: &mut SysInfo) -> ErrorStatus {
+    utils::expose::info(info);
 
     ErrorStatus::None
 }
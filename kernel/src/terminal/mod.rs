@@ -9,10 +9,16 @@ use crate::{
         keys::{Key, KeyCode, KeyFlags},
         HandleKey,
     },
-    threading::expose::{pspawn, SpawnFlags},
+    threading::{
+        expose::{pspawn, SpawnFlags},
+        waitqueue::WaitQueue,
+    },
     utils::{alloc::PageString, Locked},
 };
 
+/// threads waiting for a line of input, woken up whenever a line is entered
+pub static STDIN_WAITERS: WaitQueue = WaitQueue::new();
+
 pub mod framebuffer;
 
 /// defines the interface for a tty
@@ -157,6 +163,10 @@ impl HandleKey for TTY<'_> {
                         let _ = self.write_char(char);
                         self.stdin_buffer.push_char(char);
                     }
+
+                    if char == '\n' {
+                        STDIN_WAITERS.wake_all();
+                    }
                     // put the cursor back
                     _ = self.write_char('_');
                 }
@@ -1,14 +1,106 @@
+use alloc::vec::Vec;
 use macros::test_module;
 
+use crate::drivers::vfs::expose::{close, open, read, write};
+use crate::drivers::vfs::OpenFlags;
+use crate::memory::paging::PAGE_SIZE;
+use crate::utils::elf::{
+    ElfClass, ElfHeader, ElfIEndianness, ElfInstrSet, ElfType, ProgramFlags, ProgramHeader,
+    ProgramType, SectionHeader,
+};
+use crate::VirtAddr;
+
+/// writes `content` to the file at `path` creating it if it doesn't exist
+fn write_file(path: &str, content: &[u8]) {
+    let ri = open(
+        path,
+        OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+    )
+    .unwrap();
+    assert_eq!(write(ri, content).unwrap(), content.len());
+    close(ri).unwrap();
+}
+
+/// reads up to 16 bytes from the start of the file at `path`
+fn read_file(path: &str) -> Vec<u8> {
+    let ri = open(path, OpenFlags::READ).unwrap();
+    let mut buffer = [0u8; 16];
+    let count = read(ri, &mut buffer).unwrap();
+    close(ri).unwrap();
+    buffer[..count].to_vec()
+}
+
+/// an executable with nothing but program headers
+#[allow(dead_code)]
+#[repr(C)]
+struct TestImage {
+    header: ElfHeader,
+    program_headers: [ProgramHeader; 2],
+}
+
+/// creates an executable with a read-only executable segment at 0x400000 and a writable segment at `data_vaddr`
+fn test_image(data_vaddr: VirtAddr) -> TestImage {
+    let segment = |flags, vaddr| ProgramHeader {
+        ptype: ProgramType::LOAD,
+        flags,
+        offset: 0,
+        vaddr,
+        paddr: vaddr,
+        filez: 0,
+        memz: 0x100,
+        align: PAGE_SIZE,
+    };
+
+    TestImage {
+        header: ElfHeader {
+            magic: [0x7F, b'E', b'L', b'F'],
+            class: ElfClass::ELF64,
+            endianness: ElfIEndianness::LITTLE,
+            version: 1,
+            _osabi: 0,
+            _abiver: 0,
+            _padding: [0; 7],
+            kind: ElfType::EXE,
+            insturction_set: ElfInstrSet::AMD64,
+            version_2: 1,
+            entry_point: 0x400000,
+            program_headers_table_offset: size_of::<ElfHeader>(),
+            section_header_table_offset: 0,
+            flags: 0,
+            size: size_of::<ElfHeader>() as u16,
+            program_headers_table_entry_size: size_of::<ProgramHeader>() as u16,
+            program_headers_table_entries_number: 2,
+            section_table_entry_size: size_of::<SectionHeader>() as u16,
+            section_table_entries: 0,
+            sections_names_section_offset: 0,
+        },
+        program_headers: [
+            segment(ProgramFlags::READ | ProgramFlags::EXEC, 0x400000),
+            segment(ProgramFlags::READ | ProgramFlags::WRITE, data_vaddr),
+        ],
+    }
+}
+
 #[test_module]
 pub mod testing_module {
     use alloc::vec::Vec;
 
     use crate::cross_println;
+    use crate::drivers::vfs::canonicalize;
+    use crate::drivers::vfs::expose::{
+        close, createdir, open, pread, pwrite, read, remove, rename, rmdir, seek, write, SEEK_CUR,
+        SEEK_END, SEEK_SET,
+    };
+    use crate::drivers::vfs::{FSError, OpenFlags};
+    use crate::hddm;
+    use crate::memory::paging::{allocate_pml4, EntryFlags, Page, PageTable};
     use crate::println;
     use crate::threading::expose::pspawn;
     use crate::threading::expose::wait;
     use crate::threading::expose::SpawnFlags;
+    use crate::utils::elf::{Elf, ElfError};
+
+    use super::{read_file, test_image, write_file, TestImage};
     use core::arch::asm;
 
     fn serial() {}
@@ -70,6 +162,236 @@ pub mod testing_module {
         assert_eq!(ret, 1);
     }
 
+    fn path_canonicalization() {
+        let cwd = || "ram:/home/user/";
+
+        assert_eq!(canonicalize("ram:/", cwd).unwrap(), "ram:/");
+        assert_eq!(canonicalize("ram:/a//b/./c/", cwd).unwrap(), "ram:/a/b/c");
+        assert_eq!(canonicalize("sys:\\bin\\..\\tmp", cwd).unwrap(), "sys:/tmp");
+        assert_eq!(canonicalize("ram:/../..", cwd).unwrap(), "ram:/");
+        assert_eq!(canonicalize("..", cwd).unwrap(), "ram:/home");
+        assert_eq!(canonicalize("../../../etc", cwd).unwrap(), "ram:/etc");
+        assert_eq!(
+            canonicalize("docs/./notes", cwd).unwrap(),
+            "ram:/home/user/docs/notes"
+        );
+        assert!(canonicalize(":/a", cwd).is_err());
+    }
+
+    fn vfs_remove() {
+        createdir("ram:/remove").unwrap();
+        write_file("ram:/remove/file", b"file");
+
+        // open files and directories cannot be removed
+        let ri = open("ram:/remove/file", OpenFlags::READ).unwrap();
+        assert!(matches!(
+            remove("ram:/remove/file"),
+            Err(FSError::ResourceBusy)
+        ));
+        close(ri).unwrap();
+
+        assert!(matches!(
+            rmdir("ram:/remove"),
+            Err(FSError::DirectoryNotEmpty)
+        ));
+        assert!(matches!(
+            rmdir("ram:/remove/file"),
+            Err(FSError::NotADirectory)
+        ));
+        assert!(matches!(remove("ram:/remove"), Err(FSError::NotAFile)));
+        remove("ram:/remove/file").unwrap();
+
+        let ri = open("ram:/remove", OpenFlags::READ).unwrap();
+        assert!(matches!(rmdir("ram:/remove"), Err(FSError::ResourceBusy)));
+        close(ri).unwrap();
+
+        rmdir("ram:/remove").unwrap();
+        assert!(matches!(
+            open("ram:/remove", OpenFlags::READ),
+            Err(FSError::NoSuchAFileOrDirectory)
+        ));
+    }
+
+    fn vfs_rename() {
+        createdir("ram:/rename").unwrap();
+        createdir("ram:/rename/dir").unwrap();
+
+        // a directory cannot be moved into itself or onto the root of the drive
+        assert!(matches!(
+            rename("ram:/rename/dir", "ram:/rename/dir/inner"),
+            Err(FSError::InvaildPath)
+        ));
+        assert!(matches!(
+            rename("ram:/rename/dir", "ram:/rename/.."),
+            Err(FSError::InvaildPath)
+        ));
+        rename("ram:/rename/dir", "ram:/rename/moved").unwrap();
+        assert!(matches!(
+            open("ram:/rename/dir", OpenFlags::READ),
+            Err(FSError::NoSuchAFileOrDirectory)
+        ));
+
+        // an open file cannot be replaced, like it cannot be removed
+        write_file("ram:/rename/a", b"a");
+        write_file("ram:/rename/b", b"b");
+        let ri = open("ram:/rename/b", OpenFlags::READ).unwrap();
+        assert!(matches!(
+            rename("ram:/rename/a", "ram:/rename/b"),
+            Err(FSError::ResourceBusy)
+        ));
+        assert_eq!(read_file("ram:/rename/a"), b"a");
+        close(ri).unwrap();
+
+        rename("ram:/rename/a", "ram:/rename/b").unwrap();
+        assert_eq!(read_file("ram:/rename/b"), b"a");
+
+        // a file cannot replace a directory
+        assert!(matches!(
+            rename("ram:/rename/b", "ram:/rename/moved"),
+            Err(FSError::NotAFile)
+        ));
+        assert!(matches!(
+            rename("ram:/rename/b", "sys:/b"),
+            Err(FSError::CrossDrive)
+        ));
+
+        remove("ram:/rename/b").unwrap();
+        rmdir("ram:/rename/moved").unwrap();
+        rmdir("ram:/rename").unwrap();
+    }
+
+    fn vfs_offsets() {
+        let ri = open(
+            "ram:/offsets",
+            OpenFlags::READ | OpenFlags::WRITE | OpenFlags::CREATE,
+        )
+        .unwrap();
+        let mut buffer = [0u8; 16];
+
+        assert_eq!(write(ri, b"hello").unwrap(), 5);
+        assert_eq!(read(ri, &mut buffer).unwrap(), 0);
+        assert_eq!(seek(ri, 0, SEEK_END).unwrap(), 5);
+        assert_eq!(pread(ri, &mut buffer, 5).unwrap(), 0);
+        assert_eq!(pread(ri, &mut buffer, 100).unwrap(), 0);
+
+        // writing past the end fills the gap with zeros without moving the position
+        assert_eq!(pwrite(ri, b"!", 7).unwrap(), 1);
+        assert_eq!(seek(ri, 0, SEEK_CUR).unwrap(), 5);
+        assert_eq!(pread(ri, &mut buffer, 0).unwrap(), 8);
+        assert_eq!(&buffer[..8], b"hello\0\0!");
+
+        assert_eq!(seek(ri, 2, SEEK_END).unwrap(), 10);
+        assert_eq!(read(ri, &mut buffer).unwrap(), 0);
+        assert!(matches!(
+            seek(ri, -1, SEEK_SET),
+            Err(FSError::InvaildArgument)
+        ));
+        assert!(matches!(seek(ri, 0, 3), Err(FSError::InvaildArgument)));
+        assert!(matches!(
+            pwrite(ri, b"x", usize::MAX),
+            Err(FSError::InvaildArgument)
+        ));
+
+        close(ri).unwrap();
+        remove("ram:/offsets").unwrap();
+    }
+
+    fn vfs_open_flags() {
+        assert!(matches!(
+            open("ram:/flags", OpenFlags::READ),
+            Err(FSError::NoSuchAFileOrDirectory)
+        ));
+        write_file("ram:/flags", b"content");
+        assert!(matches!(
+            open(
+                "ram:/flags",
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE
+            ),
+            Err(FSError::AlreadyExists)
+        ));
+        assert!(matches!(
+            open("ram:/flags", OpenFlags::READ | OpenFlags::TRUNCATE),
+            Err(FSError::InvaildArgument)
+        ));
+        assert!(matches!(
+            open("ram:/flags", OpenFlags::from_bits_retain(1 << 7)),
+            Err(FSError::InvaildArgument)
+        ));
+
+        let mut buffer = [0u8; 16];
+        let ri = open("ram:/flags", OpenFlags::READ).unwrap();
+        assert!(matches!(write(ri, b"x"), Err(FSError::MissingPermissions)));
+        assert!(matches!(
+            pwrite(ri, b"x", 0),
+            Err(FSError::MissingPermissions)
+        ));
+        close(ri).unwrap();
+
+        let ri = open("ram:/flags", OpenFlags::WRITE).unwrap();
+        assert!(matches!(
+            read(ri, &mut buffer),
+            Err(FSError::MissingPermissions)
+        ));
+        assert!(matches!(
+            pread(ri, &mut buffer, 0),
+            Err(FSError::MissingPermissions)
+        ));
+        close(ri).unwrap();
+
+        // appending writes go to the end wherever the position is
+        let ri = open("ram:/flags", OpenFlags::WRITE | OpenFlags::APPEND).unwrap();
+        seek(ri, 0, SEEK_SET).unwrap();
+        write(ri, b"!").unwrap();
+        close(ri).unwrap();
+        assert_eq!(read_file("ram:/flags"), b"content!");
+
+        let ri = open("ram:/flags", OpenFlags::WRITE | OpenFlags::TRUNCATE).unwrap();
+        close(ri).unwrap();
+        assert_eq!(read_file("ram:/flags"), b"");
+
+        remove("ram:/flags").unwrap();
+    }
+
+    fn elf_writable_segments() {
+        // returns the flags of the code page and the data page after loading `test_image(data_vaddr)`
+        let load = |data_vaddr| {
+            let image = test_image(data_vaddr);
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    &image as *const TestImage as *const u8,
+                    size_of::<TestImage>(),
+                )
+            };
+            let elf = Elf::new(bytes).unwrap();
+
+            let page_table_addr = allocate_pml4().unwrap();
+            let page_table = unsafe { &mut *((page_table_addr | hddm()) as *mut PageTable) };
+
+            let results = elf.load_exec(page_table, 0).map(|_| {
+                let mut flags = |addr| {
+                    page_table
+                        .get_entry(Page::containing_address(addr))
+                        .unwrap()
+                        .flags()
+                };
+                (flags(0x400000), flags(data_vaddr))
+            });
+
+            unsafe { page_table.free(4) };
+            results
+        };
+
+        let (code, data) = load(0x401000).unwrap();
+        assert!(!code.contains(EntryFlags::WRITABLE) && !code.contains(EntryFlags::NO_EXECUTE));
+        assert!(data.contains(EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE));
+
+        // the writable segment shares the page of the executable segment
+        assert!(matches!(
+            load(0x400800),
+            Err(ElfError::WritableAndExecutable)
+        ));
+    }
+
     fn userspace() {
         let pid = pspawn("TEST_BOT", "sys:/bin/TestBot", &[], SpawnFlags::empty()).unwrap();
         let ret = wait(pid);
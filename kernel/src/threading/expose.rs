@@ -1,29 +1,101 @@
 use core::arch::asm;
 
-use alloc::{
-    string::{String, ToString},
-    vec::Vec,
-};
+use alloc::{string::String, vec::Vec};
 use bitflags::bitflags;
 
 use crate::{
+    arch::threading::{restore_cpu_status, without_interrupts, CPUStatus},
     drivers::vfs::{
-        expose::{fstat, open, read, DirEntry},
-        FSError, FSResult, InodeType, VFS_STRUCT,
+        expose::{close, fstat, open, read, DirEntry},
+        read_vfs, FSError, FSResult, InodeType, OpenFlags,
     },
     khalt, scheduler,
     threading::processes::Process,
-    utils::elf::{Elf, ElfError},
+    time,
+    utils::{
+        elf::{Elf, ElfError},
+        errors::ErrorStatus,
+    },
+    VirtAddr,
 };
 
 use super::{
     processes::{ProcessInfo, ProcessState},
-    resources::Resource,
+    resources::{Resource, ResourceManager},
+    vmas::{MMapFlags, MMapProt, VMABacking},
+    with_scheduler_lock, EXIT_WAITERS, NICE_MAX, NICE_MIN,
 };
 
+/// terminates the current process with all of it's threads
 #[no_mangle]
-pub fn thread_exit(code: usize) {
-    scheduler().current_process().terminate(code, 0);
+pub fn process_exit(code: usize) {
+    let pid = scheduler().current_process().pid;
+    scheduler().terminate(pid, code, 0);
+    // enables interrupts if they were disabled to give control back to the scheduler
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        asm!("sti")
+    }
+    khalt()
+}
+
+/// spawns a thread in the current process that starts executing `entry` with `arg`, see `Process::spawn_thread`
+/// returns the tid of the thread
+pub fn thread_spawn(entry: VirtAddr, arg: usize) -> Result<u64, ErrorStatus> {
+    scheduler().current_process().spawn_thread(entry, arg)
+}
+
+/// exits the current thread, the current process exits too if it was the last thread
+#[no_mangle]
+pub fn thread_exit(code: usize) -> ! {
+    let tid = scheduler().current_thread().tid;
+    scheduler().current_process().exit_thread(tid, code);
+    // enables interrupts if they were disabled to give control back to the scheduler
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        asm!("sti")
+    }
+    khalt()
+}
+
+/// waits for the thread `tid` of the current process to exit
+/// returns it's exit code after cleaning it up
+pub fn thread_join(tid: u64) -> Result<usize, ErrorStatus> {
+    if tid == scheduler().current_thread().tid {
+        return Err(ErrorStatus::InvaildPid);
+    }
+
+    let mut results = Ok(0);
+    EXIT_WAITERS.wait_until(|| match scheduler().current_process().reap_thread(tid) {
+        Ok(None) => false,
+        Ok(Some(exit_code)) => {
+            results = Ok(exit_code);
+            true
+        }
+        Err(err) => {
+            results = Err(err);
+            true
+        }
+    });
+
+    results
+}
+
+/// handles a page fault at `addr` in the current process's address space
+/// returns true if the fault was resolved and the faulting instruction can be retried
+pub fn handle_page_fault(addr: VirtAddr, write: bool) -> bool {
+    match scheduler().current_process().state {
+        ProcessState::Alive(ref state) => state.handle_page_fault(addr, write),
+        ProcessState::Zombie(_) => false,
+    }
+}
+
+/// terminates the current process because of an unrecoverable fault while accessing `addr`
+/// `at` and `stack_at` are the instruction and stack pointers of the faulting instruction
+pub fn thread_fault(addr: VirtAddr, at: VirtAddr, stack_at: VirtAddr) -> ! {
+    scheduler()
+        .current_process()
+        .terminate_faulted(addr, at, stack_at);
     // enables interrupts if they were disabled to give control back to the scheduler
     #[cfg(target_arch = "x86_64")]
     unsafe {
@@ -32,6 +104,21 @@ pub fn thread_exit(code: usize) {
     khalt()
 }
 
+/// blocks the current thread for at least `ms` milliseconds
+pub fn sleep(ms: u64) {
+    let wake_at = time::ticks().saturating_add(time::ms_to_ticks(ms));
+
+    while time::ticks() < wake_at {
+        without_interrupts(|| {
+            if time::ticks() < wake_at {
+                scheduler().sleep(wake_at);
+            }
+        });
+
+        thread_yeild();
+    }
+}
+
 #[no_mangle]
 pub fn thread_yeild() {
     #[cfg(target_arch = "x86_64")]
@@ -40,18 +127,12 @@ pub fn thread_yeild() {
     }
 }
 
-#[no_mangle]
-/// waits for `pid` to exit
-/// returns it's exit code after cleaning it up
-pub fn wait(pid: u64) -> usize {
-    // loops through the processes until it finds the process with `pid` as a zombie
-    loop {
+/// removes the process `pid` if it is a zombie
+/// returns it's exit code or None if it didn't exit yet, returns Err if there is no process with `pid`
+fn reap_process(pid: u64) -> Result<Option<usize>, ()> {
+    with_scheduler_lock(|| {
         let mut current = scheduler().head.as_mut();
-        let mut found = false;
 
-        // cycles through the processes one by one untils it finds the process with `pid`
-        // returns the exit code of the process if it's a zombie and cleans it up
-        // if it's not a zombie it will be caught by the next above loop
         loop {
             if current
                 .next
@@ -61,36 +142,49 @@ pub fn wait(pid: u64) -> usize {
                 // TODO: rethink returning only the exit code
                 // a bit of a hack to fight the borrow checker
                 let mut exit_code = None;
+                let process = current.next.as_ref().unwrap();
 
-                if let ProcessState::Zombie(ref state) = current.next.as_ref().unwrap().state {
-                    exit_code = Some(state.exit_code);
+                // a cpu may still be switching away from one of it's threads
+                if let ProcessState::Zombie(ref state) = process.state {
+                    if !process.threads.iter().any(|thread| thread.on_cpu) {
+                        exit_code = Some(state.exit_code);
+                    }
                 }
 
-                if let Some(exit_code) = exit_code {
+                if exit_code.is_some() {
                     // cleans up the process
                     current.next = current.next.as_mut().unwrap().next.take();
                     scheduler().processes_count -= 1;
-                    return exit_code;
                 }
 
-                found = true;
-                break;
+                return Ok(exit_code);
             }
 
             if let Some(ref mut process) = current.next {
                 current = process;
-                thread_yeild()
             } else {
-                break;
+                return Err(());
             }
         }
+    })
+}
+
+#[no_mangle]
+/// waits for `pid` to exit, blocking the current thread until it does
+/// returns it's exit code after cleaning it up, returns 0 if there is no process with `pid`
+pub fn wait(pid: u64) -> usize {
+    let mut exit_code = 0;
 
-        if !found {
-            return 0;
+    EXIT_WAITERS.wait_until(|| match reap_process(pid) {
+        Ok(None) => false,
+        Ok(Some(code)) => {
+            exit_code = code;
+            true
         }
+        Err(()) => true,
+    });
 
-        thread_yeild();
-    }
+    exit_code
 }
 
 bitflags! {
@@ -109,7 +203,7 @@ pub fn spawn(
     flags: SpawnFlags,
 ) -> Result<u64, ElfError> {
     let cwd = if flags.contains(SpawnFlags::CLONE_CWD) {
-        getcwd().to_string()
+        getcwd()
     } else {
         String::from("ram:/")
     };
@@ -136,39 +230,104 @@ pub fn spawn(
     Ok(pid)
 }
 
+/// duplicates the current process sharing it's memory copy-on-write, see `Process::fork`
+/// the child continues from the syscall the current process is executing with a successful result
+/// returns the pid of the child
+pub fn fork() -> Result<u64, ErrorStatus> {
+    #[cfg(target_arch = "x86_64")]
+    let context = CPUStatus::from_current_syscall(ErrorStatus::None as u64)
+        .ok_or(ErrorStatus::OperationNotSupported)?;
+
+    let process = scheduler().current_process().fork(context)?;
+    let pid = process.pid;
+
+    scheduler().add_process(process);
+    Ok(pid)
+}
+
+/// reads the whole file at `path` into a buffer
+pub(super) fn read_file(path: &str) -> FSResult<Vec<u8>> {
+    let file = open(path, OpenFlags::READ)?;
+
+    let read_all = || {
+        let mut stat = unsafe { DirEntry::zeroed() };
+        fstat(file, &mut stat)?;
+
+        if stat.kind != InodeType::File {
+            return Err(FSError::NotAFile);
+        }
+
+        let mut buffer = Vec::with_capacity(stat.size);
+        buffer.resize(stat.size, 0);
+        read(file, &mut buffer)?;
+        Ok(buffer)
+    };
+
+    let results = read_all();
+    close(file)?;
+    results
+}
+
 /// spawns an elf process from a path
 pub fn pspawn(name: &str, path: &str, argv: &[&str], flags: SpawnFlags) -> Result<u64, FSError> {
-    let file = open(path)?;
-
-    let mut stat = unsafe { DirEntry::zeroed() };
-    fstat(file, &mut stat)?;
+    let buffer = read_file(path)?;
+    spawn(name, &buffer, argv, flags).map_err(|_| FSError::NotExecuteable)
+}
 
-    if stat.kind != InodeType::File {
-        return Err(FSError::NotAFile);
+/// replaces the current process's image with the elf at `path`, see `Process::exec`
+/// only returns if it fails, `path` and `argv` are taken so they can be freed before the new image runs
+pub fn exec(path: String, argv: Vec<String>) -> ErrorStatus {
+    // only userspace processes run on the ring0 stack which is the only thing that survives an exec
+    #[cfg(target_arch = "x86_64")]
+    if CPUStatus::from_current_syscall(0).is_none() {
+        return ErrorStatus::OperationNotSupported;
     }
-    let mut buffer = Vec::with_capacity(stat.size);
-    buffer.resize(stat.size, 0);
-    read(file, &mut buffer).map_err(|e| e.into())?;
-    spawn(name, &buffer, argv, flags).map_err(|_| FSError::NotExecuteable)
+
+    let buffer = match read_file(&path) {
+        Ok(buffer) => buffer,
+        Err(err) => return err.into(),
+    };
+
+    let name = path.rsplit('/').next().unwrap_or(&path);
+    let args: Vec<&str> = argv.iter().map(|arg| arg.as_str()).collect();
+    let context = match Elf::new(&buffer)
+        .and_then(|elf| scheduler().current_process().exec(elf, name, &args))
+    {
+        Ok(context) => context,
+        Err(err) => return err.into(),
+    };
+
+    // nothing on this stack is dropped after the jump
+    drop(args);
+    drop(argv);
+    drop(path);
+    drop(buffer);
+    unsafe { restore_cpu_status(&context) };
+    unreachable!()
 }
 
+/// stores the canonical form of new_dir so the cwd never has `.` or `..` in it
 /// also ensures the cwd ends with /
 /// will only Err if new_dir doesn't exists or is not a directory
 #[no_mangle]
 pub fn chdir(new_dir: &str) -> FSResult<()> {
-    let new_dir = VFS_STRUCT.read().verify_path_dir(new_dir)?;
-    let cwd = &mut scheduler().current_process_state().current_dir;
-    *cwd = new_dir;
-    if !cwd.ends_with('/') {
-        cwd.push('/');
+    let mut new_dir = read_vfs().verify_path_dir(new_dir)?;
+    if !new_dir.ends_with('/') {
+        new_dir.push('/');
     }
 
+    *scheduler().current_process_state().current_dir.lock() = new_dir;
     Ok(())
 }
 
+/// returns a copy of the cwd, it can be changed by another thread once the lock is released
 #[no_mangle]
-pub fn getcwd<'a>() -> &'a str {
-    &scheduler().current_process_state().current_dir
+pub fn getcwd() -> String {
+    scheduler()
+        .current_process_state()
+        .current_dir
+        .lock()
+        .clone()
 }
 
 #[no_mangle]
@@ -178,29 +337,51 @@ pub fn pkill(pid: u64) -> Result<(), ()> {
         return Err(());
     }
 
-    let process = scheduler().find(pid).ok_or(())?;
-    let current_pid = scheduler().current_process().pid;
-
-    if process.ppid == current_pid || process.pid == current_pid {
-        process.terminate(1, current_pid);
-        return Ok(());
-    }
+    check_descendant(pid).map_err(|_| ())?;
 
-    // loops through parents and checks if one of the great-grandparents is the current process
-    let mut ppid = process.ppid;
+    let current_pid = scheduler().current_process().pid;
+    scheduler().terminate(pid, 1, current_pid);
+    Ok(())
+}
 
-    while ppid != 0 {
-        let process = scheduler().find(ppid).ok_or(())?;
+/// checks that the process `pid` exists and is the current process or one of it's descendants
+fn check_descendant(pid: u64) -> Result<(), ErrorStatus> {
+    let current_pid = scheduler().current_process().pid;
+    let mut ppid = scheduler()
+        .find_with(pid, |process| process.pid)
+        .ok_or(ErrorStatus::InvaildPid)?;
 
-        if process.pid == current_pid {
-            process.terminate(1, current_pid);
-            return Ok(());
+    while ppid != current_pid {
+        if ppid == 0 {
+            return Err(ErrorStatus::MissingPermissions);
         }
 
-        ppid = process.ppid;
+        ppid = scheduler()
+            .find_with(ppid, |process| process.ppid)
+            .ok_or(ErrorStatus::MissingPermissions)?;
     }
+    Ok(())
+}
+
+/// sets the nice value of the process `pid` to `nice` clamped between `NICE_MIN` and `NICE_MAX`
+/// the process has to be the current process or one of it's descendants
+pub fn setpriority(pid: u64, nice: isize) -> Result<(), ErrorStatus> {
+    check_descendant(pid)?;
 
-    Err(())
+    let nice = nice.clamp(NICE_MIN as isize, NICE_MAX as isize) as i8;
+    scheduler()
+        .find_with(pid, |process| process.nice = nice)
+        .ok_or(ErrorStatus::InvaildPid)
+}
+
+/// starts or stops logging the syscalls of the process `pid` to serial
+/// the process has to be the current process or one of it's descendants
+pub fn systrace(pid: u64, on: bool) -> Result<(), ErrorStatus> {
+    check_descendant(pid)?;
+
+    scheduler()
+        .find_with(pid, |process| process.traced = on)
+        .ok_or(ErrorStatus::InvaildPid)
 }
 
 #[no_mangle]
@@ -208,26 +389,28 @@ pub fn pkill(pid: u64) -> Result<(), ()> {
 /// collects `buffer.len()` processes
 /// if it didn't finish returns Err(())
 pub fn pcollect(info: &mut [ProcessInfo]) -> Result<(), ()> {
-    let mut current = &mut *scheduler().head;
-    let mut i = 1;
+    with_scheduler_lock(|| {
+        let mut current = &mut *scheduler().head;
+        let mut i = 1;
 
-    if 0 >= info.len() {
-        return Err(());
-    }
-
-    info[0] = current.info();
-
-    while let Some(ref mut process) = current.next {
-        if i >= info.len() {
+        if 0 >= info.len() {
             return Err(());
         }
 
-        info[i] = process.info();
+        info[0] = current.info();
 
-        current = &mut *process;
-        i += 1;
-    }
-    Ok(())
+        while let Some(ref mut process) = current.next {
+            if i >= info.len() {
+                return Err(());
+            }
+
+            info[i] = process.info();
+
+            current = &mut *process;
+            i += 1;
+        }
+        Ok(())
+    })
 }
 
 #[no_mangle]
@@ -237,16 +420,103 @@ pub fn pcollect(info: &mut [ProcessInfo]) -> Result<(), ()> {
 pub fn sbrk(amount: isize) -> *mut u8 {
     scheduler()
         .current_process_state()
-        .extend_data_by(amount)
+        .with_memory(|memory| memory.extend_data_by(amount))
         .unwrap_or(core::ptr::null_mut())
 }
-// TODO: lock? or should every resource handle it's own lock?
-pub fn get_resource(ri: usize) -> Option<&'static mut Resource> {
+/// called by every syscall when it is entered, the time before it is charged as userspace time if the caller is in userspace
+/// see `Scheduler::account`
+pub fn enter_syscall() {
+    #[cfg(target_arch = "x86_64")]
+    scheduler().account(crate::arch::threading::is_userspace_syscall());
+}
+
+/// called by every syscall that returns, the time it took is charged as kernel time
+pub fn leave_syscall() {
+    scheduler().account(false);
+}
+
+/// checks if the caller of the current syscall can access `len` bytes at `addr`, see `AliveProcessState::prepare_user_range`
+/// kernel processes can access anything while userspace processes can only access their own memory
+pub fn can_access(addr: VirtAddr, len: usize, write: bool) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    if !crate::arch::threading::is_userspace_syscall() {
+        return true;
+    }
+
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+
     scheduler()
         .current_process_state()
-        .resource_manager
-        .get_mut()
-        .get(ri)
+        .prepare_user_range(addr, end, write)
+}
+
+/// checks if `len` bytes at `addr` are already mapped in the memory the caller of the current syscall can access
+/// unlike `can_access` nothing is mapped so checking them has no side effects, use `copy_from_user` to read them
+pub fn is_user_range_mapped(addr: VirtAddr, len: usize) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    if !crate::arch::threading::is_userspace_syscall() {
+        return true;
+    }
+
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+
+    scheduler()
+        .current_process_state()
+        .is_user_range_mapped(addr, end)
+}
+
+/// maps `len` bytes of memory at `addr` (or anywhere if `addr` is 0) with `prot` protection, see `AddressSpace::mmap`
+/// if `flags` doesn't contain `MMapFlags::ANONYMOUS` the memory is a private copy of the file
+/// resource `ri` starting from `offset`
+/// returns the address the memory was mapped at
+pub fn mmap(
+    addr: VirtAddr,
+    len: usize,
+    prot: MMapProt,
+    flags: MMapFlags,
+    ri: usize,
+    offset: usize,
+) -> Result<VirtAddr, ErrorStatus> {
+    let backing = if flags.contains(MMapFlags::ANONYMOUS) {
+        VMABacking::Anonymous
+    } else {
+        let inode = with_resource(ri, |resource| match resource {
+            Resource::File(fd) => Some(fd.node.clone()),
+            _ => None,
+        })
+        .flatten()
+        .ok_or(ErrorStatus::InvaildResource)?;
+
+        // only inodes with a known size can be mapped
+        inode.size()?;
+        VMABacking::File { inode, offset }
+    };
+
+    scheduler().current_process_state().with_memory(|memory| {
+        memory.mmap(addr, len, prot, flags.contains(MMapFlags::FIXED), backing)
+    })
+}
+
+/// unmaps `len` bytes at `addr` that were mapped with `mmap`
+pub fn munmap(addr: VirtAddr, len: usize) -> Result<(), ErrorStatus> {
+    scheduler()
+        .current_process_state()
+        .with_memory(|memory| memory.munmap(addr, len))
+}
+
+/// runs `f` with the resources of the current process locked
+pub fn with_resources<R>(f: impl FnOnce(&mut ResourceManager) -> R) -> R {
+    f(&mut scheduler().current_process_state().resource_manager.lock())
+}
+
+/// runs `f` on the resource with index `ri` of the current process with it's resources locked
+/// returns `None` if `ri` is invaild
+pub fn with_resource<R>(ri: usize, f: impl FnOnce(&mut Resource) -> R) -> Option<R> {
+    with_resources(|resources| resources.get(ri).map(f))
 }
 
 pub fn add_resource(resource: Resource) -> usize {
@@ -257,7 +527,8 @@ pub fn add_resource(resource: Resource) -> usize {
         .add_resource(resource)
 }
 
-pub fn remove_resource(ri: usize) -> Result<(), ()> {
+/// removes the resource with index `ri` from the current process and returns it
+pub fn remove_resource(ri: usize) -> Result<Resource, ()> {
     scheduler()
         .current_process_state()
         .resource_manager
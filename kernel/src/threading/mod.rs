@@ -1,40 +1,121 @@
 pub mod expose;
 pub mod processes;
 pub mod resources;
+pub mod threads;
+pub mod vmas;
+pub mod waitqueue;
+
+/// the maximum number of threads a process can have at once
+/// each thread gets a slot with it's own stacks and thread local storage block, the main thread's slot is 0
+pub const MAX_THREADS: usize = 256;
+/// the stacks of the threads are placed one after the other separated by a guard page
+pub const STACK_STRIDE: usize = STACK_SIZE + PAGE_SIZE;
 
 pub const STACK_SIZE: usize = PAGE_SIZE * 6;
 pub const STACK_START: usize = 0x00007A3000000000;
-pub const STACK_END: usize = STACK_START + STACK_SIZE;
+pub const STACKS_END: usize = STACK_START + STACK_STRIDE * MAX_THREADS;
 
 pub const RING0_STACK_START: usize = 0x00007A0000000000;
-pub const RING0_STACK_END: usize = RING0_STACK_START + STACK_SIZE;
+pub const RING0_STACKS_END: usize = RING0_STACK_START + STACK_STRIDE * MAX_THREADS;
 
 pub const ENVIROMENT_START: usize = 0x00007E0000000000;
 pub const ARGV_START: usize = ENVIROMENT_START + 0xA000000000;
 pub const ARGV_SIZE: usize = PAGE_SIZE * 4;
 
-use core::{arch::asm, mem::MaybeUninit};
-use processes::{AliveProcessState, Process, ProcessFlags, ProcessState, ProcessStatus};
+/// where position independent executables are loaded
+pub const ELF_DYN_BASE: usize = 0x0000000000400000;
+/// the area the interpreter of dynamically linked executables is loaded in
+pub const INTERPRETER_START: usize = 0x00007C0000000000;
+pub const INTERPRETER_END: usize = INTERPRETER_START + 0x40000000;
+
+/// the area the thread local storage blocks of the threads are allocated in, each thread gets `TLS_SIZE` bytes
+pub const TLS_START: usize = 0x00007B0000000000;
+pub const TLS_SIZE: usize = PAGE_SIZE * 64;
+pub const TLS_END: usize = TLS_START + TLS_SIZE * MAX_THREADS;
+
+/// the nice values a process can have, the lower the nice value the more cpu time it's threads get
+pub const NICE_MIN: i8 = -20;
+pub const NICE_MAX: i8 = 19;
+
+/// the weight of the nice value 0
+const NICE_0_WEIGHT: u64 = 1024;
+/// the weights of the nice values from `NICE_MIN` to `NICE_MAX`, each nice level gets ~1.25 times the cpu time of the next one
+const NICE_WEIGHTS: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// how far behind the least virtual runtime threads that were blocked or just spawned are placed
+/// so they run right after waking up without being able to take over the cpu by sleeping for long
+const WAKEUP_CREDIT: u64 = 5 * NANOS_PER_TICK;
+
+/// the weight of `nice`, threads get cpu time in proportion to the weights of their processes
+pub const fn nice_weight(nice: i8) -> u64 {
+    NICE_WEIGHTS[(nice - NICE_MIN) as usize]
+}
+
+/// the start of the stack of the thread in `slot`
+pub const fn stack_start(slot: usize) -> VirtAddr {
+    STACK_START + STACK_STRIDE * slot
+}
+
+/// the start of the ring0 stack of the thread in `slot`
+pub const fn ring0_stack_start(slot: usize) -> VirtAddr {
+    RING0_STACK_START + STACK_STRIDE * slot
+}
+
+/// the start of the thread local storage block of the thread in `slot`
+pub const fn tls_start(slot: usize) -> VirtAddr {
+    TLS_START + TLS_SIZE * slot
+}
+
+use core::{
+    arch::asm,
+    mem::MaybeUninit,
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use processes::{AliveProcessState, Process, ProcessFlags, ProcessState, Zombify};
+use threads::{Thread, ThreadStatus};
+use waitqueue::WaitQueue;
 
-use alloc::{boxed::Box, string::String};
+use alloc::{boxed::Box, collections::binary_heap::BinaryHeap, string::String, vec};
+use core::cmp::Reverse;
+use spin::Mutex;
 
 use crate::{
-    arch::threading::{restore_cpu_status, CPUStatus},
+    arch::{
+        smp::{self, cpu_local, cpu_locals},
+        threading::{fpu, restore_cpu_status, without_interrupts, CPUStatus},
+        tsc,
+    },
     debug, hddm,
     memory::{
+        align_up, copy_from_userspace, copy_to_userspace,
         frame_allocator::Frame,
         paging::{current_root_table, EntryFlags, MapToError, Page, PageTable, PAGE_SIZE},
     },
-    scheduler, SCHEDULER,
+    scheduler,
+    time::{self, NANOS_PER_TICK},
+    utils::elf::{ElfError, TLSTemplate},
+    VirtAddr, SCHEDULER,
 };
 
+/// threads waiting for a process or a thread to exit, woken up whenever one exits
+pub static EXIT_WAITERS: WaitQueue = WaitQueue::new();
+
 /// allocates and maps an area starting from `$start` with size `$size` and returns `Result<(), MapToError>` in `$page_table`
 macro_rules! alloc_map {
-    ($page_table: expr, $start: ident, $size: ident) => {
+    ($page_table: expr, $start: expr, $size: ident) => {
+        alloc_map!($page_table, $start, $size, EntryFlags::USER_ACCESSIBLE);
+    };
+    ($page_table: expr, $start: expr, $size: ident, $flags: expr) => {
         let page_table = $page_table;
+        let start: VirtAddr = $start;
 
         const PAGES: usize = $size / PAGE_SIZE;
-        const END: usize = $start + $size;
+        let end = start + $size;
 
         // allocating frames
         let mut frames: [Frame; PAGES] = [Frame::containing_address(0); PAGES];
@@ -51,8 +132,8 @@ macro_rules! alloc_map {
             byte_array.fill(0);
         }
 
-        let start_page = Page::containing_address($start);
-        let end_page = Page::containing_address(END);
+        let start_page = Page::containing_address(start);
+        let end_page = Page::containing_address(end);
 
         let iter = Page::iter_pages(start_page, end_page);
 
@@ -60,7 +141,7 @@ macro_rules! alloc_map {
             page_table.map_to(
                 page,
                 frames[i],
-                EntryFlags::WRITABLE | EntryFlags::USER_ACCESSIBLE | EntryFlags::PRESENT,
+                EntryFlags::WRITABLE | EntryFlags::PRESENT | EntryFlags::NO_EXECUTE | $flags,
             )?;
         }
 
@@ -68,9 +149,9 @@ macro_rules! alloc_map {
     };
 }
 
-/// allocates and maps a stack to page_table
-pub fn alloc_stack(page_table: &mut PageTable) -> Result<(), MapToError> {
-    alloc_map!(page_table, STACK_START, STACK_SIZE);
+/// allocates and maps the stack of the thread in `slot` to page_table
+pub fn alloc_stack(page_table: &mut PageTable, slot: usize) -> Result<(), MapToError> {
+    alloc_map!(page_table, stack_start(slot), STACK_SIZE);
 }
 
 /// allocates and maps the argv area to `page_table`
@@ -78,32 +159,228 @@ pub fn alloc_argv(page_table: &mut PageTable) -> Result<(), MapToError> {
     alloc_map!(page_table, ARGV_START, ARGV_SIZE);
 }
 
-/// allocates and maps a ring0 stack to page_table
-pub fn alloc_ring0_stack(page_table: &mut PageTable) -> Result<(), MapToError> {
-    alloc_map!(page_table, RING0_STACK_START, STACK_SIZE);
+/// allocates and maps the ring0 stack of the thread in `slot` to page_table
+/// it isn't accessible by userspace and so with SMAP the kernel can use it while userspace is inaccessible
+pub fn alloc_ring0_stack(page_table: &mut PageTable, slot: usize) -> Result<(), MapToError> {
+    alloc_map!(
+        page_table,
+        ring0_stack_start(slot),
+        STACK_SIZE,
+        EntryFlags::empty()
+    );
+}
+
+/// allocates and maps the thread local storage block of the thread in `slot` to `page_table`
+/// the block is initialized from `template` which must be loaded in `page_table`
+/// returns the thread pointer, it points to the end of the block which stores a pointer to itself
+pub fn alloc_tls(
+    page_table: &mut PageTable,
+    template: &TLSTemplate,
+    slot: usize,
+) -> Result<VirtAddr, ElfError> {
+    let start = tls_start(slot);
+    // the block is followed by the thread control block which is only the self pointer for now
+    let thread_pointer = start + align_up(template.size, template.align);
+    let end = thread_pointer + size_of::<usize>();
+
+    if template.align > TLS_SIZE || end > start + TLS_SIZE {
+        return Err(ElfError::TooLarge);
+    }
+
+    let iter = Page::iter_pages(
+        Page::containing_address(start),
+        Page::containing_address(align_up(end, PAGE_SIZE)),
+    );
+
+    for page in iter {
+        let frame = crate::memory::frame_allocator::allocate_frame().ok_or(ElfError::MapToError)?;
+
+        let byte_array = (frame.start_address | hddm()) as *mut u8;
+        let byte_array = unsafe { core::slice::from_raw_parts_mut(byte_array, PAGE_SIZE) };
+        byte_array.fill(0);
+
+        page_table
+            .map_to(
+                page,
+                frame,
+                EntryFlags::WRITABLE
+                    | EntryFlags::USER_ACCESSIBLE
+                    | EntryFlags::PRESENT
+                    | EntryFlags::NO_EXECUTE,
+            )
+            .map_err(|_| ElfError::MapToError)?;
+    }
+
+    // variant II layout, the data is right before the thread pointer
+    let mut data = vec![0u8; template.file_size];
+    copy_from_userspace(page_table, template.vaddr, &mut data);
+
+    copy_to_userspace(page_table, start, &data);
+    copy_to_userspace(page_table, thread_pointer, &thread_pointer.to_ne_bytes());
+    Ok(thread_pointer)
+}
+
+/// unmaps the stacks and the thread local storage block of the thread in `slot` from `page_table`
+/// must not be called on the slot of the running thread
+pub fn free_thread_slot(page_table: &mut PageTable, slot: usize) {
+    let areas = [
+        (stack_start(slot), STACK_SIZE),
+        (ring0_stack_start(slot), STACK_SIZE),
+        (tls_start(slot), TLS_SIZE),
+    ];
+
+    for (start, size) in areas {
+        let iter = Page::iter_pages(
+            Page::containing_address(start),
+            Page::containing_address(start + size),
+        );
+
+        for page in iter {
+            page_table.unmap(page);
+        }
+    }
 }
+/// what a cpu is running, kept in the cpu's `CPULocal` block
+pub struct CPUState {
+    /// raw pointers for peformance, we are ring0 we need the lowest stuff
+    current_process: *mut Process,
+    /// the running thread, always belongs to `current_process`
+    current_thread: *mut Thread,
+    /// a thread of the kernel process that only halts, it runs when there is nothing else to run on the cpu
+    idle_thread: *mut Thread,
+    /// the tick the current thread was switched to at
+    switched_at: u64,
+    /// the tsc value the current process was last charged for it's time at, see `Scheduler::account`
+    accounted_at: u64,
+    /// set if the last switch was away from a thread that exited, see `Scheduler::after_switch`
+    left_zombie: bool,
+    /// the address space of a zombie process the last switch was away from, freed by `Scheduler::after_switch`
+    retired_page_table: *mut PageTable,
+    /// the number of times the cpu switched to a different thread
+    context_switches: u64,
+}
+
+impl CPUState {
+    pub const fn new() -> Self {
+        Self {
+            current_process: null_mut(),
+            current_thread: null_mut(),
+            idle_thread: null_mut(),
+            switched_at: 0,
+            accounted_at: 0,
+            left_zombie: false,
+            retired_page_table: null_mut(),
+            context_switches: 0,
+        }
+    }
+}
+
+/// held while a thread is picked to run and while the processes or the threads of a process are added or removed
+/// it is always taken with interrupts disabled, so a cpu holding it cannot be interrupted into `Scheduler::switch`
+static SCHEDULER_LOCK: Mutex<()> = Mutex::new(());
+/// set once the scheduler is initialized, the application processors wait for it before running threads
+static SCHEDULER_READY: AtomicBool = AtomicBool::new(false);
+
+/// runs `f` while holding the scheduler lock with interrupts disabled
+pub fn with_scheduler_lock<R>(f: impl FnOnce() -> R) -> R {
+    without_interrupts(|| {
+        let _guard = smp::lock(&SCHEDULER_LOCK);
+        f()
+    })
+}
+
 pub struct Scheduler {
     pub head: Box<Process>,
-    /// raw pointers for peformance, we are ring0 we need the lowest stuff
-    pub current_process: *mut Process,
-    pub next_pid: u64,
+    /// the sleeping threads as (the tick they wake up at, pid, tid), the earliest first
+    sleepers: BinaryHeap<Reverse<(u64, u64, u64)>>,
+    /// the least virtual runtime of the threads that are ready to run, it never goes backwards
+    min_vruntime: u64,
+    pub next_pid: AtomicU64,
     pub processes_count: usize,
 }
 
 impl Scheduler {
+    /// the state of the current cpu
+    #[inline(always)]
+    fn cpu(&self) -> *mut CPUState {
+        &mut cpu_local().scheduler
+    }
+
+    /// charges the process running on this cpu for the time since it was last charged
+    /// as userspace time if `userspace` and as kernel time otherwise, the idle thread's time isn't charged
+    /// called whenever the cpu enters or leaves the kernel for a syscall and on every switch
+    /// so the time is split where the mode changed, interrupts must be disabled
+    pub fn account(&mut self, userspace: bool) {
+        let cpu = self.cpu();
+        let now = tsc::read();
+
+        unsafe {
+            let elapsed = now.wrapping_sub((*cpu).accounted_at);
+            (*cpu).accounted_at = now;
+
+            if !(*(*cpu).current_thread).idle {
+                (*(*cpu).current_process).account(tsc::to_nanoseconds(elapsed), userspace);
+            }
+        }
+    }
+
+    /// the number of context switches done by every cpu since boot
+    pub fn context_switches(&self) -> u64 {
+        cpu_locals()
+            .iter()
+            .map(|local| local.scheduler.context_switches)
+            .sum()
+    }
+
+    /// returns true if the current cpu started running threads
+    #[inline(always)]
+    pub fn running(&self) -> bool {
+        unsafe { !(*self.cpu()).current_process.is_null() }
+    }
+
     #[inline(always)]
     pub fn current_process(&self) -> &mut Process {
-        unsafe { &mut *self.current_process }
+        unsafe { &mut *(*self.cpu()).current_process }
     }
 
     #[inline(always)]
-    pub fn current_process_state(&self) -> &mut AliveProcessState {
-        if let ProcessState::Alive(ref mut state) = self.current_process().state {
+    pub fn current_thread(&self) -> &mut Thread {
+        unsafe { &mut *(*self.cpu()).current_thread }
+    }
+
+    /// the state of the current process, it is shared by all of it's threads so anything mutable in it is behind a lock
+    #[inline(always)]
+    pub fn current_process_state(&self) -> &AliveProcessState {
+        if let ProcessState::Alive(ref state) = self.current_process().state {
             return state;
         } else {
             panic!("current process is not alive");
         }
     }
+
+    /// allocates a new pid
+    #[inline]
+    pub fn alloc_pid(&self) -> u64 {
+        self.next_pid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// spawns the idle thread of the current cpu in the kernel process and makes it the running thread
+    /// must be called with the scheduler lock held, returns the context to start running it from
+    unsafe fn start_idle_thread(&mut self) -> CPUStatus {
+        let idle: *mut Thread = self.head.add_thread(crate::khalt as usize, 0).unwrap();
+        (*idle).idle = true;
+        (*idle).on_cpu = true;
+        (*idle).status = ThreadStatus::Running;
+
+        let cpu = self.cpu();
+        (*cpu).idle_thread = idle;
+        (*cpu).current_thread = idle;
+        (*cpu).current_process = &mut *self.head;
+        (*cpu).switched_at = time::ticks();
+        (*cpu).accounted_at = tsc::read();
+        (*idle).context
+    }
+
     #[inline]
     /// inits the scheduler
     /// jumps to `function` after initing!
@@ -111,7 +388,7 @@ impl Scheduler {
         debug!(Scheduler, "initing ...");
         asm!("cli");
         let page_table_addr = current_root_table() as *mut PageTable as usize - hddm();
-        let mut process = Box::new(
+        let process = Box::new(
             Process::new(
                 function,
                 0,
@@ -127,57 +404,277 @@ impl Scheduler {
         );
 
         let this = Self {
-            current_process: &mut *process,
             head: process,
-            next_pid: 1,
+            sleepers: BinaryHeap::new(),
+            min_vruntime: 0,
+            next_pid: AtomicU64::new(1),
             processes_count: 1,
         };
         unsafe {
             (*SCHEDULER.0.get()) = MaybeUninit::new(this);
         }
 
-        let context = scheduler().current_process().context;
+        // the bootstrap processor starts with the main thread of the kernel process instead of it's idle thread
+        let context = with_scheduler_lock(|| {
+            scheduler().start_idle_thread();
+            scheduler().current_thread().status = ThreadStatus::Waiting;
+            scheduler().current_thread().on_cpu = false;
+
+            let main = &mut *scheduler().head.threads[0];
+            main.status = ThreadStatus::Running;
+            main.on_cpu = true;
+            (*scheduler().cpu()).current_thread = main;
+            main.context
+        });
+
+        SCHEDULER_READY.store(true, Ordering::Release);
         restore_cpu_status(&context)
     }
 
-    /// context switches into next process, takes current context outputs new context
+    /// starts running threads on the current cpu beginning with a new idle thread
+    /// called by the application processors once they are set up, waits for the scheduler to be initialized first
+    pub unsafe fn start_cpu() -> ! {
+        while !SCHEDULER_READY.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+
+        let context = with_scheduler_lock(|| scheduler().start_idle_thread());
+        restore_cpu_status(&context);
+        unreachable!()
+    }
+
+    /// context switches into next thread, takes current context outputs new context
+    /// the current thread is charged for the time it ran then the ready thread with the least virtual runtime is picked
+    /// switches to the idle thread if every other thread is blocked
     pub unsafe fn switch(&mut self, context: CPUStatus) -> CPUStatus {
         unsafe { asm!("cli") }
+        let _guard = smp::lock(&SCHEDULER_LOCK);
 
-        self.current_process().context = context;
+        let cpu = self.cpu();
+        let current = (*cpu).current_thread;
+        let cpu_bit = 1 << smp::cpu_id();
 
-        if self.current_process().status != ProcessStatus::Zombie {
-            self.current_process().status = ProcessStatus::Waiting;
+        if let ProcessState::Alive(ref state) = (*(*cpu).current_process).state {
+            state.cpus.fetch_and(!cpu_bit, Ordering::SeqCst);
         }
 
-        loop {
-            if self.current_process().next.is_some() {
-                self.current_process = &mut **(*self.current_process).next.as_mut().unwrap();
-            } else {
-                self.current_process = &mut *self.head;
+        (*current).context = context;
+        (*current).on_cpu = false;
+        fpu::save(&mut (*current).fpu);
+
+        if (*current).status == ThreadStatus::Running {
+            (*current).status = ThreadStatus::Waiting;
+        }
+
+        if (*current).status == ThreadStatus::Zombie {
+            // the address space of the thread may be freed as soon as the lock is released
+            // so the cpu switches to the kernel's until it restores the next thread
+            let kernel_page_table = self.head.threads[0].context.cr3;
+            asm!("mov cr3, {}", in(reg) kernel_page_table, options(nostack));
+            (*cpu).left_zombie = true;
+
+            let process = &mut *(*cpu).current_process;
+            let running = process.threads.iter().any(|thread| thread.on_cpu);
+
+            if let ProcessState::Zombie(ref mut state) = process.state {
+                if !running {
+                    (*cpu).retired_page_table =
+                        core::mem::replace(&mut state.page_table, null_mut());
+                }
             }
+        }
 
-            if self.current_process().status == ProcessStatus::Waiting {
-                (*self.current_process).status = ProcessStatus::Running;
-                break;
+        let now = time::ticks();
+        let ran = now - (*cpu).switched_at;
+        (*cpu).switched_at = now;
+
+        self.account(context.is_userspace());
+
+        let weight = nice_weight((*(*cpu).current_process).nice);
+        (*current).vruntime += ran * NANOS_PER_TICK * NICE_0_WEIGHT / weight;
+
+        let mut next_index = (*(*cpu).current_process)
+            .threads
+            .iter()
+            .position(|thread| core::ptr::eq(&**thread, current))
+            .map_or(0, |index| index + 1);
+
+        let least = self.min_vruntime.saturating_sub(WAKEUP_CREDIT);
+        let mut process = (*cpu).current_process;
+        let mut next: Option<(*mut Process, *mut Thread)> = None;
+
+        // the search starts right after the current thread and on ties the first thread found wins so threads
+        // with the same virtual runtime take turns, the current process is visited twice to go through the threads before the current thread too
+        // threads running on other cpus are skipped
+        for _ in 0..=self.processes_count {
+            for thread in (*process).threads[next_index..].iter_mut() {
+                if thread.status != ThreadStatus::Waiting || thread.idle || thread.on_cpu {
+                    continue;
+                }
+
+                thread.vruntime = thread.vruntime.max(least);
+                match next {
+                    Some((_, best)) if (*best).vruntime <= thread.vruntime => {}
+                    _ => next = Some((process, &mut **thread)),
+                }
             }
+
+            process = match (*process).next {
+                Some(ref mut process) => &mut **process,
+                None => &mut *self.head,
+            };
+            next_index = 0;
+        }
+
+        if let Some((process, thread)) = next {
+            if !core::ptr::eq(thread, current) {
+                (*(*cpu).current_process).context_switches += 1;
+                (*cpu).context_switches += 1;
+            }
+
+            self.min_vruntime = self.min_vruntime.max((*thread).vruntime);
+            (*cpu).current_process = process;
+            (*cpu).current_thread = thread;
+        } else {
+            (*cpu).current_process = &mut *self.head;
+            (*cpu).current_thread = (*cpu).idle_thread;
+        }
+
+        // the cpu is in the mask before it loads the page table of the process so it cannot miss a shootdown
+        if let ProcessState::Alive(ref state) = (*(*cpu).current_process).state {
+            state.cpus.fetch_or(cpu_bit, Ordering::SeqCst);
+        }
+
+        let thread = (*cpu).current_thread;
+        (*thread).status = ThreadStatus::Running;
+        (*thread).on_cpu = true;
+        return (*thread).context;
+    }
+
+    /// called right after `switch` once the scheduler lock is released
+    /// frees the address space the switch retired, and wakes up the threads waiting for exits
+    /// if the current cpu just left a thread that exited because a thread cannot be reaped while it is still on a cpu
+    pub fn after_switch(&self) {
+        let cpu = self.cpu();
+        unsafe {
+            if !(*cpu).retired_page_table.is_null() {
+                (*(*cpu).retired_page_table).free(4);
+                (*cpu).retired_page_table = null_mut();
+            }
+
+            if (*cpu).left_zombie {
+                (*cpu).left_zombie = false;
+                EXIT_WAITERS.wake_all();
+            }
+        }
+    }
+
+    /// wakes up the blocked thread `tid` of the process `pid`, does nothing if it isn't blocked
+    pub fn wake(&mut self, pid: u64, tid: u64) {
+        with_scheduler_lock(|| self.wake_locked(pid, tid))
+    }
+
+    fn wake_locked(&mut self, pid: u64, tid: u64) {
+        let Some(process) = self.find_locked(pid) else {
+            return;
+        };
+
+        if let Some(thread) = process
+            .threads
+            .iter_mut()
+            .find(|thread| thread.tid == tid && thread.status == ThreadStatus::Blocked)
+        {
+            thread.status = ThreadStatus::Waiting;
         }
+    }
+
+    /// blocks the current thread until the tick `wake_at`, the current thread has to yield afterwards
+    /// must be called with interrupts disabled
+    pub fn sleep(&mut self, wake_at: u64) {
+        let pid = self.current_process().pid;
+        let tid = self.current_thread().tid;
+
+        with_scheduler_lock(|| {
+            // a thread terminated by another cpu stays a zombie
+            if self.current_thread().status == ThreadStatus::Running {
+                self.current_thread().status = ThreadStatus::Blocked;
+                self.sleepers.push(Reverse((wake_at, pid, tid)));
+            }
+        })
+    }
+
+    /// wakes up the sleeping threads whose sleep ends at or before the tick `now`
+    pub fn wake_sleepers(&mut self, now: u64) {
+        with_scheduler_lock(|| {
+            while let Some(Reverse((wake_at, pid, tid))) = self.sleepers.peek().copied() {
+                if wake_at > now {
+                    break;
+                }
 
-        return (*self.current_process).context;
+                self.sleepers.pop();
+                self.wake_locked(pid, tid);
+            }
+        })
     }
 
     /// appends a process to the end of the scheduler head
     pub fn add_process(&mut self, process: Process) {
-        let mut current = &mut *self.head;
-        while let Some(ref mut process) = current.next {
-            current = &mut **process;
-        }
+        let process = Box::new(process);
 
-        current.next = Some(Box::new(process));
-        self.processes_count += 1;
+        with_scheduler_lock(|| {
+            let mut current = &mut *self.head;
+            while let Some(ref mut process) = current.next {
+                current = &mut **process;
+            }
+
+            current.next = Some(process);
+            self.processes_count += 1;
+        })
+    }
+
+    /// runs `f` on the process `pid` with the scheduler lock held so it cannot be reaped while `f` uses it
+    /// returns None if there is no process with `pid`
+    pub fn find_with<R>(&mut self, pid: u64, f: impl FnOnce(&mut Process) -> R) -> Option<R> {
+        with_scheduler_lock(|| self.find_locked(pid).map(f))
     }
 
-    pub fn find(&mut self, pid: u64) -> Option<&mut Process> {
+    /// terminates the process `pid` with all of it's threads, does nothing if it is already a zombie
+    /// waits for the threads of the process running on other cpus to switch away before freeing it's state
+    /// also moves the parentership of the process (it's children) to it's parent
+    /// returns false if there is no process with `pid`
+    pub fn terminate(&mut self, pid: u64, exit_code: usize, terminator: u64) -> bool {
+        let (state, in_use, ppid) = loop {
+            let zombified = self.find_with(pid, |process| {
+                // the current thread was terminated by another cpu which waits for it to switch away
+                if scheduler().current_thread().status == ThreadStatus::Zombie {
+                    return None;
+                }
+
+                Some((process.zombify(exit_code, terminator), process.ppid))
+            });
+
+            match zombified {
+                None => return false,
+                Some(None) | Some(Some((Zombify::Dead, _))) => return true,
+                Some(Some((Zombify::Running, _))) => expose::thread_yeild(),
+                Some(Some((Zombify::Done(state, in_use), ppid))) => break (state, in_use, ppid),
+            }
+        };
+
+        let last_resource_id = state.release(in_use);
+        self.find_with(pid, |process| {
+            if let ProcessState::Zombie(ref mut zombie) = process.state {
+                zombie.last_resource_id = last_resource_id;
+            }
+        });
+
+        self.move_parentership(pid, ppid);
+        EXIT_WAITERS.wake_all();
+        debug!(Process, "process with pid {} TERMINATED ...", pid);
+        true
+    }
+
+    fn find_locked(&mut self, pid: u64) -> Option<&mut Process> {
         let mut current = &mut *self.head;
         if current.pid == pid {
             return Some(current);
@@ -198,13 +695,15 @@ impl Scheduler {
 
     /// moves all the parentership of processes with parent `ppid` to `pid`
     pub fn move_parentership(&mut self, pid: u64, ppid: u64) {
-        let mut current = &mut *self.head;
-        while let Some(ref mut process) = current.next {
-            if process.ppid == ppid {
-                process.ppid = pid;
+        with_scheduler_lock(|| {
+            let mut current = &mut *self.head;
+            while let Some(ref mut process) = current.next {
+                if process.ppid == ppid {
+                    process.ppid = pid;
+                }
+
+                current = &mut **process;
             }
-
-            current = &mut **process;
-        }
+        })
     }
 }
@@ -1,19 +1,40 @@
-use core::slice;
+use core::{
+    ptr::null_mut,
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use super::resources::ResourceManager;
-use super::{ARGV_START, STACK_END};
-
-use crate::memory::{align_up, copy_to_userspace, frame_allocator};
-use crate::utils::elf::{Elf, ElfError};
-use crate::{arch, debug, hddm, scheduler, PhysAddr};
-
-use crate::memory::paging::{self, EntryFlags, MapToError, Page, PAGE_SIZE};
+use super::threads::{Thread, ThreadStatus};
+use super::vmas::{MMapProt, VMABacking, VMAs, VMA};
+use super::{
+    ring0_stack_start, stack_start, ARGV_SIZE, ARGV_START, ELF_DYN_BASE, INTERPRETER_END,
+    INTERPRETER_START, MAX_THREADS, RING0_STACKS_END, RING0_STACK_START, STACKS_END, STACK_SIZE,
+    STACK_START, TLS_END, TLS_START,
+};
+
+use super::with_scheduler_lock;
+use crate::memory::frame_allocator::{self, Frame};
+use crate::memory::{align_up, checked_align_up, copy_to_userspace, sorcery};
+use crate::utils::elf::{Elf, ElfError, ElfType, LoadedElf, TLSTemplate};
+use crate::{arch, debug, hddm, scheduler, time, PhysAddr, VirtAddr};
+
+use crate::memory::paging::{self, EntryFlags, MapToError, Page, PAGE_SIZE, USERSPACE_END};
+use crate::utils::errors::ErrorStatus;
 use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 use bitflags::bitflags;
 use spin::Mutex;
 
-use crate::{arch::threading::CPUStatus, memory::paging::PageTable};
+use crate::{
+    arch::{
+        smp,
+        threading::{fpu, without_interrupts, CPUStatus},
+    },
+    memory::paging::PageTable,
+};
 
 bitflags! {
     #[repr(C)]
@@ -29,103 +50,508 @@ pub enum ProcessStatus {
     Waiting,
     Running,
     Zombie,
+    /// all of the threads of the process are blocked
+    Blocked,
 }
 
-pub struct AliveProcessState {
-    root_page_table: *mut PageTable,
-    pub(super) resource_manager: Mutex<ResourceManager>,
-    data_pages: usize,
-    pub(super) current_dir: String,
+/// what `AddressSpace::handle_page_fault` did with a fault
+pub enum PageFault {
+    Resolved,
+    Invaild,
+    /// the page belongs to a file backed area and has to be read from the file before it is mapped
+    ReadFile(VMA),
+}
 
+impl From<bool> for PageFault {
+    fn from(resolved: bool) -> Self {
+        if resolved {
+            Self::Resolved
+        } else {
+            Self::Invaild
+        }
+    }
+}
+
+/// the memory of a process shared by all of it's threads, see `AliveProcessState::with_memory`
+pub struct AddressSpace {
+    root_page_table: *mut PageTable,
     data_start: usize,
     data_break: usize,
+    vmas: VMAs,
+    /// the thread local storage template of the loaded image, every thread gets a copy of it
+    tls: Option<TLSTemplate>,
+    /// set when a page was unmapped or made read-only, the other cpus running the process have to flush their TLB
+    /// before the address space is unlocked, see `AliveProcessState::with_memory`
+    stale: bool,
+    /// the frames of the pages unmapped since the address space was locked
+    /// they are released once no cpu can access them through it's TLB anymore
+    unmapped: Vec<Frame>,
+}
+
+pub struct AliveProcessState {
+    memory: Mutex<AddressSpace>,
+    /// a mask of the cpus running a thread of the process, kept by `Scheduler::switch`
+    pub(super) cpus: AtomicU64,
+    pub(super) resource_manager: Mutex<ResourceManager>,
+    /// the canonical path of the current working directory, always ends with `/`
+    pub(super) current_dir: Mutex<String>,
 }
 
 impl AliveProcessState {
     pub fn new(current_dir: String, root_page_table_addr: PhysAddr, data_break: usize) -> Self {
         let data_break = align_up(data_break, PAGE_SIZE);
         AliveProcessState {
-            root_page_table: (root_page_table_addr | hddm()) as *mut PageTable,
+            memory: Mutex::new(AddressSpace {
+                root_page_table: (root_page_table_addr | hddm()) as *mut PageTable,
+                data_break,
+                data_start: data_break,
+                vmas: VMAs::new(),
+                tls: None,
+                stale: false,
+                unmapped: Vec::new(),
+            }),
+            cpus: AtomicU64::new(0),
             resource_manager: Mutex::new(ResourceManager::new()),
-            current_dir,
+            current_dir: Mutex::new(current_dir),
+        }
+    }
 
-            data_pages: 0,
-            data_break,
-            data_start: data_break,
+    /// attempts to resolve a page fault caused by an access to `addr`, see `AddressSpace::handle_page_fault`
+    /// pages of file backed areas are read with the address space unlocked
+    /// so a fault while the inode is locked, or a read that faults, doesn't deadlock on the address space lock
+    /// returns false if the access is invaild and the fault cannot be recovered from
+    pub fn handle_page_fault(&self, addr: VirtAddr, write: bool) -> bool {
+        let vma = match self.with_memory(|memory| memory.handle_page_fault(addr, write)) {
+            PageFault::Resolved => return true,
+            PageFault::Invaild => return false,
+            PageFault::ReadFile(vma) => vma,
+        };
+
+        // the inode locks are taken with interrupts disabled everywhere else, a thread switched away from while holding one could deadlock the cpu
+        let page = Page::containing_address(addr);
+        let Some(content) = without_interrupts(|| vma.read_page(page)) else {
+            return false;
+        };
+
+        self.with_memory(|memory| memory.map_file_page(page, &vma, &content))
+    }
+
+    /// returns true if every page in `start`..`end` is already mapped and accessible by userspace
+    /// unlike `prepare_user_range` nothing is mapped, lazily mapped pages that weren't touched yet are not mapped
+    pub fn is_user_range_mapped(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        if start > end || end > USERSPACE_END {
+            return false;
         }
+
+        self.with_memory(|memory| {
+            Page::iter_pages(
+                Page::containing_address(start),
+                Page::containing_address(align_up(end, PAGE_SIZE)),
+            )
+            .all(|page| {
+                memory
+                    .mapped_flags(page)
+                    .is_some_and(|flags| flags.contains(EntryFlags::USER_ACCESSIBLE))
+            })
+        })
     }
 
-    #[inline(always)]
-    fn data_break_actual(&self) -> usize {
-        self.data_start + PAGE_SIZE * self.data_pages
+    /// makes sure every page in `start`..`end` is mapped and accessible by userspace (and writable if `write`)
+    /// maps lazily mapped pages and copies copy-on-write pages ahead of time so the kernel doesn't fault on them
+    /// returns false if any of the pages cannot be accessed
+    pub fn prepare_user_range(&self, start: VirtAddr, end: VirtAddr, write: bool) -> bool {
+        if start > end || end > USERSPACE_END {
+            return false;
+        }
+
+        if start == end {
+            return true;
+        }
+
+        let mut iter = Page::iter_pages(
+            Page::containing_address(start),
+            Page::containing_address(align_up(end, PAGE_SIZE)),
+        )
+        .peekable();
+
+        // a page is only skipped once it is accessible, the fault may have been retried without mapping it
+        while let Some(page) = iter.peek().copied() {
+            match self.with_memory(|memory| memory.mapped_flags(page)) {
+                Some(flags) if !flags.contains(EntryFlags::USER_ACCESSIBLE) => return false,
+                Some(flags) if !write || flags.contains(EntryFlags::WRITABLE) => {
+                    iter.next();
+                }
+                _ => {
+                    if !self.handle_page_fault(page.start_address, write) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
     }
 
-    fn page_extend_data(&mut self) -> Result<(), MapToError> {
-        let page_end = self.data_break_actual();
-        let new_page = Page::containing_address(page_end);
+    /// runs `f` with the address space of the process locked
+    /// interrupts are disabled meanwhile so the lock is never held by a thread that was switched away from
+    /// if `f` unmapped pages or made them read-only the TLB of the other cpus running the process is flushed before it is unlocked
+    pub fn with_memory<R>(&self, f: impl FnOnce(&mut AddressSpace) -> R) -> R {
+        without_interrupts(|| {
+            let mut memory = smp::lock(&self.memory);
+            let result = f(&mut memory);
 
-        let frame = frame_allocator::allocate_frame().ok_or(MapToError::FrameAllocationFailed)?;
+            if memory.stale {
+                smp::shootdown(self.cpus.load(Ordering::SeqCst));
+                memory.stale = false;
 
-        unsafe {
-            (*self.root_page_table).map_to(
-                new_page,
-                frame,
-                EntryFlags::WRITABLE | EntryFlags::USER_ACCESSIBLE | EntryFlags::PRESENT,
-            )?
+                for frame in memory.unmapped.drain(..) {
+                    frame_allocator::release_frame(frame);
+                }
+            }
+
+            result
+        })
+    }
+
+    /// frees the address space unless `in_use` and closes the resources of a state that was replaced by `Process::zombify`
+    /// returns the id the next resource would have had
+    pub(super) fn release(self, in_use: bool) -> usize {
+        if !in_use {
+            unsafe { (*self.memory.into_inner().root_page_table).free(4) };
+        }
+
+        // a thread that was blocked while using a resource never runs again so the lock may have been left locked
+        self.resource_manager.into_inner().clean()
+    }
+
+    /// duplicates this state, sharing all of the userspace memory copy-on-write, see `AddressSpace::fork`
+    /// must only be called on the state of the current process because it flushes the TLB of the current cpu
+    pub fn fork(&self, slot: usize) -> Result<Self, MapToError> {
+        let memory = self.with_memory(|memory| memory.fork(slot))?;
+
+        let mut resource_manager = ResourceManager::new();
+        resource_manager.overwrite_resources(self.resource_manager.lock().clone_resources());
+
+        Ok(Self {
+            memory: Mutex::new(memory),
+            cpus: AtomicU64::new(0),
+            resource_manager: Mutex::new(resource_manager),
+            current_dir: Mutex::new(self.current_dir.lock().clone()),
+        })
+    }
+}
+
+impl AddressSpace {
+    /// allocates, zeroes and maps a single page to `page_table` with `flags`
+    /// `fill` can initialize the page before it is mapped, the page is not mapped if it returns false
+    fn map_new_page(
+        page_table: &mut PageTable,
+        page: Page,
+        flags: EntryFlags,
+        fill: impl FnOnce(&mut [u8]),
+    ) -> bool {
+        let Some(frame) = frame_allocator::allocate_frame() else {
+            return false;
         };
 
         let addr = frame.start_address | hddm();
         let ptr = addr as *mut u8;
         let slice = unsafe { slice::from_raw_parts_mut(ptr, PAGE_SIZE) };
-
         slice.fill(0);
-        self.data_pages += 1;
-        Ok(())
-    }
+        fill(slice);
 
-    fn page_unextend_data(&mut self) {
-        let page_end = self.data_break_actual();
-        let new_page = Page::containing_address(page_end);
+        if page_table.map_to(page, frame, flags).is_err() {
+            frame_allocator::deallocate_frame(frame);
+            return false;
+        }
 
-        let frame = unsafe { (*self.root_page_table).get_frame(new_page).unwrap() };
-        frame_allocator::deallocate_frame(frame);
+        true
+    }
 
-        self.data_pages -= 1;
+    /// unmaps every mapped page in `start`..`end`
+    /// the frames are released once the other cpus running the process flushed their TLB, see `AliveProcessState::with_memory`
+    fn unmap_range(&mut self, start: VirtAddr, end: VirtAddr) {
+        let start = align_up(start, PAGE_SIZE);
+        let end = align_up(end, PAGE_SIZE);
+
+        if start < end {
+            let iter = Page::iter_pages(
+                Page::containing_address(start),
+                Page::containing_address(end),
+            );
+
+            for page in iter {
+                if let Some(frame) = unsafe { (*self.root_page_table).take(page) } {
+                    self.unmapped.push(frame);
+                    self.stale = true;
+                }
+            }
+        }
     }
 
+    /// moves the program break by `amount`
+    /// the pages between the data start and the program break are only mapped when they are
+    /// first accessed, see `handle_page_fault`
     pub fn extend_data_by(&mut self, amount: isize) -> Result<*mut u8, MapToError> {
         if amount >= 0 {
-            let amount = amount as usize;
-            while self.data_break_actual() < self.data_break + amount {
-                self.page_extend_data()?;
+            let new_break = self
+                .data_break
+                .checked_add(amount as usize)
+                .ok_or(MapToError::AlreadyMapped)?;
+
+            if amount > 0 && !self.is_range_free(self.data_break, new_break) {
+                return Err(MapToError::AlreadyMapped);
             }
 
-            self.data_break += amount;
+            self.data_break = new_break;
         } else {
-            let amount = amount as usize;
-            while self.data_break_actual() > self.data_break - amount {
-                self.page_unextend_data();
-            }
+            let new_break = self
+                .data_break
+                .saturating_sub(amount.unsigned_abs())
+                .max(self.data_start);
 
-            self.data_break -= amount;
+            self.unmap_range(new_break, self.data_break);
+            self.data_break = new_break;
         }
 
         Ok(self.data_break as *mut u8)
     }
+
+    /// attempts to resolve a page fault caused by an access to `addr`
+    /// maps the faulting page if it belongs to a lazily mapped region such as the data area
+    /// or copies it if it was a `write` to a copy-on-write page
+    /// pages of file backed areas are not mapped here, see `AliveProcessState::handle_page_fault`
+    pub fn handle_page_fault(&mut self, addr: VirtAddr, write: bool) -> PageFault {
+        let page = Page::containing_address(addr);
+        let root_page_table = unsafe { &mut *self.root_page_table };
+
+        // the page is already there so this is either a write to a copy-on-write page or a protection violation
+        if root_page_table.get_frame(page).is_some() {
+            let copied = write && root_page_table.copy_on_write(page).unwrap_or(false);
+            // the other cpus may still read the frame that was copied
+            self.stale |= copied;
+            return copied.into();
+        }
+
+        if let Some(vma) = self.vmas.find(addr) {
+            if vma.prot.is_empty() || (write && !vma.prot.contains(MMapProt::WRITE)) {
+                return PageFault::Invaild;
+            }
+
+            if let VMABacking::File { .. } = vma.backing {
+                return PageFault::ReadFile(vma.clone());
+            }
+
+            return Self::map_new_page(root_page_table, page, vma.entry_flags(), |_| {}).into();
+        }
+
+        if addr < self.data_start || addr >= self.data_break {
+            return PageFault::Invaild;
+        }
+
+        Self::map_new_page(
+            root_page_table,
+            page,
+            EntryFlags::WRITABLE
+                | EntryFlags::USER_ACCESSIBLE
+                | EntryFlags::PRESENT
+                | EntryFlags::NO_EXECUTE,
+            |_| {},
+        )
+        .into()
+    }
+
+    /// maps `page` of `vma` that faulted with `PageFault::ReadFile` with the `content` read from it's file
+    /// if the page was mapped or the area was changed while the address space was unlocked nothing is mapped and true is returned so the access is retried
+    fn map_file_page(&mut self, page: Page, vma: &VMA, content: &[u8]) -> bool {
+        let root_page_table = unsafe { &mut *self.root_page_table };
+        if root_page_table.get_frame(page).is_some() {
+            return true;
+        }
+
+        if !self
+            .vmas
+            .find(page.start_address)
+            .is_some_and(|found| found.is_same(vma))
+        {
+            return true;
+        }
+
+        Self::map_new_page(root_page_table, page, vma.entry_flags(), |frame| {
+            frame[..content.len()].copy_from_slice(content)
+        })
+    }
+
+    /// the flags of `page` if it is mapped
+    fn mapped_flags(&self, page: Page) -> Option<EntryFlags> {
+        let root_page_table = unsafe { &mut *self.root_page_table };
+        root_page_table
+            .get_entry(page)
+            .filter(|entry| entry.is_mapped())
+            .map(|entry| entry.flags())
+    }
+
+    /// returns true if `start`..`end` doesn't overlap with anything in the address space
+    /// the program image and the data area is everything below the program break
+    fn is_range_free(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        const RESERVED: [(VirtAddr, VirtAddr); 5] = [
+            (STACK_START, STACKS_END),
+            (RING0_STACK_START, RING0_STACKS_END),
+            (ARGV_START, ARGV_START + ARGV_SIZE),
+            (INTERPRETER_START, INTERPRETER_END),
+            (TLS_START, TLS_END),
+        ];
+
+        if start >= end || end > USERSPACE_END {
+            return false;
+        }
+
+        let overlaps =
+            |(area_start, area_end): &(VirtAddr, VirtAddr)| start < *area_end && *area_start < end;
+
+        start >= self.data_break
+            && !RESERVED.iter().any(overlaps)
+            && !self.vmas.overlaps(start, end)
+    }
+
+    /// maps `len` bytes with `prot` protection which are only allocated when they are first accessed
+    /// the pages are mapped at `addr` if it is free, otherwise `addr` is only a hint unless `fixed` is true
+    /// returns where the pages were mapped
+    pub fn mmap(
+        &mut self,
+        addr: VirtAddr,
+        len: usize,
+        prot: MMapProt,
+        fixed: bool,
+        backing: VMABacking,
+    ) -> Result<VirtAddr, ErrorStatus> {
+        if len == 0 || addr % PAGE_SIZE != 0 || (fixed && addr == 0) {
+            return Err(ErrorStatus::InvaildPtr);
+        }
+
+        let len = checked_align_up(len, PAGE_SIZE).ok_or(ErrorStatus::InvaildPtr)?;
+        let end = addr.checked_add(len).ok_or(ErrorStatus::InvaildPtr)?;
+
+        let start = if addr != 0 && self.is_range_free(addr, end) {
+            addr
+        } else if fixed {
+            return Err(ErrorStatus::AlreadyExists);
+        } else {
+            self.vmas.find_free(len).ok_or(ErrorStatus::MMapError)?
+        };
+
+        self.vmas.insert(VMA {
+            start,
+            end: start + len,
+            prot,
+            backing,
+        });
+
+        Ok(start)
+    }
+
+    /// unmaps the pages in `addr`..`addr + len` that were mapped with `mmap`
+    pub fn munmap(&mut self, addr: VirtAddr, len: usize) -> Result<(), ErrorStatus> {
+        if len == 0 || addr % PAGE_SIZE != 0 {
+            return Err(ErrorStatus::InvaildPtr);
+        }
+
+        let end = checked_align_up(len, PAGE_SIZE)
+            .and_then(|len| addr.checked_add(len))
+            .filter(|end| *end <= USERSPACE_END)
+            .ok_or(ErrorStatus::InvaildPtr)?;
+
+        for vma in self.vmas.remove(addr, end) {
+            self.unmap_range(vma.start, vma.end);
+        }
+
+        Ok(())
+    }
+
+    /// maps the frames of the ring0 stack of the thread in `slot` to `page_table` too, sharing them with this state's page table
+    fn share_ring0_stack(&self, page_table: &mut PageTable, slot: usize) -> Result<(), MapToError> {
+        let root_page_table = unsafe { &mut *self.root_page_table };
+        let start = ring0_stack_start(slot);
+        let iter = Page::iter_pages(
+            Page::containing_address(start),
+            Page::containing_address(start + STACK_SIZE),
+        );
+
+        for page in iter {
+            let entry = root_page_table
+                .get_entry(page)
+                .expect("ring0 stack is not mapped");
+            let frame = entry.frame().expect("ring0 stack is not mapped");
+
+            page_table.map_to(page, frame, entry.flags())?;
+            frame_allocator::share_frame(frame);
+        }
+
+        Ok(())
+    }
+
+    /// duplicates this address space, sharing all of the userspace memory copy-on-write
+    /// the ring0 stacks are not copied, instead the duplicate gets it's own ring0 stack for the thread in `slot`
+    /// must only be called on the address space of the current process because it flushes the TLB of the current cpu
+    fn fork(&mut self, slot: usize) -> Result<Self, MapToError> {
+        let page_table_addr = paging::allocate_pml4()?;
+        let page_table = unsafe { &mut *((page_table_addr | hddm()) as *mut PageTable) };
+        let root_page_table = unsafe { &mut *self.root_page_table };
+
+        let results = root_page_table
+            .copy_cow(page_table, 4, 0, &(RING0_STACK_START..RING0_STACKS_END))
+            .and_then(|()| super::alloc_ring0_stack(page_table, slot));
+        // our writable pages are now read-only
+        paging::flush_tlb();
+        self.stale = true;
+
+        if let Err(err) = results {
+            unsafe { page_table.free(4) };
+            return Err(err);
+        }
+
+        Ok(Self {
+            root_page_table: page_table,
+            data_start: self.data_start,
+            data_break: self.data_break,
+            vmas: self.vmas.clone(),
+            tls: self.tls,
+            stale: false,
+            unmapped: Vec::new(),
+        })
+    }
+}
+
+/// what `Process::zombify` did
+pub(super) enum Zombify {
+    /// nothing, the process was already a zombie
+    Dead,
+    /// made the threads of the process zombies but one of them is still running on another cpu
+    Running,
+    /// made the process a zombie, the state has to be released with `AliveProcessState::release`
+    /// the bool is wether or not the current thread belongs to the process and still uses it's address space
+    Done(AliveProcessState, bool),
 }
 
+/// the exit code of a process that was killed because of an invaild memory access
+pub const FAULT_EXIT_CODE: usize = 139;
+
 pub struct ZombieProcessState {
     pub exit_code: usize,
     pub exit_addr: usize,
     pub exit_stack_addr: usize,
+    /// the address that caused the process to fault, 0 if it exited normally
+    pub fault_addr: usize,
     pub killed_by: u64,
     pub last_resource_id: usize,
 
     pub data_start: usize,
     pub data_break: usize,
+    /// the address space of the process if a cpu still runs one of it's threads, freed and cleared by the scheduler afterwards
+    pub page_table: *mut PageTable,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct ProcessInfo {
     pub ppid: u64,
@@ -137,10 +563,17 @@ pub struct ProcessInfo {
     pub exit_code: usize,
     pub exit_addr: usize,
     pub exit_stack_addr: usize,
+    pub fault_addr: usize,
 
     pub killed_by: u64,
     pub data_start: usize,
     pub data_break: usize,
+    pub nice: i8,
+
+    pub user_ticks: u64,
+    pub kernel_ticks: u64,
+    pub context_switches: u64,
+    pub start_time: u64,
 }
 
 pub enum ProcessState {
@@ -152,13 +585,215 @@ pub struct Process {
     pub ppid: u64,
     pub pid: u64,
     pub name: [u8; 64],
-    pub status: ProcessStatus,
-    pub context: CPUStatus,
+    flags: ProcessFlags,
+    /// between `super::NICE_MIN` and `super::NICE_MAX`, inherited from the parent
+    pub nice: i8,
+    /// the nanoseconds the threads of the process spent running in userspace and in the kernel, see `Process::account`
+    /// atomic because the cpu running the process charges it without the scheduler lock
+    user_time: AtomicU64,
+    kernel_time: AtomicU64,
+    /// the number of times a thread of the process was switched away from
+    pub context_switches: u64,
+    /// wether or not the syscalls of the process are logged, not inherited by children
+    pub traced: bool,
+    /// the milliseconds since boot the process was created at
+    pub start_time: u64,
+    /// the threads sharing the state of the process, the main thread is the first one unless it was reaped
+    /// boxed so the scheduler can keep pointers to them while threads are added and removed
+    pub threads: Vec<Box<Thread>>,
+    next_tid: u64,
 
     pub state: ProcessState,
     pub next: Option<Box<Self>>,
 }
 
+/// converts `name` to a fixed size process name, truncating it if it is too long
+fn make_name(name: &str) -> [u8; 64] {
+    let name_bytes = name.as_bytes();
+
+    let mut name = [0u8; 64];
+
+    let len = name_bytes.len().min(64);
+    name[..len].copy_from_slice(&name_bytes[..len]);
+    name
+}
+
+/// copies `argv` to the already allocated argv area of `page_table`
+/// then passes it to the process as arguments in `context`
+unsafe fn copy_argv(page_table: &mut PageTable, argv: &[&str], context: &mut CPUStatus) {
+    if argv.len() != 0 {
+        let mut start_addr = ARGV_START;
+        const USIZE_BYTES: usize = size_of::<usize>();
+        let argc = argv.len();
+
+        // argc
+        copy_to_userspace(
+            page_table,
+            start_addr,
+            &core::mem::transmute::<_, [u8; USIZE_BYTES]>(argc),
+        );
+
+        // argv*
+        start_addr += USIZE_BYTES;
+
+        for arg in argv {
+            let arg = arg.as_bytes();
+            let len = arg.len();
+
+            copy_to_userspace(
+                page_table,
+                start_addr,
+                &core::mem::transmute::<_, [u8; USIZE_BYTES]>(len),
+            );
+            start_addr += USIZE_BYTES;
+
+            copy_to_userspace(page_table, start_addr, arg);
+            // null-terminate arg
+            copy_to_userspace(page_table, start_addr + len, &[b'\0']);
+            start_addr += len + 1;
+        }
+
+        let argv_addr = start_addr;
+        let mut current_argv_ptr = ARGV_START + USIZE_BYTES /* after argc */;
+        // argv**
+        for arg in argv {
+            copy_to_userspace(
+                page_table,
+                start_addr,
+                &core::mem::transmute::<_, [u8; USIZE_BYTES]>(current_argv_ptr),
+            );
+            start_addr += USIZE_BYTES;
+
+            current_argv_ptr += USIZE_BYTES; // skip the len
+            current_argv_ptr += arg.len() + 1; // skip the data
+        }
+
+        // set rdi and rsi to argc and argv
+        // _start looks like: extern "C" _start(argc: u64, argv: *const (len, str))
+        #[cfg(target_arch = "x86_64")]
+        {
+            context.rdi = argc as u64;
+            context.rsi = argv_addr as u64;
+        }
+        // looks like this: argc: 8 (u64) -> argv: (len: 8 (u64) + bytes: len ([u8])) * argc -> argv_pointers: 8 (u64) * argc
+        // where numbers is bytes count, (TYPE) is the type of the bytes
+    }
+}
+
+/// an executable loaded by `load_elf`
+struct Image<'a> {
+    /// the entry point of the interpreter if the executable has one, otherwise the executable's
+    entry_point: VirtAddr,
+    program_break: VirtAddr,
+    /// the thread pointer of the thread local storage block of the main thread, 0 if the executable has none
+    thread_pointer: VirtAddr,
+    tls: Option<TLSTemplate>,
+    /// the executable if it has to be loaded by an interpreter
+    interpreted: Option<LoadedElf<'a>>,
+}
+
+/// loads `elf` to `page_table`, position independent executables are loaded at `ELF_DYN_BASE`
+/// if `elf` requests an interpreter, it is read from the vfs and loaded at `INTERPRETER_START`
+/// also allocates the thread local storage block of `elf` for the main thread in `slot` if it has one
+fn load_elf<'a>(
+    elf: &Elf<'a>,
+    page_table: &mut PageTable,
+    slot: usize,
+) -> Result<Image<'a>, ElfError> {
+    let exec = elf.load_exec(page_table, ELF_DYN_BASE)?;
+    let thread_pointer = match exec.tls {
+        Some(ref template) => super::alloc_tls(page_table, template, slot)?,
+        None => 0,
+    };
+
+    let Some(path) = exec.interpreter else {
+        return Ok(Image {
+            entry_point: exec.entry_point,
+            program_break: exec.program_break,
+            thread_pointer,
+            tls: exec.tls,
+            interpreted: None,
+        });
+    };
+
+    let buffer = super::expose::read_file(path).map_err(|_| ElfError::InterpreterNotFound)?;
+    let interpreter = Elf::new(&buffer)?;
+
+    // the interpreter has to be position independent and has to load itself
+    if interpreter.header.kind != ElfType::DYN || interpreter.interpreter()?.is_some() {
+        return Err(ElfError::UnsupportedKind);
+    }
+
+    let interpreter = interpreter.load_exec(page_table, INTERPRETER_START)?;
+    if interpreter.program_break > INTERPRETER_END {
+        return Err(ElfError::TooLarge);
+    }
+
+    Ok(Image {
+        entry_point: interpreter.entry_point,
+        program_break: exec.program_break,
+        thread_pointer,
+        tls: exec.tls,
+        interpreted: Some(exec),
+    })
+}
+
+/// passes the thread pointer of `image` in `context`
+/// and the info the interpreter needs to load the executable if it has one, after argc and argv
+fn pass_image(image: &Image, context: &mut CPUStatus) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        context.fs_base = image.thread_pointer as u64;
+
+        // _start of the interpreter looks like:
+        // extern "C" _start(argc: u64, argv: *const (len, str), phdr: *const ProgramHeader, phnum: u64, entry: usize, base: usize)
+        if let Some(ref exec) = image.interpreted {
+            context.rdx = exec.program_headers as u64;
+            context.rcx = exec.program_headers_count as u64;
+            context.r8 = exec.entry_point as u64;
+            context.r9 = exec.base as u64;
+        }
+    }
+}
+
+/// sets up `context` to start executing `function` from the top of the stack of the thread in `slot`
+/// in the address space of the page table at `root_page_table_addr`
+fn init_context(
+    context: &mut CPUStatus,
+    function: usize,
+    slot: usize,
+    root_page_table_addr: PhysAddr,
+    flags: ProcessFlags,
+) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use arch::x86_64::threading::RFLAGS;
+
+        context.rsp = (stack_start(slot) + STACK_SIZE) as u64;
+        context.rip = function as u64;
+
+        // Kernel process
+        // runs on it's ring0 stack which isn't accessible by userspace so it can run with SMAP, see `arch::usercopy`
+        // the cpu never switches to it because the thread is never in ring3
+        if flags.is_empty() {
+            context.rsp = (ring0_stack_start(slot) + STACK_SIZE) as u64;
+            context.rflags = RFLAGS::from_bits_retain(0x202);
+
+            context.ss = arch::x86_64::gdt::KERNEL_DATA_SEG as u64;
+            context.cs = arch::x86_64::gdt::KERNEL_CODE_SEG as u64;
+        } else if flags.contains(ProcessFlags::USERSPACE) {
+            context.rflags = RFLAGS::IOPL_LOW
+                | RFLAGS::IOPL_HIGH
+                | RFLAGS::INTERRUPT_FLAG
+                | RFLAGS::from_bits_retain(0x2);
+
+            context.ss = arch::x86_64::gdt::USER_DATA_SEG as u64;
+            context.cs = arch::x86_64::gdt::USER_CODE_SEG as u64;
+        }
+        context.cr3 = root_page_table_addr as u64;
+    }
+}
+
 impl Process {
     #[inline(always)]
     pub fn new(
@@ -172,114 +807,35 @@ impl Process {
         current_work_dir: String,
         flags: ProcessFlags,
     ) -> Result<Self, MapToError> {
-        let name_bytes = name.as_bytes();
-
-        let mut name = [0u8; 64];
-
-        let len = name_bytes.len().min(64);
-        name[..len].copy_from_slice(&name_bytes[..len]);
-
-        let status = ProcessStatus::Waiting;
+        let name = make_name(name);
         let mut context = CPUStatus::default();
 
         let root_page_table = (root_page_table_addr | hddm()) as *mut PageTable;
 
         unsafe {
             let page_table = &mut *root_page_table;
-            super::alloc_stack(page_table)?;
-            super::alloc_ring0_stack(page_table)?;
+            super::alloc_stack(page_table, 0)?;
+            super::alloc_ring0_stack(page_table, 0)?;
             super::alloc_argv(page_table)?;
 
-            if argv.len() != 0 {
-                let mut start_addr = ARGV_START;
-                const USIZE_BYTES: usize = size_of::<usize>();
-                let argc = argv.len();
-
-                // argc
-                copy_to_userspace(
-                    page_table,
-                    start_addr,
-                    &core::mem::transmute::<_, [u8; USIZE_BYTES]>(argc),
-                );
-
-                // argv*
-                start_addr += USIZE_BYTES;
-
-                for arg in argv {
-                    let arg = arg.as_bytes();
-                    let len = arg.len();
-
-                    copy_to_userspace(
-                        page_table,
-                        start_addr,
-                        &core::mem::transmute::<_, [u8; USIZE_BYTES]>(len),
-                    );
-                    start_addr += USIZE_BYTES;
-
-                    copy_to_userspace(page_table, start_addr, arg);
-                    // null-terminate arg
-                    copy_to_userspace(page_table, start_addr + len, &[b'\0']);
-                    start_addr += len + 1;
-                }
-
-                let argv_addr = start_addr;
-                let mut current_argv_ptr = ARGV_START + USIZE_BYTES /* after argc */;
-                // argv**
-                for arg in argv {
-                    copy_to_userspace(
-                        page_table,
-                        start_addr,
-                        &core::mem::transmute::<_, [u8; USIZE_BYTES]>(current_argv_ptr),
-                    );
-                    start_addr += USIZE_BYTES;
-
-                    current_argv_ptr += USIZE_BYTES; // skip the len
-                    current_argv_ptr += arg.len() + 1; // skip the data
-                }
-
-                // set rdi and rsi to argc and argv
-                // _start looks like: extern "C" _start(argc: u64, argv: *const (len, str))
-                #[cfg(target_arch = "x86_64")]
-                {
-                    context.rdi = argc as u64;
-                    context.rsi = argv_addr as u64;
-                }
-                // looks like this: argc: 8 (u64) -> argv: (len: 8 (u64) + bytes: len ([u8])) * argc -> argv_pointers: 8 (u64) * argc
-                // where numbers is bytes count, (TYPE) is the type of the bytes
-            }
+            copy_argv(page_table, argv, &mut context);
         }
 
-        #[cfg(target_arch = "x86_64")]
-        {
-            use arch::x86_64::threading::RFLAGS;
-
-            context.rsp = STACK_END as u64;
-            context.rip = function as u64;
-
-            // Kernel process
-            if flags.is_empty() {
-                context.rflags = RFLAGS::from_bits_retain(0x202);
-
-                context.ss = arch::x86_64::gdt::KERNEL_DATA_SEG as u64;
-                context.cs = arch::x86_64::gdt::KERNEL_CODE_SEG as u64;
-            } else if flags.contains(ProcessFlags::USERSPACE) {
-                context.rflags = RFLAGS::IOPL_LOW
-                    | RFLAGS::IOPL_HIGH
-                    | RFLAGS::INTERRUPT_FLAG
-                    | RFLAGS::from_bits_retain(0x2);
-
-                context.ss = arch::x86_64::gdt::USER_DATA_SEG as u64;
-                context.cs = arch::x86_64::gdt::USER_CODE_SEG as u64;
-            }
-            context.cr3 = root_page_table_addr as u64;
-        }
+        init_context(&mut context, function, 0, root_page_table_addr, flags);
 
         Ok(Process {
             ppid,
             pid,
             name,
-            status,
-            context,
+            flags,
+            nice: 0,
+            user_time: AtomicU64::new(0),
+            kernel_time: AtomicU64::new(0),
+            context_switches: 0,
+            traced: false,
+            start_time: time::uptime(),
+            threads: vec![Box::new(Thread::new(0, 0, context))],
+            next_tid: 1,
 
             state: ProcessState::Alive(AliveProcessState::new(
                 current_work_dir,
@@ -299,9 +855,9 @@ impl Process {
         current_work_dir: String,
         flags: ProcessFlags,
     ) -> Result<Self, MapToError> {
-        let pid = scheduler().next_pid;
+        let pid = scheduler().alloc_pid();
 
-        let results = Self::new(
+        let mut results = Self::new(
             function,
             scheduler().current_process().pid,
             pid,
@@ -312,7 +868,7 @@ impl Process {
             current_work_dir,
             flags,
         )?;
-        scheduler().next_pid += 1;
+        results.nice = scheduler().current_process().nice;
 
         debug!(Process, "process with pid {} ({}) CREATED ...", pid, name);
         Ok(results)
@@ -328,14 +884,21 @@ impl Process {
     ) -> Result<Self, ElfError> {
         let page_table_addr = paging::allocate_pml4().map_err(|_| ElfError::MapToError)?;
 
-        let data_break =
-            unsafe { elf.load_exec(&mut *((page_table_addr | hddm()) as *mut PageTable))? };
+        let page_table = unsafe { &mut *((page_table_addr | hddm()) as *mut PageTable) };
+
+        let image = match load_elf(&elf, page_table, 0) {
+            Ok(image) => image,
+            Err(err) => {
+                unsafe { page_table.free(4) };
+                return Err(err);
+            }
+        };
 
-        let process = Self::create(
-            elf.header.entry_point,
+        let mut process = Self::create(
+            image.entry_point,
             name,
             argv,
-            data_break,
+            image.program_break,
             page_table_addr,
             current_work_dir,
             ProcessFlags::USERSPACE,
@@ -343,33 +906,394 @@ impl Process {
         .ok()
         .ok_or(ElfError::MapToError)?;
 
+        if let ProcessState::Alive(ref state) = process.state {
+            state.with_memory(|memory| memory.tls = image.tls);
+        }
+
+        pass_image(&image, &mut process.threads[0].context);
         Ok(process)
     }
 
-    /// makes a process a zombie
-    /// does nothing if the process is already a zombie
-    /// also moves the parentership of the process (it's children) to it's parent
-    pub fn terminate(&mut self, exit_code: usize, terminator: u64) {
-        if let ProcessState::Alive(ref mut state) = &mut self.state {
-            let root_page_table = unsafe { &mut (*state.root_page_table) };
-            unsafe { root_page_table.free(4) };
+    /// the status of the process, a process is running if any of it's threads is running
+    /// and blocked if all of it's threads that didn't exit are blocked
+    pub fn status(&self) -> ProcessStatus {
+        if let ProcessState::Zombie(_) = self.state {
+            return ProcessStatus::Zombie;
+        }
+
+        let mut alive = self
+            .threads
+            .iter()
+            .filter(|thread| thread.status != ThreadStatus::Zombie);
+
+        if alive
+            .clone()
+            .any(|thread| thread.status == ThreadStatus::Running)
+        {
+            ProcessStatus::Running
+        } else if alive.all(|thread| thread.status == ThreadStatus::Blocked) {
+            ProcessStatus::Blocked
+        } else {
+            ProcessStatus::Waiting
+        }
+    }
+
+    /// finds the first slot that isn't used by any thread, zombie threads keep their slots until they are reaped
+    fn free_slot(&self) -> Option<usize> {
+        (0..MAX_THREADS).find(|slot| !self.threads.iter().any(|thread| thread.slot == *slot))
+    }
+
+    /// spawns a thread that starts executing `entry` with `arg` as it's argument
+    /// the thread gets it's own stacks and thread local storage block initialized from the process's template
+    /// returns the tid of the new thread
+    pub fn spawn_thread(&mut self, entry: VirtAddr, arg: usize) -> Result<u64, ErrorStatus> {
+        with_scheduler_lock(|| self.add_thread(entry, arg).map(|thread| thread.tid))
+    }
+
+    /// spawns a thread like `spawn_thread` and returns it, must be called with the scheduler lock held
+    /// so the slot of the thread cannot be taken by another cpu
+    pub(super) fn add_thread(
+        &mut self,
+        entry: VirtAddr,
+        arg: usize,
+    ) -> Result<&mut Thread, ErrorStatus> {
+        let ProcessState::Alive(ref state) = self.state else {
+            unreachable!("spawning a thread in a zombie process")
+        };
+
+        let slot = self.free_slot().ok_or(ErrorStatus::Busy)?;
+
+        let (thread_pointer, root_page_table) = state.with_memory(|memory| {
+            let page_table = unsafe { &mut *memory.root_page_table };
+
+            let alloc_slot = |page_table: &mut PageTable| -> Result<VirtAddr, ErrorStatus> {
+                super::alloc_stack(page_table, slot)?;
+                super::alloc_ring0_stack(page_table, slot)?;
+
+                match memory.tls {
+                    Some(ref template) => Ok(super::alloc_tls(page_table, template, slot)?),
+                    None => Ok(0),
+                }
+            };
+
+            match alloc_slot(page_table) {
+                Ok(thread_pointer) => Ok((thread_pointer, memory.root_page_table)),
+                Err(err) => {
+                    super::free_thread_slot(page_table, slot);
+                    Err(err)
+                }
+            }
+        })?;
+
+        let mut context = CPUStatus::default();
+        let root_page_table_addr = root_page_table as usize - hddm();
+        init_context(&mut context, entry, slot, root_page_table_addr, self.flags);
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            context.rdi = arg as u64;
+            context.fs_base = thread_pointer as u64;
+        }
+
+        let tid = self.next_tid;
+        self.next_tid += 1;
+        self.threads.push(Box::new(Thread::new(tid, slot, context)));
+
+        debug!(
+            Process,
+            "process with pid {} SPAWNED thread {} ...", self.pid, tid
+        );
+        Ok(&mut **self.threads.last_mut().unwrap())
+    }
+
+    /// makes the thread `tid` a zombie until it is reaped by `reap_thread`
+    /// terminates the process with `exit_code` if it was the last thread that didn't exit
+    pub fn exit_thread(&mut self, tid: u64, exit_code: usize) {
+        let last = with_scheduler_lock(|| {
+            if let Some(thread) = self.threads.iter_mut().find(|thread| thread.tid == tid) {
+                thread.status = ThreadStatus::Zombie;
+                thread.exit_code = exit_code;
+            }
+
+            self.threads
+                .iter()
+                .all(|thread| thread.status == ThreadStatus::Zombie)
+        });
+
+        if last {
+            scheduler().terminate(self.pid, exit_code, 0);
+        }
+
+        super::EXIT_WAITERS.wake_all();
+    }
+
+    /// removes the thread `tid` if it exited freeing it's stacks and thread local storage block
+    /// returns it's exit code or None if it didn't exit yet or a cpu didn't switch away from it yet
+    pub fn reap_thread(&mut self, tid: u64) -> Result<Option<usize>, ErrorStatus> {
+        with_scheduler_lock(|| {
+            let index = self
+                .threads
+                .iter()
+                .position(|thread| thread.tid == tid)
+                .ok_or(ErrorStatus::InvaildPid)?;
+
+            let thread = &self.threads[index];
+            if thread.status != ThreadStatus::Zombie || thread.on_cpu {
+                return Ok(None);
+            }
+
+            let exit_code = thread.exit_code;
+            // the whole address space is already freed if the process is a zombie
+            if let ProcessState::Alive(ref state) = self.state {
+                state.with_memory(|memory| {
+                    super::free_thread_slot(unsafe { &mut *memory.root_page_table }, thread.slot)
+                });
+            }
+
+            self.threads.remove(index);
+            Ok(Some(exit_code))
+        })
+    }
+
+    /// makes the threads of the process zombies so no cpu can pick them and replaces it's state with a zombie state
+    /// the state is only replaced once no other cpu runs a thread of the process because they may still be using it
+    /// the current thread is only made a zombie then, it keeps running until it switches away
+    /// must be called with the scheduler lock held, see `Scheduler::terminate`
+    pub(super) fn zombify(&mut self, exit_code: usize, terminator: u64) -> Zombify {
+        let ProcessState::Alive(ref state) = self.state else {
+            return Zombify::Dead;
+        };
+
+        // the exit address is taken from the current thread if it is a thread of the process
+        // or from a running thread if there is one
+        let current = scheduler().current_thread() as *const Thread;
+        let is_current = |thread: &Thread| core::ptr::eq(thread, current);
+        let context = self
+            .threads
+            .iter()
+            .find(|thread| is_current(thread))
+            .or_else(|| {
+                self.threads
+                    .iter()
+                    .find(|thread| thread.status == ThreadStatus::Running)
+            })
+            .or(self.threads.first())
+            .map(|thread| thread.context)
+            .unwrap_or_default();
+
+        for thread in self.threads.iter_mut().filter(|thread| !is_current(thread)) {
+            thread.status = ThreadStatus::Zombie;
+        }
+
+        if self
+            .threads
+            .iter()
+            .any(|thread| thread.on_cpu && !is_current(thread))
+        {
+            return Zombify::Running;
+        }
+
+        for thread in &mut self.threads {
+            thread.status = ThreadStatus::Zombie;
+        }
+
+        // the address space is still in use if the current thread belongs to the process
+        // it is freed by the scheduler once it is switched away from
+        let running = self.threads.iter().any(|thread| thread.on_cpu);
+        let (data_start, data_break, root_page_table) = state
+            .with_memory(|memory| (memory.data_start, memory.data_break, memory.root_page_table));
+        let zombified = ProcessState::Zombie(ZombieProcessState {
+            exit_code,
+            exit_addr: context.at(),
+            exit_stack_addr: context.stack_at(),
+            fault_addr: 0,
+            killed_by: terminator,
+            last_resource_id: 0,
+            data_start,
+            data_break,
+            page_table: if running { root_page_table } else { null_mut() },
+        });
+
+        // the threads are kept because the scheduler may still point to one of them
+        let ProcessState::Alive(state) = core::mem::replace(&mut self.state, zombified) else {
+            unreachable!()
+        };
+        Zombify::Done(state, running)
+    }
+
+    /// replaces the image of the current process with `elf` keeping the pid, the parent, the resources and the cwd
+    /// the stack, the argv area and the data break are reset and the process is renamed to `name`
+    /// only the current thread survives, it keeps it's tid and slot
+    /// switches to the new address space and returns the context to start the new image from
+    /// `name` and `argv` are copied before switching so they may belong to the old address space
+    pub fn exec(&mut self, elf: Elf, name: &str, argv: &[&str]) -> Result<CPUStatus, ElfError> {
+        let ProcessState::Alive(ref state) = self.state else {
+            unreachable!("exec-ing a zombie process")
+        };
+
+        let slot = scheduler().current_thread().slot;
+        let page_table_addr = paging::allocate_pml4().map_err(|_| ElfError::MapToError)?;
+        let page_table = unsafe { &mut *((page_table_addr | hddm()) as *mut PageTable) };
+
+        let mut load_image = || -> Result<(VirtAddr, Option<TLSTemplate>, CPUStatus), ElfError> {
+            let image = load_elf(&elf, page_table, slot)?;
+            let mut context = CPUStatus::default();
+
+            unsafe {
+                super::alloc_stack(page_table, slot).map_err(|_| ElfError::MapToError)?;
+                super::alloc_argv(page_table).map_err(|_| ElfError::MapToError)?;
+                // we are running on the ring0 stack so it has to survive the switch
+                state
+                    .with_memory(|memory| memory.share_ring0_stack(page_table, slot))
+                    .map_err(|_| ElfError::MapToError)?;
+
+                copy_argv(page_table, argv, &mut context);
+            }
+
+            pass_image(&image, &mut context);
+
+            init_context(
+                &mut context,
+                image.entry_point,
+                slot,
+                page_table_addr,
+                ProcessFlags::USERSPACE,
+            );
+            Ok((image.program_break, image.tls, context))
+        };
+
+        let (data_break, tls, context) = match load_image() {
+            Ok(image) => image,
+            Err(err) => {
+                unsafe { page_table.free(4) };
+                return Err(err);
+            }
+        };
+
+        debug!(
+            Process,
+            "process with pid {} EXECUTING {} ...", self.pid, name
+        );
+        self.name = make_name(name);
+
+        // the other threads are stopped first as their stacks are freed with the old page table
+        with_scheduler_lock(|| {
+            for thread in self.threads.iter_mut().filter(|thread| thread.slot != slot) {
+                thread.status = ThreadStatus::Zombie;
+            }
+        });
+
+        while with_scheduler_lock(|| {
+            self.threads
+                .iter()
+                .any(|thread| thread.slot != slot && thread.on_cpu)
+        }) {
+            super::expose::thread_yeild();
+        }
+
+        let data_break = align_up(data_break, PAGE_SIZE);
+        let old_page_table = state.with_memory(|memory| {
+            memory.data_start = data_break;
+            memory.data_break = data_break;
+            memory.vmas = VMAs::new();
+            memory.tls = tls;
+            core::mem::replace(&mut memory.root_page_table, page_table)
+        });
+        let old_page_table = unsafe { &mut *old_page_table };
+        with_scheduler_lock(|| self.threads.retain(|thread| thread.slot == slot));
+        // the new image starts with reset registers
+        fpu::reset(&mut scheduler().current_thread().fpu);
+
+        sorcery::set_current_page_table(page_table);
+        unsafe { old_page_table.free(4) };
+        Ok(context)
+    }
+
+    /// duplicates the current process, the duplicate shares all of the memory copy-on-write
+    /// and continues from `context` as a child of this process
+    /// only the current thread is duplicated, it becomes the main thread of the child
+    pub fn fork(&self, mut context: CPUStatus) -> Result<Self, MapToError> {
+        let ProcessState::Alive(ref state) = self.state else {
+            unreachable!("forking a zombie process")
+        };
+
+        let current = scheduler().current_thread();
+        let slot = current.slot;
+        let mut state = state.fork(slot)?;
+        let pid = scheduler().alloc_pid();
+
+        // the child doesn't have the other threads so their stacks are not needed
+        let root_page_table = state.memory.get_mut().root_page_table;
+        let page_table = unsafe { &mut *root_page_table };
+        for thread in self.threads.iter().filter(|thread| thread.slot != slot) {
+            super::free_thread_slot(page_table, thread.slot);
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            context.cr3 = (root_page_table as usize - hddm()) as u64;
+        }
+
+        debug!(
+            Process,
+            "process with pid {} FORKED into {} ...", self.pid, pid
+        );
+
+        // the registers may only be in the cpu
+        fpu::save(&mut current.fpu);
+        let mut thread = Box::new(Thread::new(0, slot, context));
+        thread.fpu = current.fpu.as_mut().map(|state| state.duplicate());
+
+        Ok(Process {
+            ppid: self.pid,
+            pid,
+            name: self.name,
+            flags: self.flags,
+            nice: self.nice,
+            user_time: AtomicU64::new(0),
+            kernel_time: AtomicU64::new(0),
+            context_switches: 0,
+            traced: false,
+            start_time: time::uptime(),
+            threads: vec![thread],
+            next_tid: 1,
+
+            state: ProcessState::Alive(state),
+            next: None,
+        })
+    }
+
+    /// terminates the process because of an unrecoverable fault while accessing `fault_addr`
+    /// `at` and `stack_at` are the instruction and stack pointers of the faulting instruction
+    pub fn terminate_faulted(&mut self, fault_addr: VirtAddr, at: VirtAddr, stack_at: VirtAddr) {
+        // the faulting thread is the current thread
+        let thread = scheduler().current_thread();
+        thread.context.rip = at as u64;
+        thread.context.rsp = stack_at as u64;
 
-            let last_resource_id = state.resource_manager.lock().clean();
-            let zombified = ProcessState::Zombie(ZombieProcessState {
-                exit_code,
-                exit_addr: self.context.at(),
-                exit_stack_addr: self.context.stack_at(),
-                killed_by: terminator,
-                last_resource_id,
-                data_start: state.data_start,
-                data_break: state.data_break,
-            });
+        scheduler().terminate(self.pid, FAULT_EXIT_CODE, 0);
 
-            self.state = zombified;
-            self.status = ProcessStatus::Zombie;
-            scheduler().move_parentership(self.pid, self.ppid);
-            debug!(Process, "process with pid {} TERMINATED ...", self.pid);
+        if let ProcessState::Zombie(ref mut state) = self.state {
+            state.fault_addr = fault_addr;
         }
+
+        debug!(
+            Process,
+            "process with pid {} FAULTED at {:#x} while accessing {:#x} ...",
+            self.pid,
+            at,
+            fault_addr
+        );
+    }
+
+    /// charges the process for `nanoseconds` of running in userspace or in the kernel
+    pub fn account(&self, nanoseconds: u64, userspace: bool) {
+        let time = if userspace {
+            &self.user_time
+        } else {
+            &self.kernel_time
+        };
+        time.fetch_add(nanoseconds, Ordering::Relaxed);
     }
 
     pub fn info(&self) -> ProcessInfo {
@@ -377,6 +1301,7 @@ impl Process {
             exit_code,
             exit_addr,
             exit_stack_addr,
+            fault_addr,
             killed_by,
             resource_count,
             data_start,
@@ -386,6 +1311,7 @@ impl Process {
                 state.exit_code,
                 state.exit_addr,
                 state.exit_stack_addr,
+                state.fault_addr,
                 state.killed_by,
                 state.last_resource_id,
                 state.data_start,
@@ -396,9 +1322,20 @@ impl Process {
                 0,
                 0,
                 0,
-                state.resource_manager.lock().next_ri(),
-                state.data_start,
-                state.data_break,
+                0,
+                // the scheduler's lock may be held, waiting for the lock could deadlock with a cpu that holds it
+                state
+                    .resource_manager
+                    .try_lock()
+                    .map_or(0, |resources| resources.next_ri()),
+                state
+                    .memory
+                    .try_lock()
+                    .map_or(0, |memory| memory.data_start),
+                state
+                    .memory
+                    .try_lock()
+                    .map_or(0, |memory| memory.data_break),
             ),
         };
 
@@ -406,16 +1343,23 @@ impl Process {
             ppid: self.ppid,
             pid: self.pid,
             name: self.name,
-            status: self.status,
+            status: self.status(),
 
             exit_code,
             exit_addr,
             exit_stack_addr,
+            fault_addr,
 
             killed_by,
             resource_count,
             data_start,
             data_break,
+            nice: self.nice,
+
+            user_ticks: self.user_time.load(Ordering::Relaxed) / time::NANOS_PER_TICK,
+            kernel_ticks: self.kernel_time.load(Ordering::Relaxed) / time::NANOS_PER_TICK,
+            context_switches: self.context_switches,
+            start_time: self.start_time,
         }
     }
 }
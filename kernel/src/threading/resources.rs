@@ -1,6 +1,6 @@
 use alloc::vec::Vec;
 
-use crate::drivers::vfs::{DirIter, FileDescriptor, FS, VFS_STRUCT};
+use crate::drivers::vfs::{read_vfs, DirIter, FileDescriptor, FS};
 
 #[derive(Clone)]
 pub enum Resource {
@@ -55,17 +55,18 @@ impl ResourceManager {
         ri
     }
 
+    /// removes the resource with index `ri` and returns it
     #[inline]
-    pub fn remove_resource(&mut self, ri: usize) -> Result<(), ()> {
+    pub fn remove_resource(&mut self, ri: usize) -> Result<Resource, ()> {
         if ri >= self.resources.len() {
             return Err(());
         }
 
-        self.resources[ri] = Resource::Null;
+        let resource = core::mem::replace(&mut self.resources[ri], Resource::Null);
         if ri < self.next_ri {
             self.next_ri = ri;
         }
-        Ok(())
+        Ok(resource)
     }
 
     /// cleans up all resources
@@ -73,7 +74,7 @@ impl ResourceManager {
     pub fn clean(&mut self) -> usize {
         for resource in &mut self.resources {
             match resource {
-                Resource::File(fd) => VFS_STRUCT.read().close(fd).unwrap(),
+                Resource::File(fd) => read_vfs().close(fd).unwrap(),
                 _ => *resource = Resource::Null,
             }
         }
@@ -0,0 +1,62 @@
+use alloc::boxed::Box;
+
+use crate::{
+    arch::threading::{fpu::FPUState, CPUStatus},
+    VirtAddr,
+};
+
+use super::{ring0_stack_start, STACK_SIZE};
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadStatus {
+    Waiting,
+    Running,
+    Zombie,
+    /// waiting in a `super::waitqueue::WaitQueue`, skipped by the scheduler until it is woken up
+    Blocked,
+}
+
+/// a thread of a process, the threads of a process share it's `AliveProcessState`
+/// while each thread has it's own context, stacks and thread local storage block
+pub struct Thread {
+    /// the id of the thread in it's process, the main thread's is 0
+    pub tid: u64,
+    pub status: ThreadStatus,
+    pub context: CPUStatus,
+    /// the slot of the stacks and the thread local storage block of the thread, see `super::stack_start`
+    pub slot: usize,
+    /// only vaild if the thread is a zombie
+    pub exit_code: usize,
+    /// the nanoseconds the thread ran for scaled by the weight of it's process's nice value, see `super::nice_weight`
+    /// the scheduler runs the ready thread with the least virtual runtime
+    pub vruntime: u64,
+    /// set while a cpu runs the thread, the thread's stacks and address space cannot be freed until it is cleared
+    pub on_cpu: bool,
+    /// set for the idle threads of the cpus, they are only ran when there is nothing else to run
+    pub idle: bool,
+    /// the saved x87, sse and avx registers, None until the thread uses them
+    pub fpu: Option<Box<FPUState>>,
+}
+
+impl Thread {
+    pub const fn new(tid: u64, slot: usize, context: CPUStatus) -> Self {
+        Self {
+            tid,
+            status: ThreadStatus::Waiting,
+            context,
+            slot,
+            exit_code: 0,
+            vruntime: 0,
+            on_cpu: false,
+            idle: false,
+            fpu: None,
+        }
+    }
+
+    /// the top of the stack the cpu switches to when the thread is interrupted in ring3
+    #[inline(always)]
+    pub const fn ring0_stack_end(&self) -> VirtAddr {
+        ring0_stack_start(self.slot) + STACK_SIZE
+    }
+}
@@ -0,0 +1,221 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec, vec::Vec};
+use bitflags::bitflags;
+
+use crate::{
+    drivers::vfs::Inode,
+    memory::paging::{EntryFlags, Page, PAGE_SIZE},
+    VirtAddr,
+};
+
+/// the area mmap picks addresses from when the caller doesn't ask for a fixed address
+pub const MMAP_START: VirtAddr = 0x0000_4000_0000_0000;
+pub const MMAP_END: VirtAddr = 0x0000_7000_0000_0000;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct MMapProt: u8 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    pub struct MMapFlags: u8 {
+        /// maps exactly at the given address instead of treating it as a hint
+        const FIXED = 1 << 0;
+        /// the mapping isn't backed by a file and is filled with zeros
+        const ANONYMOUS = 1 << 1;
+    }
+}
+
+/// where the pages of a `VMA` get their initial content from
+#[derive(Clone)]
+pub enum VMABacking {
+    Anonymous,
+    /// the area is a private copy of `inode` starting from `offset`, writes are not written back
+    File {
+        inode: Inode,
+        offset: usize,
+    },
+}
+
+/// a virtual memory area, a range of pages that are mapped on demand when they are first accessed
+#[derive(Clone)]
+pub struct VMA {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub prot: MMapProt,
+    pub backing: VMABacking,
+}
+
+impl VMA {
+    pub fn entry_flags(&self) -> EntryFlags {
+        let mut flags = EntryFlags::PRESENT | EntryFlags::USER_ACCESSIBLE;
+
+        if self.prot.contains(MMapProt::WRITE) {
+            flags |= EntryFlags::WRITABLE;
+        }
+
+        if !self.prot.contains(MMapProt::EXEC) {
+            flags |= EntryFlags::NO_EXECUTE;
+        }
+
+        flags
+    }
+
+    /// splits the area at `addr`, self keeps the lower half and the upper half is returned
+    fn split_at(&mut self, addr: VirtAddr) -> VMA {
+        let backing = match self.backing {
+            VMABacking::Anonymous => VMABacking::Anonymous,
+            VMABacking::File { ref inode, offset } => VMABacking::File {
+                inode: inode.clone(),
+                offset: offset + (addr - self.start),
+            },
+        };
+
+        let upper = VMA {
+            start: addr,
+            end: self.end,
+            prot: self.prot,
+            backing,
+        };
+
+        self.end = addr;
+        upper
+    }
+
+    /// reads the initial content of `page` which must belong to this area, the rest of the page is zeroed
+    /// it takes the lock of the inode so it must not be called with the address space locked
+    /// returns None if the file cannot be read
+    pub fn read_page(&self, page: Page) -> Option<Vec<u8>> {
+        let VMABacking::File { ref inode, offset } = self.backing else {
+            return Some(Vec::new());
+        };
+
+        let offset = offset + (page.start_address - self.start);
+        let size = inode.size().ok()?;
+
+        // the rest of the page after the end of the file stays zeroed
+        if offset >= size {
+            return Some(Vec::new());
+        }
+
+        let count = (size - offset).min(PAGE_SIZE);
+        let mut buffer = vec![0; count];
+        inode.read(&mut buffer, offset, count).ok()?;
+        Some(buffer)
+    }
+
+    /// returns true if `other` is the same mapping as this area, used to check that an area didn't change while the address space was unlocked
+    pub fn is_same(&self, other: &VMA) -> bool {
+        let same_backing = match (&self.backing, &other.backing) {
+            (VMABacking::Anonymous, VMABacking::Anonymous) => true,
+            (
+                VMABacking::File { inode, offset },
+                VMABacking::File {
+                    inode: other_inode,
+                    offset: other_offset,
+                },
+            ) => Arc::ptr_eq(inode, other_inode) && offset == other_offset,
+            _ => false,
+        };
+
+        same_backing
+            && self.start == other.start
+            && self.end == other.end
+            && self.prot == other.prot
+    }
+}
+
+/// the virtual memory areas of a process sorted by their start address
+#[derive(Clone)]
+pub struct VMAs {
+    areas: BTreeMap<VirtAddr, VMA>,
+}
+
+impl VMAs {
+    pub const fn new() -> Self {
+        Self {
+            areas: BTreeMap::new(),
+        }
+    }
+
+    /// finds the area containing `addr`
+    pub fn find(&self, addr: VirtAddr) -> Option<&VMA> {
+        let (_, vma) = self.areas.range(..=addr).next_back()?;
+
+        if addr < vma.end {
+            Some(vma)
+        } else {
+            None
+        }
+    }
+
+    /// returns true if any area overlaps with `start`..`end`
+    pub fn overlaps(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        if self.find(start).is_some() {
+            return true;
+        }
+
+        self.areas.range(start..end).next().is_some()
+    }
+
+    /// finds the lowest free range of `len` bytes in the mmap area
+    pub fn find_free(&self, len: usize) -> Option<VirtAddr> {
+        let mut candidate = MMAP_START;
+
+        for vma in self.areas.values() {
+            if vma.end <= candidate {
+                continue;
+            }
+
+            if vma.start >= candidate.checked_add(len)? {
+                break;
+            }
+
+            candidate = vma.end;
+        }
+
+        candidate
+            .checked_add(len)
+            .filter(|end| *end <= MMAP_END)
+            .map(|_| candidate)
+    }
+
+    /// adds `vma`, it must not overlap with any other area
+    pub fn insert(&mut self, vma: VMA) {
+        debug_assert!(!self.overlaps(vma.start, vma.end));
+        self.areas.insert(vma.start, vma);
+    }
+
+    /// splits the area containing `addr` into two areas at `addr`
+    fn split(&mut self, addr: VirtAddr) {
+        let upper = match self.areas.range_mut(..addr).next_back() {
+            Some((_, vma)) if vma.end > addr => vma.split_at(addr),
+            _ => return,
+        };
+
+        self.areas.insert(upper.start, upper);
+    }
+
+    /// removes `start`..`end` from the areas splitting the areas that are partially inside it
+    /// returns the removed (parts of the) areas
+    pub fn remove(&mut self, start: VirtAddr, end: VirtAddr) -> Vec<VMA> {
+        self.split(start);
+        self.split(end);
+
+        let keys: Vec<VirtAddr> = self
+            .areas
+            .range(start..end)
+            .map(|(start, _)| *start)
+            .collect();
+
+        keys.iter()
+            .filter_map(|start| self.areas.remove(start))
+            .collect()
+    }
+}
@@ -0,0 +1,70 @@
+use alloc::collections::vec_deque::VecDeque;
+use spin::Mutex;
+
+use crate::{arch::threading::without_interrupts, scheduler};
+
+use super::{expose::thread_yeild, threads::ThreadStatus, with_scheduler_lock};
+
+/// a queue of threads blocked until something happens
+/// blocked threads are skipped by the scheduler until they are woken up
+pub struct WaitQueue {
+    /// the pid and the tid of every blocked thread
+    waiters: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// blocks the current thread and adds it to `waiters`, the current thread has to yield afterwards
+    /// must be called with interrupts disabled so a wake up cannot be missed
+    fn block(waiters: &mut VecDeque<(u64, u64)>) {
+        let pid = scheduler().current_process().pid;
+        let thread = scheduler().current_thread();
+
+        // a thread terminated by another cpu stays a zombie
+        with_scheduler_lock(|| {
+            if thread.status == ThreadStatus::Running {
+                thread.status = ThreadStatus::Blocked;
+            }
+        });
+        waiters.push_back((pid, thread.tid));
+    }
+
+    /// blocks the current thread until `condition` returns true
+    /// `condition` is checked with interrupts disabled every time the thread is woken up
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        loop {
+            let done = without_interrupts(|| {
+                // the queue is kept locked until the thread is blocked so another cpu cannot wake the queue in between
+                let mut waiters = self.waiters.lock();
+                if condition() {
+                    return true;
+                }
+
+                Self::block(&mut waiters);
+                false
+            });
+
+            if done {
+                return;
+            }
+
+            thread_yeild();
+        }
+    }
+
+    /// wakes up every thread in the queue, threads that exited in the meantime are ignored
+    pub fn wake_all(&self) {
+        without_interrupts(|| {
+            let mut waiters = self.waiters.lock();
+
+            while let Some((pid, tid)) = waiters.pop_front() {
+                scheduler().wake(pid, tid);
+            }
+        })
+    }
+}
@@ -0,0 +1,55 @@
+//! the kernel's notion of time, counted in ticks of the preemption timer since it was enabled
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{scheduler, scheduler_inited};
+
+/// how many times the timer fires each second
+pub const TICKS_PER_SECOND: u64 = 1000;
+pub const NANOS_PER_TICK: u64 = 1_000_000_000 / TICKS_PER_SECOND;
+
+/// the wall-clock time since the unix epoch, kept by the rtc driver
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct TimeSpec {
+    pub seconds: u64,
+    pub nanoseconds: u64,
+}
+
+/// called by the timer interrupt every tick, wakes up the threads whose sleep is over
+pub fn tick() {
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    if scheduler_inited() {
+        scheduler().wake_sleepers(ticks);
+    }
+}
+
+/// the number of ticks since the timer was enabled
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// converts `ms` to ticks rounding up so sleeps are never shorter than asked for
+pub const fn ms_to_ticks(ms: u64) -> u64 {
+    ms.saturating_mul(TICKS_PER_SECOND).div_ceil(1000)
+}
+
+/// the milliseconds since the timer was enabled
+pub fn uptime() -> u64 {
+    ticks() * 1000 / TICKS_PER_SECOND
+}
+
+/// the time since the timer was enabled, it never goes backwards
+pub fn monotonic() -> TimeSpec {
+    let ticks = ticks();
+
+    TimeSpec {
+        seconds: ticks / TICKS_PER_SECOND,
+        nanoseconds: (ticks % TICKS_PER_SECOND) * NANOS_PER_TICK,
+    }
+}
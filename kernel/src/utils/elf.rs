@@ -7,8 +7,8 @@ use macros::display_consts;
 use crate::{
     hddm,
     memory::{
-        copy_to_userspace, frame_allocator,
-        paging::{EntryFlags, IterPage, Page, PageTable, PAGE_SIZE},
+        align_up, copy_to_userspace, frame_allocator,
+        paging::{EntryFlags, IterPage, Page, PageTable, PAGE_SIZE, USERSPACE_END},
     },
     utils::errors::{ErrorStatus, IntoErr},
     VirtAddr,
@@ -20,6 +20,8 @@ pub struct ElfType(u16);
 impl ElfType {
     pub const RELOC: ElfType = Self(1);
     pub const EXE: ElfType = Self(2);
+    /// position independent executables and shared objects
+    pub const DYN: ElfType = Self(3);
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -91,6 +93,13 @@ pub enum ElfError {
     NotAnExecutable,
     MapToError,
     SupportedElfCorrupted,
+    /// the image has a relocation that has to be applied by the kernel but isn't supported
+    UnsupportedRelocation,
+    InterpreterNotFound,
+    /// the image doesn't fit in the area it has to be loaded in
+    TooLarge,
+    /// a page of the image would be both writable and executable
+    WritableAndExecutable,
 }
 
 impl IntoErr for ElfError {
@@ -99,6 +108,8 @@ impl IntoErr for ElfError {
             Self::NotAnExecutable | Self::NotAnElf => ErrorStatus::NotExecutable,
             Self::MapToError => ErrorStatus::MMapError,
             Self::SupportedElfCorrupted => ErrorStatus::Corrupted,
+            Self::InterpreterNotFound => ErrorStatus::NoSuchAFileOrDirectory,
+            Self::TooLarge => ErrorStatus::MMapError,
 
             _ => ErrorStatus::NotSupported,
         }
@@ -119,7 +130,7 @@ impl ElfHeader {
             Err(ElfError::UnsupportedClass)
         } else if self.endianness != ElfIEndianness::LITTLE {
             Err(ElfError::UnsupportedEndianness)
-        } else if ![ElfType::EXE, ElfType::RELOC].contains(&self.kind) {
+        } else if ![ElfType::EXE, ElfType::RELOC, ElfType::DYN].contains(&self.kind) {
             Err(ElfError::UnsupportedKind)
         } else if self.insturction_set != ElfInstrSet::AMD64 {
             Err(ElfError::UnsupportedInsturctionSet)
@@ -166,6 +177,10 @@ pub struct ProgramType(u32);
 impl ProgramType {
     pub const NULL: Self = Self(0);
     pub const LOAD: Self = Self(1);
+    pub const DYNAMIC: Self = Self(2);
+    pub const INTERP: Self = Self(3);
+    pub const PHDR: Self = Self(6);
+    pub const TLS: Self = Self(7);
 }
 
 bitflags! {
@@ -190,12 +205,113 @@ pub struct ProgramHeader {
     pub align: usize,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DynTag(u64);
+#[display_consts]
+impl DynTag {
+    pub const NULL: Self = Self(0);
+    pub const RELA: Self = Self(7);
+    pub const RELASZ: Self = Self(8);
+    pub const RELAENT: Self = Self(9);
+}
+
+/// an entry of the `ProgramType::DYNAMIC` segment
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Dyn {
+    pub tag: DynTag,
+    pub value: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct RelocationType(u32);
+#[display_consts]
+impl RelocationType {
+    pub const NONE: Self = Self(0);
+    pub const RELATIVE: Self = Self(8);
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rela {
+    pub offset: VirtAddr,
+    pub info: u64,
+    pub addend: isize,
+}
+
+impl Rela {
+    #[inline]
+    pub fn kind(&self) -> RelocationType {
+        RelocationType(self.info as u32)
+    }
+}
+
+/// the template the thread local storage of every thread of an image is initialized from
+#[derive(Debug, Clone, Copy)]
+pub struct TLSTemplate {
+    /// where the template is in the loaded image
+    pub vaddr: VirtAddr,
+    /// the size of the initialized part of the template, the rest is zeroed
+    pub file_size: usize,
+    pub size: usize,
+    pub align: usize,
+}
+
+/// an image loaded with `Elf::load_exec`
+#[derive(Debug, Clone, Copy)]
+pub struct LoadedElf<'a> {
+    /// the address the image was loaded at, 0 unless it is position independent
+    pub base: VirtAddr,
+    pub entry_point: VirtAddr,
+    pub program_break: VirtAddr,
+    /// the address of the program headers in the loaded image, 0 if they were not loaded
+    pub program_headers: VirtAddr,
+    pub program_headers_count: usize,
+    /// the path of the interpreter the image has to be loaded by if any
+    pub interpreter: Option<&'a str>,
+    pub tls: Option<TLSTemplate>,
+}
+
 #[derive(Debug)]
 pub struct Elf<'a> {
+    pub bytes: &'a [u8],
     pub header: &'a ElfHeader,
     pub sections: &'a [SectionHeader],
     pub program_headers: &'a [ProgramHeader],
 }
+/// returns `len` bytes of `bytes` starting from `offset` as a slice of `T`
+/// fails with `ElfError::SupportedElfCorrupted` if they are out of bounds or aren't aligned for `T`
+fn bytes_slice<T>(bytes: &[u8], offset: usize, len: usize) -> Result<&[T], ElfError> {
+    let end = offset
+        .checked_add(len)
+        .filter(|end| *end <= bytes.len())
+        .ok_or(ElfError::SupportedElfCorrupted)?;
+
+    let ptr = bytes[offset..end].as_ptr() as *const T;
+    if !ptr.is_aligned() {
+        return Err(ElfError::SupportedElfCorrupted);
+    }
+
+    Ok(unsafe { slice::from_raw_parts(ptr, len / size_of::<T>()) })
+}
+
+/// returns true if a page with `flags` can be both written to and executed
+/// no page of an image is mapped like that otherwise userspace could write code and run it
+fn writable_and_executable(flags: EntryFlags) -> bool {
+    flags.contains(EntryFlags::WRITABLE) && !flags.contains(EntryFlags::NO_EXECUTE)
+}
+
+/// returns `base + offset` which is where an address of the image is loaded if the image was loaded at `base`
+/// the `len` bytes starting there must be in userspace otherwise Err(ElfError::SupportedElfCorrupted) is returned
+fn user_addr(base: VirtAddr, offset: usize, len: usize) -> Result<VirtAddr, ElfError> {
+    base.checked_add(offset)
+        .filter(|addr| {
+            addr.checked_add(len)
+                .is_some_and(|end| end <= USERSPACE_END)
+        })
+        .ok_or(ElfError::SupportedElfCorrupted)
+}
+
 impl<'a> Elf<'a> {
     #[inline]
     pub fn section_names_table(&self) -> &SectionHeader {
@@ -273,105 +389,272 @@ impl<'a> Elf<'a> {
     }
 
     /// creates an elf from a u8 ptr that lives as long as `bytes`
-    pub fn new(bytes: &[u8]) -> Result<Self, ElfError> {
-        if bytes.len() < size_of::<ElfHeader>() {
-            return Err(ElfError::NotAnElf);
-        }
-
-        let bytes_ptr = bytes.as_ptr();
-        let header_ptr = bytes_ptr as *const ElfHeader;
-
-        let header = unsafe {
-            if (*header_ptr).verify() {
-                &*header_ptr
-            } else {
-                return Err(ElfError::NotAnElf);
-            }
+    pub fn new(bytes: &'a [u8]) -> Result<Self, ElfError> {
+        let header = match bytes_slice::<ElfHeader>(bytes, 0, size_of::<ElfHeader>()) {
+            Ok([header]) if header.verify() => header,
+            _ => return Err(ElfError::NotAnElf),
         };
 
         header.supported()?;
 
-        assert_eq!(
-            size_of::<SectionHeader>(),
-            header.section_table_entry_size as usize
-        );
-
-        assert_eq!(
-            size_of::<ProgramHeader>(),
-            header.program_headers_table_entry_size as usize
-        );
-
-        if bytes.len() < header.section_header_table_offset
-            || bytes.len() < header.program_headers_table_offset
+        if size_of::<SectionHeader>() != header.section_table_entry_size as usize
+            || size_of::<ProgramHeader>() != header.program_headers_table_entry_size as usize
         {
             return Err(ElfError::SupportedElfCorrupted);
         }
 
-        let section_header_table_ptr =
-            unsafe { bytes_ptr.add(header.section_header_table_offset) } as *const SectionHeader;
-
-        // TODO: instead make an nth_section function and a section_len function or whateve
-        // because section_header_ptr may be unaligned same for programe headers
-        assert!(section_header_table_ptr.is_aligned());
-
-        let section_header_table = unsafe {
-            slice::from_raw_parts(
-                section_header_table_ptr,
-                header.section_table_entries as usize,
-            )
-        };
+        let section_header_table = bytes_slice(
+            bytes,
+            header.section_header_table_offset,
+            header.section_table_entries as usize * size_of::<SectionHeader>(),
+        )?;
 
         let program_headers_table = if header.program_headers_table_offset != 0 {
-            let program_headers_table_ptr =
-                unsafe { bytes_ptr.add(header.program_headers_table_offset) }
-                    as *const ProgramHeader;
-            assert!(program_headers_table_ptr.is_aligned());
-            unsafe {
-                slice::from_raw_parts(
-                    program_headers_table_ptr,
-                    header.program_headers_table_entries_number as usize,
-                )
-            }
+            bytes_slice(
+                bytes,
+                header.program_headers_table_offset,
+                header.program_headers_table_entries_number as usize * size_of::<ProgramHeader>(),
+            )?
         } else {
             &[]
         };
 
         Ok(Self {
+            bytes,
             header,
             sections: section_header_table,
             program_headers: program_headers_table,
         })
     }
 
-    /// loads an executable ELF, maps, and copies it to `page_table`.
-    /// returns the program break on success.
-    pub fn load_exec(&self, page_table: &mut PageTable) -> Result<VirtAddr, ElfError> {
-        if self.header.kind != ElfType::EXE {
-            return Err(ElfError::NotAnExecutable);
+    /// returns `len` bytes of the file starting from `offset` as a slice of `T`
+    fn file_slice<T>(&self, offset: usize, len: usize) -> Result<&'a [T], ElfError> {
+        bytes_slice(self.bytes, offset, len)
+    }
+
+    /// returns the `ProgramType::LOAD` segment `vaddr` is loaded in
+    fn segment_containing(&self, vaddr: VirtAddr) -> Option<&'a ProgramHeader> {
+        self.program_headers.iter().find(|header| {
+            header.ptype == ProgramType::LOAD
+                && vaddr >= header.vaddr
+                && header
+                    .vaddr
+                    .checked_add(header.memz)
+                    .is_some_and(|end| vaddr < end)
+        })
+    }
+
+    /// the path of the interpreter requested by the `ProgramType::INTERP` segment if any
+    pub fn interpreter(&self) -> Result<Option<&'a str>, ElfError> {
+        let Some(header) = self
+            .program_headers
+            .iter()
+            .find(|header| header.ptype == ProgramType::INTERP)
+        else {
+            return Ok(None);
+        };
+
+        let bytes = self.file_slice::<u8>(header.offset, header.filez)?;
+        CStr::from_bytes_until_nul(bytes)
+            .ok()
+            .and_then(|path| path.to_str().ok())
+            .map(Some)
+            .ok_or(ElfError::SupportedElfCorrupted)
+    }
+
+    /// the thread local storage template from the `ProgramType::TLS` segment if the image was loaded at `base`
+    pub fn tls_template(&self, base: VirtAddr) -> Result<Option<TLSTemplate>, ElfError> {
+        let Some(header) = self
+            .program_headers
+            .iter()
+            .find(|header| header.ptype == ProgramType::TLS)
+        else {
+            return Ok(None);
+        };
+
+        let align = header.align.max(1);
+        if !align.is_power_of_two() || header.filez > header.memz {
+            return Err(ElfError::SupportedElfCorrupted);
+        }
+
+        // the template must be loaded with the rest of the image
+        // `segment_containing` already checked that the end of the segment doesn't overflow
+        let loaded = self
+            .segment_containing(header.vaddr)
+            .is_some_and(|segment| {
+                header
+                    .vaddr
+                    .checked_add(header.filez)
+                    .is_some_and(|end| end <= segment.vaddr + segment.memz)
+            });
+
+        if header.filez != 0 && !loaded {
+            return Err(ElfError::SupportedElfCorrupted);
+        }
+
+        Ok(Some(TLSTemplate {
+            vaddr: user_addr(base, header.vaddr, header.memz)?,
+            file_size: header.filez,
+            size: header.memz,
+            align,
+        }))
+    }
+
+    /// the address of the program headers after loading the image at `base`, 0 if they are not loaded
+    fn loaded_program_headers(&self, base: VirtAddr) -> Result<VirtAddr, ElfError> {
+        let offset = self.header.program_headers_table_offset;
+        let size = self.program_headers.len() * size_of::<ProgramHeader>();
+
+        if let Some(header) = self
+            .program_headers
+            .iter()
+            .find(|header| header.ptype == ProgramType::PHDR)
+        {
+            return user_addr(base, header.vaddr, size);
+        }
+
+        let Some(header) = self.program_headers.iter().find(|header| {
+            header.ptype == ProgramType::LOAD
+                && offset >= header.offset
+                && header
+                    .offset
+                    .checked_add(header.filez)
+                    .is_some_and(|end| offset < end)
+        }) else {
+            return Ok(0);
+        };
+
+        let vaddr = header
+            .vaddr
+            .checked_add(offset - header.offset)
+            .ok_or(ElfError::SupportedElfCorrupted)?;
+        user_addr(base, vaddr, size)
+    }
+
+    /// applies the relocations of the image loaded at `base` to `page_table`
+    /// only `RelocationType::RELATIVE` is applied, the rest are left to the interpreter if the image has one
+    fn relocate(
+        &self,
+        page_table: &mut PageTable,
+        base: VirtAddr,
+        interpreted: bool,
+    ) -> Result<(), ElfError> {
+        let Some(dynamic) = self
+            .program_headers
+            .iter()
+            .find(|header| header.ptype == ProgramType::DYNAMIC)
+        else {
+            return Ok(());
+        };
+
+        let mut rela = None;
+        let mut rela_size = 0;
+        let mut rela_entry_size = size_of::<Rela>();
+
+        for entry in self.file_slice::<Dyn>(dynamic.offset, dynamic.filez)? {
+            match entry.tag {
+                DynTag::NULL => break,
+                DynTag::RELA => rela = Some(entry.value),
+                DynTag::RELASZ => rela_size = entry.value,
+                DynTag::RELAENT => rela_entry_size = entry.value,
+                _ => {}
+            }
+        }
+
+        let Some(rela) = rela else {
+            return Ok(());
+        };
+
+        if rela_entry_size != size_of::<Rela>() {
+            return Err(ElfError::SupportedElfCorrupted);
         }
 
+        // the relocation table is part of a loaded segment, so we read it from the file
+        let segment = self
+            .segment_containing(rela)
+            .ok_or(ElfError::SupportedElfCorrupted)?;
+        let offset = segment
+            .offset
+            .checked_add(rela - segment.vaddr)
+            .ok_or(ElfError::SupportedElfCorrupted)?;
+
+        for relocation in self.file_slice::<Rela>(offset, rela_size)? {
+            match relocation.kind() {
+                RelocationType::NONE => {}
+                RelocationType::RELATIVE => {
+                    // the relocated value must be inside of the image
+                    let fits = self
+                        .segment_containing(relocation.offset)
+                        .is_some_and(|segment| {
+                            relocation
+                                .offset
+                                .checked_add(size_of::<usize>())
+                                .is_some_and(|end| end <= segment.vaddr + segment.memz)
+                        });
+
+                    if !fits {
+                        return Err(ElfError::SupportedElfCorrupted);
+                    }
+
+                    let addr = user_addr(base, relocation.offset, size_of::<usize>())?;
+                    let value = base.wrapping_add_signed(relocation.addend);
+                    copy_to_userspace(page_table, addr, &value.to_ne_bytes());
+                }
+                _ if interpreted => {}
+                _ => return Err(ElfError::UnsupportedRelocation),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// loads an executable ELF, maps, and copies it to `page_table`.
+    /// position independent executables are loaded at `base` which must be page aligned, it is ignored otherwise.
+    /// the interpreter of the image (if any) has to be loaded separately.
+    pub fn load_exec(
+        &self,
+        page_table: &mut PageTable,
+        base: VirtAddr,
+    ) -> Result<LoadedElf<'a>, ElfError> {
+        let base = match self.header.kind {
+            ElfType::EXE => 0,
+            ElfType::DYN => base,
+            _ => return Err(ElfError::NotAnExecutable),
+        };
+
+        debug_assert_eq!(base % PAGE_SIZE, 0);
+        let interpreter = self.interpreter()?;
+        let tls = self.tls_template(base)?;
+
         let mut program_break = 0;
         for header in self.program_headers {
             if header.ptype != ProgramType::LOAD {
                 continue;
             }
 
-            let mut entry_flags = EntryFlags::PRESENT | EntryFlags::USER_ACCESSIBLE;
-
-            if header.flags.contains(ProgramFlags::READ) {
-                entry_flags |= EntryFlags::empty();
+            let vaddr = user_addr(base, header.vaddr, header.memz)?;
+            let file = self.file_slice::<u8>(header.offset, header.filez)?;
+            if header.filez > header.memz {
+                return Err(ElfError::SupportedElfCorrupted);
             }
 
+            // present pages are always readable on x86_64 so there is nothing to do for `ProgramFlags::READ`
+            let mut entry_flags = EntryFlags::PRESENT | EntryFlags::USER_ACCESSIBLE;
+
             if header.flags.contains(ProgramFlags::WRITE) {
                 entry_flags |= EntryFlags::WRITABLE;
             }
 
-            if header.flags.contains(ProgramFlags::EXEC) {
-                entry_flags |= EntryFlags::WRITABLE;
+            if !header.flags.contains(ProgramFlags::EXEC) {
+                entry_flags |= EntryFlags::NO_EXECUTE;
             }
 
-            let start_page = Page::containing_address(header.vaddr);
-            let end_page = Page::containing_address(header.vaddr + header.memz + PAGE_SIZE);
+            if writable_and_executable(entry_flags) {
+                return Err(ElfError::WritableAndExecutable);
+            }
+
+            let start_page = Page::containing_address(vaddr);
+            let end_page = Page::containing_address(align_up(vaddr + header.memz, PAGE_SIZE));
             let iter = IterPage {
                 start: start_page,
                 end: end_page,
@@ -379,6 +662,24 @@ impl<'a> Elf<'a> {
 
             unsafe {
                 for page in iter {
+                    // segments may share a page, which then allows everything both segments allow
+                    // unless a segment is writable while the other is executable
+                    if let Some(entry) = page_table.get_entry(page).filter(|e| e.is_mapped()) {
+                        let old_flags = entry.flags();
+                        let mut flags = old_flags | entry_flags;
+
+                        if !(old_flags & entry_flags).contains(EntryFlags::NO_EXECUTE) {
+                            flags.remove(EntryFlags::NO_EXECUTE);
+                        }
+
+                        if writable_and_executable(flags) {
+                            return Err(ElfError::WritableAndExecutable);
+                        }
+
+                        entry.set(flags, entry.frame().unwrap().start_address);
+                        continue;
+                    }
+
                     let frame = frame_allocator::allocate_frame().ok_or(ElfError::MapToError)?;
 
                     page_table
@@ -392,10 +693,7 @@ impl<'a> Elf<'a> {
                     );
                     slice.fill(0);
                 }
-                let file_start = (self.header as *const ElfHeader as *const u8).add(header.offset);
-                let file = slice::from_raw_parts(file_start, header.filez);
-
-                copy_to_userspace(page_table, header.vaddr, file);
+                copy_to_userspace(page_table, vaddr, file);
                 // let mut size_to_copy = if index < pages_required - 1 {
                 //     PAGE_SIZE
                 // } else {
@@ -419,9 +717,22 @@ impl<'a> Elf<'a> {
                 // );
                 // mem[..size_to_copy].copy_from_slice(&file[start..size_to_copy + start]);
             }
-            program_break = header.vaddr + header.memz;
+            program_break = program_break.max(vaddr + header.memz);
         }
-        Ok(program_break)
+
+        if self.header.kind == ElfType::DYN {
+            self.relocate(page_table, base, interpreter.is_some())?;
+        }
+
+        Ok(LoadedElf {
+            base,
+            entry_point: user_addr(base, self.header.entry_point, 0)?,
+            program_break,
+            program_headers: self.loaded_program_headers(base)?,
+            program_headers_count: self.program_headers.len(),
+            interpreter,
+            tls,
+        })
     }
 
     // pub fn debug(&self) {
@@ -26,13 +26,17 @@ pub enum ErrorStatus {
     NotADirectory,
     AlreadyExists,
     NotExecutable,
-    // would be useful when i add remove related operations to the vfs
+    // removing a directory that still has entries
     DirectoryNotEmpty,
     // Generic premissions(protection) related error
     MissingPermissions,
     // memory allocations and mapping error, most likely that memory is full
     MMapError,
     Busy,
+    // moving an entry from one drive to another
+    CrossDrive,
+    // for example a seek to before the start of a file
+    InvaildArgument,
     // errors sent by processes
     NotEnoughArguments,
 }
@@ -1,7 +1,8 @@
 use crate::{
+    arch::smp,
     limine::MEMORY_SIZE,
     memory::{frame_allocator, paging::PAGE_SIZE},
-    scheduler,
+    scheduler, time,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -10,6 +11,11 @@ pub struct SysInfo {
     pub total_mem: usize,
     pub used_mem: usize,
     pub processes_count: usize,
+    /// milliseconds since boot
+    pub uptime: u64,
+    pub cpu_count: usize,
+    /// context switches done by every cpu since boot
+    pub context_switches: u64,
 }
 
 #[no_mangle]
@@ -20,5 +26,8 @@ pub fn info(sysinfo: &mut SysInfo) {
         total_mem: *MEMORY_SIZE,
         used_mem,
         processes_count: scheduler().processes_count,
+        uptime: time::uptime(),
+        cpu_count: smp::cpu_count(),
+        context_switches: scheduler().context_switches(),
     }
 }
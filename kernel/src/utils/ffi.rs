@@ -1,6 +1,93 @@
+use alloc::{string::String, vec::Vec};
+use core::mem::MaybeUninit;
+
 use super::errors::{ErrorStatus, ErrorStatusResult};
+use crate::arch::usercopy::{copy_from_user, copy_to_user};
+use crate::threading::expose::can_access;
 
 ///! safe FFI types to make it easier to interact with userspace
+///! every pointer coming from userspace is checked to be accessible by the caller and what it points to is copied before it is used
+
+/// checks that `ptr` is aligned and that the caller of the current syscall can access `len` values at it
+/// `write` is wether or not they are going to be written to
+fn is_accessible<T>(ptr: *const T, len: usize, write: bool) -> bool {
+    if !ptr.is_aligned() {
+        return false;
+    }
+
+    let Some(size) = len.checked_mul(size_of::<T>()) else {
+        return false;
+    };
+
+    can_access(ptr as usize, size, write)
+}
+
+/// copies the value at `ptr` which was checked with `is_accessible` out of userspace memory
+fn read_user<T: Copy>(ptr: *const T) -> ErrorStatusResult<T> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let dest =
+        unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+
+    if copy_from_user(dest, ptr as usize) {
+        ErrorStatusResult::ok(unsafe { value.assume_init() })
+    } else {
+        ErrorStatusResult::err(ErrorStatus::InvaildPtr)
+    }
+}
+
+/// copies `value` to `ptr` which was checked with `is_accessible` in userspace memory
+fn write_user<T: Copy>(ptr: *mut T, value: T) -> ErrorStatusResult<()> {
+    let src =
+        unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+
+    if copy_to_user(ptr as usize, src) {
+        ErrorStatusResult::ok(())
+    } else {
+        ErrorStatusResult::err(ErrorStatus::InvaildPtr)
+    }
+}
+
+/// copies `len` values at `ptr` which was checked with `is_accessible` out of userspace memory into a kernel buffer
+/// returns an empty buffer if the pointer is null
+fn read_user_slice<T: Copy>(ptr: *const T, len: usize) -> ErrorStatusResult<Vec<T>> {
+    if ptr.is_null() {
+        return ErrorStatusResult::ok(Vec::new());
+    }
+
+    let mut values = Vec::<T>::new();
+    // the length was checked against the mapped memory of the caller but the heap may still be too small for it
+    if values.try_reserve_exact(len).is_err() {
+        return ErrorStatusResult::err(ErrorStatus::MMapError);
+    }
+
+    let dest = unsafe {
+        core::slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, len * size_of::<T>())
+    };
+
+    if copy_from_user(dest, ptr as usize) {
+        unsafe { values.set_len(len) };
+        ErrorStatusResult::ok(values)
+    } else {
+        ErrorStatusResult::err(ErrorStatus::InvaildPtr)
+    }
+}
+
+/// copies `values` to `ptr` which was checked with `is_accessible` in userspace memory
+fn write_user_slice<T: Copy>(ptr: *mut T, values: &[T]) -> ErrorStatusResult<()> {
+    if ptr.is_null() {
+        return ErrorStatusResult::ok(());
+    }
+
+    let src = unsafe {
+        core::slice::from_raw_parts(values.as_ptr() as *const u8, core::mem::size_of_val(values))
+    };
+
+    if copy_to_user(ptr as usize, src) {
+        ErrorStatusResult::ok(())
+    } else {
+        ErrorStatusResult::err(ErrorStatus::InvaildPtr)
+    }
+}
 
 /// a Nullable refrence to a value
 /// if null it is a None if Some it is a valid reference
@@ -25,6 +112,11 @@ impl<'a, T> Optional<T> {
         self.value.is_null()
     }
 
+    /// the pointer as it was passed without checking it
+    pub const fn as_ptr(&self) -> *const T {
+        self.value
+    }
+
     // pub fn unwrap(self) -> &'a mut T {
     //     if self.is_none() {
     //         panic!("called `Option::unwrap()` on a `None` value")
@@ -37,7 +129,7 @@ impl<'a, T> Optional<T> {
         &mut *self.value
     }
 
-    pub fn into_option(self) -> Option<&'a mut T> {
+    fn into_option(self) -> Option<&'a mut T> {
         if self.is_none() {
             None
         } else {
@@ -45,6 +137,16 @@ impl<'a, T> Optional<T> {
         }
     }
 
+    /// returns None if the pointer is null
+    /// fails with `ErrorStatus::InvaildPtr` if the pointer is not null and the caller cannot write to it
+    pub fn get(self) -> ErrorStatusResult<Option<&'a mut T>> {
+        if !self.is_none() && !is_accessible(self.value, 1, true) {
+            ErrorStatusResult::err(ErrorStatus::InvaildPtr)
+        } else {
+            ErrorStatusResult::ok(self.into_option())
+        }
+    }
+
     pub fn from_option(value: Option<&mut T>) -> Self {
         match value {
             Some(value) => Self::new(value),
@@ -53,6 +155,25 @@ impl<'a, T> Optional<T> {
     }
 }
 
+impl<T: Copy> Optional<T> {
+    /// copies the value out of userspace memory, see `Required::read`
+    /// returns None if the pointer is null
+    pub fn read(self) -> ErrorStatusResult<Option<T>> {
+        match self.get()? {
+            Some(value) => ErrorStatusResult::ok(Some(read_user(value)?)),
+            None => ErrorStatusResult::ok(None),
+        }
+    }
+
+    /// copies `value` back to userspace memory after it was `read`, does nothing if the pointer is null
+    pub fn write(self, value: Option<T>) -> ErrorStatusResult<()> {
+        match value {
+            Some(value) if !self.is_none() => write_user(self.value, value),
+            _ => ErrorStatusResult::ok(()),
+        }
+    }
+}
+
 impl<'a, T> From<Option<&'a mut T>> for Optional<T> {
     fn from(value: Option<&'a mut T>) -> Self {
         Self::from_option(value)
@@ -72,7 +193,7 @@ impl<'a, T> From<&'a mut T> for Optional<T> {
 }
 
 /// a slice of values
-/// if into_slice is called on a null pointer it will return an empty slice
+/// if read is called on a null pointer it will return an empty buffer
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct Slice<T> {
@@ -80,36 +201,33 @@ pub struct Slice<T> {
     len: usize,
 }
 
-impl<'a, T> Slice<T> {
-    /// ptr must be aligned
-    /// panics if ptr is invaild
+impl<T> Slice<T> {
+    /// ptr must be aligned and readable by the caller unless it is null
     pub fn new(ptr: *const T, len: usize) -> ErrorStatusResult<Self> {
-        if !(ptr.is_aligned() || ptr.is_null()) {
+        if !(ptr.is_null() || is_accessible(ptr, len, false)) {
             ErrorStatusResult::err(ErrorStatus::InvaildPtr)
         } else {
             ErrorStatusResult::ok(Self { ptr, len })
         }
     }
 
-    /// converts Slice to a slice
-    /// returns an empty slice if the pointer is null
-    #[inline(always)]
-    pub fn into_slice(self) -> &'a [T] {
-        if self.ptr.is_null() {
-            return &[];
-        }
-        unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+    /// checks a Slice that was passed inside of another value
+    pub fn validate(self) -> ErrorStatusResult<Self> {
+        Self::new(self.ptr, self.len)
     }
 }
 
-impl<'a, T> Into<&'a [T]> for Slice<T> {
-    fn into(self) -> &'a [T] {
-        self.into_slice()
+impl<T: Copy> Slice<T> {
+    /// copies the values out of userspace memory, the kernel never uses userspace memory directly
+    /// because another thread of the caller may unmap it at any time
+    /// fails with `ErrorStatus::InvaildPtr` instead of faulting if the memory is gone by the time it is copied
+    pub fn read(self) -> ErrorStatusResult<Vec<T>> {
+        read_user_slice(self.ptr, self.len)
     }
 }
 
 /// a mutable slice of values
-/// if into_slice is called on a null pointer it will return an empty slice
+/// if read is called on a null pointer it will return an empty buffer
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct SliceMut<T> {
@@ -117,77 +235,55 @@ pub struct SliceMut<T> {
     len: usize,
 }
 
-impl<'a, T> SliceMut<T> {
-    /// ptr must be aligned
-    /// panics if ptr is invaild
+impl<T> SliceMut<T> {
+    /// ptr must be aligned and writable by the caller unless it is null
     pub fn new(ptr: *mut T, len: usize) -> ErrorStatusResult<Self> {
-        if !(ptr.is_aligned() || ptr.is_null()) {
+        if !(ptr.is_null() || is_accessible(ptr, len, true)) {
             ErrorStatusResult::err(ErrorStatus::InvaildPtr)
         } else {
             ErrorStatusResult::ok(Self { ptr, len })
         }
     }
-
-    /// converts Slice to a slice
-    /// returns an empty slice if the pointer is null
-    #[inline(always)]
-    pub fn into_slice(self) -> &'a mut [T] {
-        if self.ptr.is_null() {
-            return &mut [];
-        }
-
-        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.len) }
-    }
 }
 
-impl<'a, T> Into<&'a mut [T]> for SliceMut<T> {
-    fn into(self) -> &'a mut [T] {
-        self.into_slice()
+impl<T: Copy> SliceMut<T> {
+    /// copies the values out of userspace memory, see `Slice::read`
+    /// they are copied so the values the kernel doesn't change are written back as they were
+    pub fn read(self) -> ErrorStatusResult<Vec<T>> {
+        read_user_slice(self.ptr, self.len)
     }
-}
 
-impl SliceMut<u8> {
-    /// converts the slice to a str which is accepted by the kernel
-    /// may panic if the slice is not valid utf8 in the future
-    pub fn into_str<'a>(self) -> &'a str {
-        unsafe { core::str::from_utf8_unchecked(self.into_slice()) }
+    /// copies `values` back to userspace memory after they were `read`
+    pub fn write(self, values: &[T]) -> ErrorStatusResult<()> {
+        debug_assert_eq!(values.len(), self.len);
+        write_user_slice(self.ptr, values)
     }
 }
 
 impl Slice<u8> {
-    /// converts the slice to a str which is accepted by the kernel
-    /// may panic if the slice is not valid utf8 in the future
-    pub fn into_str<'a>(self) -> &'a str {
-        unsafe { core::str::from_utf8_unchecked(self.into_slice()) }
-    }
-}
-
-impl<'a> Into<&'a str> for Slice<u8> {
-    fn into(self) -> &'a str {
-        self.into_str()
-    }
-}
-
-impl<'a> Into<&'a str> for SliceMut<u8> {
-    fn into(self) -> &'a str {
-        self.into_str()
+    /// copies the slice out of userspace memory as a str which is accepted by the kernel
+    /// fails with `ErrorStatus::InvaildStr` if the slice is not vaild utf8
+    pub fn read_str(self) -> ErrorStatusResult<String> {
+        match String::from_utf8(self.read()?) {
+            Ok(str) => ErrorStatusResult::ok(str),
+            Err(_) => ErrorStatusResult::err(ErrorStatus::InvaildStr),
+        }
     }
 }
 
-impl SliceMut<Slice<u8>> {
-    /// converts the slice to a slice of strs which is accepted by the kernel
-    /// may panic if the slice is not valid utf8 in the future
-    pub fn into_str_slice<'a>(self) -> &'a [&'a str] {
-        let slice = self.into_slice();
-        let double_slice = unsafe { &mut *(self.into_slice() as *const _ as *mut [&str]) };
-
-        for (i, item) in slice.iter().enumerate() {
-            double_slice[i] = item.into_str();
+impl Slice<Slice<u8>> {
+    /// copies the slice and the strs it points to out of userspace memory
+    /// fails if one of the strs is not readable by the caller or is not vaild utf8
+    pub fn read_str_slice(self) -> ErrorStatusResult<Vec<String>> {
+        let mut strs = Vec::new();
+        for item in self.read()? {
+            strs.push(item.validate()?.read_str()?);
         }
 
-        double_slice
+        ErrorStatusResult::ok(strs)
     }
 }
+
 /// a non-Nullable muttable refrence to a value
 /// use .get() to get the value
 #[derive(Debug, Clone, Copy)]
@@ -197,8 +293,13 @@ pub struct RequiredMut<T> {
 }
 
 impl<'a, T> RequiredMut<T> {
+    /// the pointer as it was passed without checking it
+    pub const fn as_ptr(&self) -> *const T {
+        self.value
+    }
+
     pub fn get(self) -> ErrorStatusResult<&'a mut T> {
-        if self.value.is_null() || !self.value.is_aligned() {
+        if self.value.is_null() || !is_accessible(self.value, 1, true) {
             ErrorStatusResult::err(ErrorStatus::InvaildPtr)
         } else {
             ErrorStatusResult::ok(unsafe { &mut *self.value })
@@ -206,6 +307,19 @@ impl<'a, T> RequiredMut<T> {
     }
 }
 
+impl<T: Copy> RequiredMut<T> {
+    /// copies the value out of userspace memory, see `Required::read`
+    pub fn read(self) -> ErrorStatusResult<T> {
+        let value = self.get()?;
+        read_user(value)
+    }
+
+    /// copies `value` back to userspace memory after it was `read`
+    pub fn write(self, value: T) -> ErrorStatusResult<()> {
+        write_user(self.value, value)
+    }
+}
+
 /// a non-Nullable refrence to a value
 /// use .get() to get the value
 #[derive(Debug, Clone, Copy)]
@@ -215,11 +329,26 @@ pub struct Required<T> {
 }
 
 impl<'a, T> Required<T> {
+    /// the pointer as it was passed without checking it
+    pub const fn as_ptr(&self) -> *const T {
+        self.value
+    }
+
     pub fn get(self) -> ErrorStatusResult<&'a T> {
-        if self.value.is_null() || !self.value.is_aligned() {
+        if self.value.is_null() || !is_accessible(self.value, 1, false) {
             ErrorStatusResult::err(ErrorStatus::InvaildPtr)
         } else {
             ErrorStatusResult::ok(unsafe { &*self.value })
         }
     }
 }
+
+impl<T: Copy> Required<T> {
+    /// copies the value out of userspace memory
+    /// prefer this over `get` for values the kernel checks before using, userspace may change them in between otherwise
+    /// fails with `ErrorStatus::InvaildPtr` instead of faulting if the memory is gone by the time it is copied
+    pub fn read(self) -> ErrorStatusResult<T> {
+        let value = self.get()?;
+        read_user(value)
+    }
+}
@@ -12,7 +12,7 @@ pub const DIR = extern struct {
     dir_ri: isize,
 
     pub fn open(path: []const u8) errors.Error!*DIR {
-        const dir_ri = try io.zopen(path);
+        const dir_ri = try io.zopen(path, .{ .read = true });
 
         const ri = try io.zdiriter_open(dir_ri);
 
@@ -293,22 +293,14 @@ pub const FILE = extern struct {
     mode: ModeFlags,
 
     pub fn open(filename: []const u8, mode: ModeFlags) errors.Error!*FILE {
-        const fd = io.zopen(filename) catch |err| blk: {
-            switch (err) {
-                error.NoSuchAFileOrDirectory => if (mode.write or mode.append) {
-                    try io.zcreate(filename);
-                    break :blk try io.zopen(filename);
-                } else return err,
-                else => return err,
-            }
-        };
-
-        if (mode.write) {
-            if (mode.access_flag) {
-                return error.AlreadyExists;
-            }
-            _ = try io.zwrite(fd, "");
-        }
+        const fd = try io.zopen(filename, .{
+            .read = mode.read or mode.extended,
+            .write = mode.write or mode.append or mode.extended,
+            .create = mode.write or mode.append,
+            .truncate = mode.write,
+            .append = mode.append,
+            .exclusive = mode.access_flag,
+        });
 
         const file = stdlib.zmalloc(FILE).?;
         file.fd = fd;
@@ -3,10 +3,11 @@ const errors = @import("errno.zig");
 const stdio = @import("../stdio.zig");
 pub const raw = @import("raw.zig");
 
-pub fn open(path: *const u8, len: usize) isize {
+/// reading or writing the file fails unless `flags` has `read` or `write`
+pub fn open(path: *const u8, len: usize, flags: raw.OpenFlags) isize {
     var fd: usize = undefined;
 
-    const err = syscalls.open(path, len, &fd);
+    const err = syscalls.open(@ptrCast(path), len, flags, &fd);
     if (err != 0) {
         errors.errno = @truncate(err);
         return -1;
@@ -74,7 +75,7 @@ pub export fn fstat(ri: isize) ?*raw.DirEntry {
 pub export fn read(fd: isize, ptr: *u8, size: usize) isize {
     var bytes_read: usize = undefined;
 
-    const err = syscalls.read(@bitCast(fd), ptr, size, &bytes_read);
+    const err = syscalls.read(@bitCast(fd), @ptrCast(ptr), size, &bytes_read);
     if (err != 0) {
         errors.errno = @truncate(err);
         return -1;
@@ -83,7 +84,7 @@ pub export fn read(fd: isize, ptr: *u8, size: usize) isize {
 }
 
 pub export fn write(fd: isize, ptr: *const u8, size: usize) isize {
-    const err = syscalls.write(@bitCast(fd), ptr, size);
+    const err = syscalls.write(@bitCast(fd), @ptrCast(ptr), size);
     if (err != 0) {
         errors.errno = @truncate(err);
         return -1;
@@ -91,8 +92,46 @@ pub export fn write(fd: isize, ptr: *const u8, size: usize) isize {
     return 0;
 }
 
+pub const SEEK_SET: c_int = 0;
+pub const SEEK_CUR: c_int = 1;
+pub const SEEK_END: c_int = 2;
+
+/// moves the position reads and writes of `fd` start at, returns the new position or -1 on error
+pub export fn seek(fd: isize, offset: isize, whence: c_int) isize {
+    var new_offset: usize = undefined;
+
+    const err = syscalls.seek(@bitCast(fd), offset, @intCast(whence), &new_offset);
+    if (err != 0) {
+        errors.errno = @truncate(err);
+        return -1;
+    }
+    return @bitCast(new_offset);
+}
+
+/// reads at `offset` without moving the position of `fd`
+pub export fn pread(fd: isize, ptr: *u8, size: usize, offset: usize) isize {
+    var bytes_read: usize = undefined;
+
+    const err = syscalls.pread(@bitCast(fd), @ptrCast(ptr), size, offset, &bytes_read);
+    if (err != 0) {
+        errors.errno = @truncate(err);
+        return -1;
+    }
+    return @bitCast(bytes_read);
+}
+
+/// writes at `offset` without moving the position of `fd`
+pub export fn pwrite(fd: isize, ptr: *const u8, size: usize, offset: usize) isize {
+    const err = syscalls.pwrite(@bitCast(fd), @ptrCast(ptr), size, offset);
+    if (err != 0) {
+        errors.errno = @truncate(err);
+        return -1;
+    }
+    return @bitCast(size);
+}
+
 pub export fn create(path: *const u8, len: usize) isize {
-    const err = syscalls.create(path, len);
+    const err = syscalls.create(@ptrCast(path), len);
     if (err != 0) {
         errors.errno = @truncate(err);
         return -1;
@@ -102,7 +141,29 @@ pub export fn create(path: *const u8, len: usize) isize {
 }
 
 pub export fn createdir(path: *const u8, len: usize) isize {
-    const err = syscalls.createdir(path, len);
+    const err = syscalls.createdir(@ptrCast(path), len);
+    if (err != 0) {
+        errors.errno = @truncate(err);
+        return -1;
+    }
+
+    return 0;
+}
+
+/// moves `old` to `new` replacing `new` if it exists, fails with CrossDrive if they are on different drives
+pub export fn rename(old: *const u8, old_len: usize, new: *const u8, new_len: usize) isize {
+    const err = syscalls.rename(@ptrCast(old), old_len, @ptrCast(new), new_len);
+    if (err != 0) {
+        errors.errno = @truncate(err);
+        return -1;
+    }
+
+    return 0;
+}
+
+/// fails with Busy if the file is still open
+pub export fn remove(path: *const u8, len: usize) isize {
+    const err = syscalls.remove(@ptrCast(path), len);
     if (err != 0) {
         errors.errno = @truncate(err);
         return -1;
@@ -110,8 +171,19 @@ pub export fn createdir(path: *const u8, len: usize) isize {
 
     return 0;
 }
-pub fn zopen(path: []const u8) errors.Error!isize {
-    const fd = open(@ptrCast(path.ptr), path.len);
+
+/// the directory has to be empty and not open
+pub export fn rmdir(path: *const u8, len: usize) isize {
+    const err = syscalls.rmdir(@ptrCast(path), len);
+    if (err != 0) {
+        errors.errno = @truncate(err);
+        return -1;
+    }
+
+    return 0;
+}
+pub fn zopen(path: []const u8, flags: raw.OpenFlags) errors.Error!isize {
+    const fd = open(@ptrCast(path.ptr), path.len, flags);
     if (fd == -1) return errors.geterr();
     return fd;
 }
@@ -154,6 +226,24 @@ pub fn zwrite(fd: isize, buffer: []const u8) errors.Error!usize {
     return @bitCast(bytes_wrote);
 }
 
+pub fn zseek(fd: isize, offset: isize, whence: c_int) errors.Error!usize {
+    const new_offset = seek(fd, offset, whence);
+    if (new_offset == -1) return errors.geterr();
+    return @bitCast(new_offset);
+}
+
+pub fn zpread(fd: isize, buffer: []u8, offset: usize) errors.Error!usize {
+    const bytes_read = pread(fd, @ptrCast(buffer.ptr), buffer.len, offset);
+    if (bytes_read == -1) return errors.geterr();
+    return @bitCast(bytes_read);
+}
+
+pub fn zpwrite(fd: isize, buffer: []const u8, offset: usize) errors.Error!usize {
+    const bytes_wrote = pwrite(fd, @ptrCast(buffer.ptr), buffer.len, offset);
+    if (bytes_wrote == -1) return errors.geterr();
+    return @bitCast(bytes_wrote);
+}
+
 pub fn zcreate(path: []const u8) errors.Error!void {
     const err = create(@ptrCast(path.ptr), path.len);
     if (err == -1) return errors.geterr();
@@ -164,6 +254,21 @@ pub fn zcreatedir(path: []const u8) errors.Error!void {
     if (err == -1) return errors.geterr();
 }
 
+pub fn zrename(old: []const u8, new: []const u8) errors.Error!void {
+    const err = rename(@ptrCast(old.ptr), old.len, @ptrCast(new.ptr), new.len);
+    if (err == -1) return errors.geterr();
+}
+
+pub fn zremove(path: []const u8) errors.Error!void {
+    const err = remove(@ptrCast(path.ptr), path.len);
+    if (err == -1) return errors.geterr();
+}
+
+pub fn zrmdir(path: []const u8) errors.Error!void {
+    const err = rmdir(@ptrCast(path.ptr), path.len);
+    if (err == -1) return errors.geterr();
+}
+
 pub export fn chdir(path: [*]const u8, path_len: usize) isize {
     const err = syscalls.chdir(path, path_len);
     if (err != 0) {
@@ -173,7 +278,7 @@ pub export fn chdir(path: [*]const u8, path_len: usize) isize {
     return 0;
 }
 
-pub export fn getcwd(ptr: [*]const u8, len: usize) isize {
+pub export fn getcwd(ptr: [*]u8, len: usize) isize {
     var dest_len: usize = undefined;
     const err = syscalls.getcwd(ptr, len, &dest_len);
     if (err != 0) {
@@ -1,4 +1,5 @@
 const syscalls = @import("syscalls.zig");
+const raw = @import("raw.zig");
 const errors = @import("errno.zig");
 const seterr = errors.seterr;
 
@@ -16,3 +17,41 @@ pub export fn sbrk(amount: isize) ?*anyopaque {
         return null;
     };
 }
+
+/// maps `len` bytes at `addr` (or anywhere if it is null) with `prot` protection
+/// unless `flags.anonymous` is set the memory is a private copy of the file resource `ri` starting from `offset`
+pub fn zmmap(addr: ?*anyopaque, len: usize, prot: raw.MMapProt, flags: raw.MMapFlags, ri: usize, offset: usize) errors.Error![*]u8 {
+    const config: raw.MMapConfig = .{ .addr = @intFromPtr(addr), .len = len, .prot = prot, .flags = flags, .ri = ri, .offset = offset };
+
+    var dest_addr: usize = 0;
+    const err = syscalls.mmap(&config, @ptrCast(&dest_addr));
+    if (err != 0) {
+        errors.errno = @truncate(err);
+        return errors.geterr();
+    }
+
+    return @ptrFromInt(dest_addr);
+}
+
+pub export fn mmap(addr: ?*anyopaque, len: usize, prot: raw.MMapProt, flags: raw.MMapFlags, ri: usize, offset: usize) ?*anyopaque {
+    return zmmap(addr, len, prot, flags, ri, offset) catch |err| {
+        seterr(err);
+        return null;
+    };
+}
+
+pub fn zmunmap(addr: *anyopaque, len: usize) errors.Error!void {
+    const err = syscalls.munmap(@ptrCast(addr), len);
+    if (err != 0) {
+        errors.errno = @truncate(err);
+        return errors.geterr();
+    }
+}
+
+pub export fn munmap(addr: *anyopaque, len: usize) i32 {
+    zmunmap(addr, len) catch |err| {
+        seterr(err);
+        return -1;
+    };
+    return 0;
+}
@@ -20,7 +20,17 @@ pub const SpawnFlags = packed struct {
     _padding: u6 = 0,
 };
 
-pub const DirEntry = extern struct { kind: u8, size: usize, name_length: usize, name: [128]u8 };
+pub const OpenFlags = packed struct {
+    read: bool = false,
+    write: bool = false,
+    create: bool = false,
+    truncate: bool = false,
+    append: bool = false,
+    exclusive: bool = false,
+    _padding: u2 = 0,
+};
+
+pub const DirEntry = extern struct { kind: u8, size: usize, name_length: usize, name: [128]u8, created: u64, modified: u64 };
 
 pub const SpawnConfig = extern struct {
     name: Slice(u8),
@@ -29,15 +39,40 @@ pub const SpawnConfig = extern struct {
     flags: SpawnFlags,
 };
 
-pub const SysInfo = extern struct { total_mem: usize, used_mem: usize, processes_count: usize };
+pub const MMapProt = packed struct {
+    read: bool = false,
+    write: bool = false,
+    exec: bool = false,
+    _padding: u5 = 0,
+};
+
+pub const MMapFlags = packed struct {
+    fixed: bool = false,
+    anonymous: bool = false,
+    _padding: u6 = 0,
+};
+
+pub const MMapConfig = extern struct {
+    addr: usize,
+    len: usize,
+    prot: MMapProt,
+    flags: MMapFlags,
+    ri: usize,
+    offset: usize,
+};
+
+pub const SysInfo = extern struct { total_mem: usize, used_mem: usize, processes_count: usize, uptime: u64, cpu_count: usize, context_switches: u64 };
+
+pub const TimeSpec = extern struct { seconds: u64, nanoseconds: u64 };
 
 pub const ProcessStatus = enum(u8) {
     Waiting,
     Running,
     Zombie,
+    Blocked,
 };
 
-pub const ProcessInfo = extern struct { ppid: u64, pid: u64, name: [64]u8, status: ProcessStatus, resource_count: usize, exit_code: usize, exit_addr: usize, exit_stack_addr: usize, killed_by: u64, data_start: usize, data_break: usize };
+pub const ProcessInfo = extern struct { ppid: u64, pid: u64, name: [64]u8, status: ProcessStatus, resource_count: usize, exit_code: usize, exit_addr: usize, exit_stack_addr: usize, fault_addr: usize, killed_by: u64, data_start: usize, data_break: usize, nice: i8, user_ticks: u64, kernel_ticks: u64, context_switches: u64, start_time: u64 };
 
 pub const OsStr = extern struct {
     len: usize,
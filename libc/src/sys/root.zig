@@ -4,6 +4,7 @@ pub const errno = @import("errno.zig");
 pub const raw = @import("raw.zig");
 pub const mem = @import("mem.zig");
 pub const utils = @import("utils.zig");
+pub const time = @import("time.zig");
 
 comptime {
     _ = io;
@@ -11,6 +12,7 @@ comptime {
     _ = raw;
     _ = mem;
     _ = utils;
+    _ = time;
 }
 
 const private = @import("../private.zig");
@@ -1,8 +1,26 @@
-//! this file should contain the raw syscall functions
+//! the raw syscall functions, generated by the kernel's build script from it's `#[syscall]` functions
+//! do not edit, see `LIBC_SYSCALLS_PATH` in kernel/build.rs
+const std = @import("std");
 const raw = @import("raw.zig");
 
+// the syscalls are made with the `syscall` instruction which overwrites rcx and r11 with the return address and the flags
+// so the 4th argument is passed in r10 instead of rcx
+
+/// converts an argument of a syscall to the register it is passed in
+inline fn register(value: anytype) usize {
+    const T = @TypeOf(value);
+    return switch (@typeInfo(T)) {
+        .Int => |int| if (int.signedness == .signed) @bitCast(@as(isize, value)) else value,
+        .Bool => @intFromBool(value),
+        .Pointer, .Optional => @intFromPtr(value),
+        // packed structs such as the flags
+        .Struct => @as(std.meta.Int(.unsigned, @bitSizeOf(T)), @bitCast(value)),
+        else => @compileError("cannot pass a " ++ @typeName(T) ++ " to a syscall"),
+    };
+}
+
 inline fn syscall0(number: usize) usize {
-    return asm volatile ("int $0x80"
+    return asm volatile ("syscall"
         : [ret] "={rax}" (-> usize),
         : [number] "{rax}" (number),
         : "rcx", "r11"
@@ -10,7 +28,7 @@ inline fn syscall0(number: usize) usize {
 }
 
 inline fn syscall1(number: usize, arg1: usize) usize {
-    return asm volatile ("int $0x80"
+    return asm volatile ("syscall"
         : [ret] "={rax}" (-> usize),
         : [number] "{rax}" (number),
           [arg1] "{rdi}" (arg1),
@@ -18,127 +36,251 @@ inline fn syscall1(number: usize, arg1: usize) usize {
     );
 }
 
+inline fn syscall2(number: usize, arg1: usize, arg2: usize) usize {
+    return asm volatile ("syscall"
+        : [ret] "={rax}" (-> usize),
+        : [number] "{rax}" (number),
+          [arg1] "{rdi}" (arg1),
+          [arg2] "{rsi}" (arg2),
+        : "rcx", "r11"
+    );
+}
+
 inline fn syscall3(number: usize, arg1: usize, arg2: usize, arg3: usize) usize {
-    return asm volatile ("int $0x80"
+    return asm volatile ("syscall"
         : [ret] "={rax}" (-> usize),
         : [number] "{rax}" (number),
           [arg1] "{rdi}" (arg1),
           [arg2] "{rsi}" (arg2),
-          [arg3] "rdx" (arg3),
+          [arg3] "{rdx}" (arg3),
         : "rcx", "r11"
     );
 }
 
 inline fn syscall4(number: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize) usize {
-    return asm volatile ("int $0x80"
+    return asm volatile ("syscall"
         : [ret] "={rax}" (-> usize),
         : [number] "{rax}" (number),
           [arg1] "{rdi}" (arg1),
           [arg2] "{rsi}" (arg2),
           [arg3] "{rdx}" (arg3),
-          [arg4] "{rcx}" (arg4),
-        : "r8", "r11"
+          [arg4] "{r10}" (arg4),
+        : "rcx", "r11"
     );
 }
+
+inline fn syscall5(number: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize, arg5: usize) usize {
+    return asm volatile ("syscall"
+        : [ret] "={rax}" (-> usize),
+        : [number] "{rax}" (number),
+          [arg1] "{rdi}" (arg1),
+          [arg2] "{rsi}" (arg2),
+          [arg3] "{rdx}" (arg3),
+          [arg4] "{r10}" (arg4),
+          [arg5] "{r8}" (arg5),
+        : "rcx", "r11"
+    );
+}
+
 inline fn syscall6(number: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize, arg5: usize, arg6: usize) usize {
-    return asm volatile ("int $0x80"
+    return asm volatile ("syscall"
         : [ret] "={rax}" (-> usize),
         : [number] "{rax}" (number),
           [arg1] "{rdi}" (arg1),
           [arg2] "{rsi}" (arg2),
           [arg3] "{rdx}" (arg3),
-          [arg4] "{rcx}" (arg4),
+          [arg4] "{r10}" (arg4),
           [arg5] "{r8}" (arg5),
           [arg6] "{r9}" (arg6),
-        : "r10", "r11"
+        : "rcx", "r11"
     );
 }
+
+/// for now
 pub inline fn exit(code: usize) void {
-    _ = syscall1(0, code);
+    _ = syscall1(0, register(code));
 }
 
 pub inline fn yield() void {
-    _ = syscall1(1);
+    _ = syscall0(1);
 }
 
-pub inline fn open(path: *const u8, len: usize, fd: *usize) usize {
-    return syscall3(2, @intFromPtr(path), len, @intFromPtr(fd));
+/// see `OpenFlags` for what `flags` does, reading or writing without `READ` or `WRITE` fails with `MissingPermissions`
+pub inline fn open(path_ptr: [*]const u8, path_len: usize, flags: raw.OpenFlags, dest_fd: ?*usize) usize {
+    return syscall4(2, register(path_ptr), register(path_len), register(flags), register(dest_fd));
 }
 
-pub inline fn write(fd: usize, ptr: *const u8, len: usize) usize {
-    return syscall4(3, fd, @intFromPtr(ptr), len, 0);
+pub inline fn write(fd: usize, buffer_ptr: [*]const u8, buffer_len: usize) usize {
+    return syscall3(3, register(fd), register(buffer_ptr), register(buffer_len));
 }
 
-pub inline fn read(fd: usize, ptr: *u8, len: usize, num_read: *usize) usize {
-    return syscall4(4, fd, @intFromPtr(ptr), len, @intFromPtr(num_read));
+pub inline fn read(fd: usize, buffer_ptr: [*]u8, buffer_len: usize, dest_read: ?*usize) usize {
+    return syscall4(4, register(fd), register(buffer_ptr), register(buffer_len), register(dest_read));
 }
 
 pub inline fn close(fd: usize) usize {
-    return syscall1(5, fd);
+    return syscall1(5, register(fd));
 }
 
-pub inline fn create(path_ptr: *const u8, path_len: usize) usize {
-    return syscall3(6, @intFromPtr(path_ptr), path_len, 0);
+pub inline fn create(path_ptr: [*]const u8, path_len: usize) usize {
+    return syscall2(6, register(path_ptr), register(path_len));
 }
 
-pub inline fn createdir(path_ptr: *const u8, path_len: usize) usize {
-    return syscall3(7, @intFromPtr(path_ptr), path_len, 0);
+pub inline fn createdir(path_ptr: [*]const u8, path_len: usize) usize {
+    return syscall2(7, register(path_ptr), register(path_len));
 }
 
 pub inline fn diriter_open(dir_ri: usize, dest_diriter: *usize) usize {
-    return syscall3(8, dir_ri, @intFromPtr(dest_diriter), 0);
+    return syscall2(8, register(dir_ri), register(dest_diriter));
 }
 
-pub inline fn diriter_close(diriter: usize) usize {
-    return syscall1(9, diriter);
+pub inline fn diriter_close(diriter_ri: usize) usize {
+    return syscall1(9, register(diriter_ri));
 }
 
-pub inline fn diriter_next(diriter: usize, direntry: *raw.DirEntry) usize {
-    return syscall3(10, diriter, @intFromPtr(direntry), 0);
+pub inline fn diriter_next(diriter_ri: usize, direntry: *raw.DirEntry) usize {
+    return syscall2(10, register(diriter_ri), register(direntry));
 }
 
-pub inline fn wait(pid: usize) usize {
-    return syscall1(11, pid);
+pub inline fn wait(pid: u64) usize {
+    return syscall1(11, register(pid));
 }
 
 pub inline fn fstat(ri: usize, direntry: *raw.DirEntry) usize {
-    return syscall3(12, ri, @intFromPtr(direntry), 0);
+    return syscall2(12, register(ri), register(direntry));
 }
 
-pub inline fn spawn(elf_ptr: *const u8, elf_len: usize, config: *const raw.SpawnConfig, dest_pid: *u64) usize {
-    return syscall4(13, @intFromPtr(elf_ptr), elf_len, @intFromPtr(config), @intFromPtr(dest_pid));
+/// if dest_pid is null we will just ignore it
+pub inline fn spawn(elf_ptr: [*]const u8, elf_len: usize, config: *const raw.SpawnConfig, dest_pid: ?*u64) usize {
+    return syscall4(13, register(elf_ptr), register(elf_len), register(config), register(dest_pid));
 }
 
 pub inline fn chdir(path_ptr: [*]const u8, path_len: usize) usize {
-    return syscall3(14, @intFromPtr(path_ptr), path_len, 0);
+    return syscall2(14, register(path_ptr), register(path_len));
 }
 
-pub inline fn getcwd(ptr: [*]const u8, len: usize, dest_len_got: *usize) usize {
-    return syscall3(15, @intFromPtr(ptr), len, @intFromPtr(dest_len_got));
+pub inline fn getcwd(path_ptr: [*]u8, path_len: usize, dest_len: ?*usize) usize {
+    return syscall3(15, register(path_ptr), register(path_len), register(dest_len));
 }
 
-pub inline fn info(ptr: *raw.SysInfo) usize {
-    return syscall1(16, @intFromPtr(ptr));
+pub inline fn info(info_arg: *raw.SysInfo) usize {
+    return syscall1(16, register(info_arg));
 }
 
-pub inline fn pcollect(ptr: *raw.ProcessInfo, len: usize) usize {
-    return syscall3(17, @intFromPtr(ptr), len, 0);
+pub inline fn pcollect(infos_ptr: [*]raw.ProcessInfo, infos_len: usize) usize {
+    return syscall2(17, register(infos_ptr), register(infos_len));
 }
 
+/// on fail returns null for unknown reasons
 pub inline fn sbrk(amount: isize) ?*u8 {
-    return @ptrFromInt(syscall1(18, @bitCast(amount)));
+    return @ptrFromInt(syscall1(18, register(amount)));
+}
+
+pub inline fn pspawn(path_ptr: [*]const u8, path_len: usize, config: *const raw.SpawnConfig, dest_pid: ?*u64) usize {
+    return syscall4(19, register(path_ptr), register(path_len), register(config), register(dest_pid));
+}
+
+pub inline fn shutdown() usize {
+    return syscall0(20);
+}
+
+pub inline fn reboot() usize {
+    return syscall0(21);
+}
+
+/// the child sees `dest_pid` as it was before the fork, so it can tell itself apart by initializing it to 0
+pub inline fn fork(dest_pid: ?*u64) usize {
+    return syscall1(22, register(dest_pid));
+}
+
+/// only returns if it fails
+pub inline fn exec(path_ptr: [*]const u8, path_len: usize, argv_ptr: [*]const raw.Slice(u8), argv_len: usize) usize {
+    return syscall4(23, register(path_ptr), register(path_len), register(argv_ptr), register(argv_len));
+}
+
+pub inline fn mmap(config: *const raw.MMapConfig, dest_addr: ?*?*u8) usize {
+    return syscall2(24, register(config), register(dest_addr));
+}
+
+pub inline fn munmap(addr: ?*u8, len: usize) usize {
+    return syscall2(25, register(addr), register(len));
+}
+
+/// `addr` is the base to set for the set codes, the base is written to `dest` for the get codes
+pub inline fn arch_prctl(code: usize, addr: usize, dest: ?*usize) usize {
+    return syscall3(26, register(code), register(addr), register(dest));
+}
+
+/// the thread starts executing `entry` with `arg` as it's only argument, `entry` must never return
+pub inline fn thread_spawn(entry: usize, arg: usize, dest_tid: ?*u64) usize {
+    return syscall3(27, register(entry), register(arg), register(dest_tid));
+}
+
+pub inline fn thread_exit(code: usize) void {
+    _ = syscall1(28, register(code));
+}
+
+pub inline fn thread_join(tid: u64, dest_code: ?*usize) usize {
+    return syscall2(29, register(tid), register(dest_code));
+}
+
+pub inline fn sleep(ms: u64) void {
+    _ = syscall1(30, register(ms));
+}
+
+/// returns the milliseconds since boot
+pub inline fn uptime() u64 {
+    return syscall0(31);
+}
+
+pub inline fn clock_gettime(clock: usize, dest: *raw.TimeSpec) usize {
+    return syscall2(32, register(clock), register(dest));
+}
+
+/// returns the seconds since the unix epoch
+pub inline fn time() u64 {
+    return syscall0(33);
+}
+
+/// `nice` is clamped to the range of vaild nice values
+pub inline fn setpriority(pid: u64, nice: isize) usize {
+    return syscall2(34, register(pid), register(nice));
+}
+
+/// logs the syscalls of `pid` to serial if `on` isn't 0 and stops logging them otherwise
+/// `pid` has to be the caller or one of it's descendants
+pub inline fn systrace(pid: u64, on: usize) usize {
+    return syscall2(35, register(pid), register(on));
+}
+
+/// fails with `ErrorStatus::Busy` if the file is still open
+pub inline fn remove(path_ptr: [*]const u8, path_len: usize) usize {
+    return syscall2(36, register(path_ptr), register(path_len));
+}
+
+/// the directory has to be empty and not open
+pub inline fn rmdir(path_ptr: [*]const u8, path_len: usize) usize {
+    return syscall2(37, register(path_ptr), register(path_len));
+}
+
+/// moves `old` to `new` replacing `new` if it exists, both have to be on the same drive
+/// like `remove` it fails with `Busy` if `new` is replaced while it is still open
+pub inline fn rename(old_ptr: [*]const u8, old_len: usize, new_ptr: [*]const u8, new_len: usize) usize {
+    return syscall4(38, register(old_ptr), register(old_len), register(new_ptr), register(new_len));
 }
 
-pub inline fn pspawn(path_ptr: *const u8, path_len: usize, config: *const raw.SpawnConfig, dest_pid: *u64) usize {
-    return syscall4(19, @intFromPtr(path_ptr), path_len, @intFromPtr(config), @intFromPtr(dest_pid));
+/// `whence` is 0 to seek from the start of the file, 1 from the current position and 2 from the end
+/// the new position is written to `dest_offset`
+pub inline fn seek(fd: usize, offset: isize, whence: usize, dest_offset: ?*usize) usize {
+    return syscall4(39, register(fd), register(offset), register(whence), register(dest_offset));
 }
 
-pub inline fn shutdown() noreturn {
-    _ = syscall0(20);
-    unreachable;
+/// reads at `offset` without moving the position of `fd`
+pub inline fn pread(fd: usize, buffer_ptr: [*]u8, buffer_len: usize, offset: usize, dest_read: ?*usize) usize {
+    return syscall5(40, register(fd), register(buffer_ptr), register(buffer_len), register(offset), register(dest_read));
 }
 
-pub inline fn reboot() noreturn {
-    _ = syscall0(21);
-    unreachable;
+/// writes at `offset` without moving the position of `fd`
+pub inline fn pwrite(fd: usize, buffer_ptr: [*]const u8, buffer_len: usize, offset: usize) usize {
+    return syscall4(41, register(fd), register(buffer_ptr), register(buffer_len), register(offset));
 }
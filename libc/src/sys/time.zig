@@ -0,0 +1,73 @@
+const raw = @import("raw.zig");
+const syscalls = @import("syscalls.zig");
+const errno = @import("errno.zig");
+
+/// how many times the kernel's timer fires each second, the cpu times of processes are counted in its ticks
+pub const TICKS_PER_SECOND: u64 = 1000;
+
+pub const CLOCK_REALTIME: c_int = 0;
+pub const CLOCK_MONOTONIC: c_int = 1;
+
+/// blocks the current thread for at least `ms` milliseconds
+pub export fn msleep(ms: u64) void {
+    syscalls.sleep(ms);
+}
+
+/// returns the milliseconds since boot
+pub export fn uptime() u64 {
+    return syscalls.uptime();
+}
+
+/// returns the seconds since the unix epoch, also writes them to `tloc` if it isn't null
+pub export fn time(tloc: ?*u64) u64 {
+    const now = syscalls.time();
+    if (tloc) |ptr| ptr.* = now;
+    return now;
+}
+
+/// writes the time of `clock` to `tp`, returns 0 on success and -1 on error
+pub export fn clock_gettime(clock: c_int, tp: *raw.TimeSpec) c_int {
+    const err = syscalls.clock_gettime(@intCast(clock), tp);
+    if (err != 0) {
+        errno.errno = @truncate(err);
+        return -1;
+    }
+    return 0;
+}
+
+pub fn zclock_gettime(clock: c_int) errno.Error!raw.TimeSpec {
+    var tp: raw.TimeSpec = undefined;
+    if (clock_gettime(clock, &tp) == -1) return errno.geterr();
+    return tp;
+}
+
+pub const DateTime = struct {
+    year: u64,
+    month: u64,
+    day: u64,
+    hours: u64,
+    minutes: u64,
+    seconds: u64,
+};
+
+/// converts the seconds since the unix epoch to a UTC date
+pub fn zgmtime(epoch: u64) DateTime {
+    const days = epoch / 86400 + 719468;
+    const time_of_day = epoch % 86400;
+
+    const era = days / 146097;
+    const day_of_era = days - era * 146097;
+    const year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    const day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    const mp = (5 * day_of_year + 2) / 153;
+    const month = if (mp < 10) mp + 3 else mp - 9;
+
+    return .{
+        .year = year_of_era + era * 400 + @intFromBool(month <= 2),
+        .month = month,
+        .day = day_of_year - (153 * mp + 2) / 5 + 1,
+        .hours = time_of_day / 3600,
+        .minutes = time_of_day % 3600 / 60,
+        .seconds = time_of_day % 60,
+    };
+}
@@ -63,3 +63,114 @@ pub fn zpspwan(path: []const u8, argv: []const raw.Slice(u8), name: []const u8)
 
     return pid;
 }
+
+/// duplicates the current process, the child continues from here too
+/// returns the pid of the child to the parent and 0 to the child, returns -1 on error
+pub export fn fork() isize {
+    // the child sees the value before the kernel writes the pid
+    var pid: u64 = 0;
+    const err = syscalls.fork(&pid);
+    if (err != 0) {
+        errno.errno = @truncate(err);
+        return -1;
+    }
+
+    return @intCast(pid);
+}
+
+pub fn zfork() errno.Error!u64 {
+    const pid = fork();
+    if (pid == -1) return errno.geterr();
+    return @intCast(pid);
+}
+
+/// sets the nice value of the process `pid`, it has to be the current process or one of it's descendants
+/// `nice` is clamped between -20 and 19, the lower it is the more cpu time the process gets
+/// returns 0 on success and -1 on error
+pub export fn setpriority(pid: u64, nice: c_int) c_int {
+    const err = syscalls.setpriority(pid, nice);
+    if (err != 0) {
+        errno.errno = @truncate(err);
+        return -1;
+    }
+    return 0;
+}
+
+pub fn zsetpriority(pid: u64, nice: c_int) errno.Error!void {
+    if (setpriority(pid, nice) == -1) return errno.geterr();
+}
+
+/// starts logging the syscalls of the process `pid` to serial if `on` is non-zero and stops otherwise
+/// `pid` has to be the current process or one of it's descendants
+/// returns 0 on success and -1 on error
+pub export fn systrace(pid: u64, on: c_int) c_int {
+    const err = syscalls.systrace(pid, @intFromBool(on != 0));
+    if (err != 0) {
+        errno.errno = @truncate(err);
+        return -1;
+    }
+    return 0;
+}
+
+pub fn zsystrace(pid: u64, on: bool) errno.Error!void {
+    if (systrace(pid, @intFromBool(on)) == -1) return errno.geterr();
+}
+
+pub const ARCH_SET_GS: c_int = 0x1001;
+pub const ARCH_SET_FS: c_int = 0x1002;
+pub const ARCH_GET_FS: c_int = 0x1003;
+pub const ARCH_GET_GS: c_int = 0x1004;
+
+/// sets the fs or gs base to `addr` or gets it into the usize `addr` points to depending on `code`
+/// returns 0 on success and -1 on error
+pub export fn arch_prctl(code: c_int, addr: usize) c_int {
+    const err = switch (code) {
+        ARCH_GET_FS, ARCH_GET_GS => syscalls.arch_prctl(@intCast(code), 0, @ptrFromInt(addr)),
+        else => syscalls.arch_prctl(@intCast(code), addr, null),
+    };
+
+    if (err != 0) {
+        errno.errno = @truncate(err);
+        return -1;
+    }
+    return 0;
+}
+
+/// spawns a thread in the current process that starts executing `entry` with `arg`
+/// `entry` must exit the thread with `thread_exit` instead of returning
+pub fn zthread_spawn(entry: *const fn (usize) callconv(.C) noreturn, arg: usize) errno.Error!u64 {
+    var tid: u64 = undefined;
+    const err = syscalls.thread_spawn(@intFromPtr(entry), arg, &tid);
+    if (err != 0) {
+        errno.errno = @truncate(err);
+        return errno.geterr();
+    }
+
+    return tid;
+}
+
+/// exits the current thread, exits the process too if it was the last thread
+pub export fn thread_exit(code: usize) noreturn {
+    syscalls.thread_exit(code);
+    unreachable;
+}
+
+/// waits for the thread `tid` of the current process to exit and returns it's exit code
+pub fn zthread_join(tid: u64) errno.Error!usize {
+    var code: usize = undefined;
+    const err = syscalls.thread_join(tid, &code);
+    if (err != 0) {
+        errno.errno = @truncate(err);
+        return errno.geterr();
+    }
+
+    return code;
+}
+
+/// replaces the current process with the executable at `path`, passing it `argv`
+/// only returns if it fails
+pub fn zexec(path: []const u8, argv: []const raw.Slice(u8)) errno.Error {
+    const err = syscalls.exec(@ptrCast(path.ptr), path.len, argv.ptr, argv.len);
+    errno.errno = @truncate(err);
+    return errno.geterr();
+}
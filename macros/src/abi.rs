@@ -0,0 +1,116 @@
+//! how the arguments of a `#[syscall]` function cross the syscall boundary
+//! shared by the `syscall` attribute and the kernel's build script which describes the abi of every syscall
+// some of it is only used by the build script
+#![allow(dead_code)]
+use syn::{GenericArgument, PathArguments, Type};
+
+/// the kind of a syscall argument
+pub enum Arg<'a> {
+    /// `&str` or `String`, passed as a pointer to utf8 bytes and a length
+    Str { owned: bool },
+    /// `&[&str]` or `Vec<String>`, passed as a pointer to (pointer, length) pairs and a length
+    StrSlice { owned: bool },
+    /// `&[T]` or `&mut [T]`, passed as a pointer and a length
+    Slice { elem: &'a Type, mutable: bool },
+    /// `Option<&mut T>`, passed as a pointer that may be null
+    Optional(&'a Type),
+    /// `&T` or `&mut T`, passed as a pointer that may not be null
+    Required { elem: &'a Type, mutable: bool },
+    /// anything else is passed as is
+    Value(&'a Type),
+}
+
+impl Arg<'_> {
+    /// the name of the kind in the abi description
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Str { .. } => "str",
+            Self::StrSlice { .. } => "str_slice",
+            Self::Slice { mutable: false, .. } => "slice",
+            Self::Slice { mutable: true, .. } => "slice_mut",
+            Self::Optional(_) => "optional",
+            Self::Required { mutable: false, .. } => "required",
+            Self::Required { mutable: true, .. } => "required_mut",
+            Self::Value(_) => "value",
+        }
+    }
+
+    /// the type the argument points to or the type of the argument for values, None for strs
+    pub fn ty(&self) -> Option<&Type> {
+        match self {
+            Self::Str { .. } | Self::StrSlice { .. } => None,
+            Self::Slice { elem, .. } | Self::Optional(elem) | Self::Required { elem, .. } => {
+                Some(elem)
+            }
+            Self::Value(ty) => Some(ty),
+        }
+    }
+}
+
+/// returns the last segment of the path `ty` if it is a path
+fn last_segment(ty: &Type) -> Option<&syn::PathSegment> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last(),
+        _ => None,
+    }
+}
+
+fn is_str(ty: &Type) -> bool {
+    last_segment(ty).is_some_and(|segment| segment.ident == "str" && segment.arguments.is_empty())
+}
+
+/// returns the type argument of `ty` if it is `name<T>`
+fn generic_arg<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let segment = last_segment(ty).filter(|segment| segment.ident == name)?;
+    let PathArguments::AngleBracketed(ref args) = segment.arguments else {
+        return None;
+    };
+
+    match args.args.first()? {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+fn is_string(ty: &Type) -> bool {
+    last_segment(ty)
+        .is_some_and(|segment| segment.ident == "String" && segment.arguments.is_empty())
+}
+
+/// returns `T` if `ty` is `Option<&mut T>`
+fn optional_elem(ty: &Type) -> Option<&Type> {
+    match generic_arg(ty, "Option")? {
+        Type::Reference(reference) if reference.mutability.is_some() => Some(&reference.elem),
+        _ => None,
+    }
+}
+
+pub fn classify(ty: &Type) -> Arg<'_> {
+    if let Some(elem) = optional_elem(ty) {
+        return Arg::Optional(elem);
+    }
+
+    if is_string(ty) {
+        return Arg::Str { owned: true };
+    }
+
+    if generic_arg(ty, "Vec").is_some_and(is_string) {
+        return Arg::StrSlice { owned: true };
+    }
+
+    let Type::Reference(reference) = ty else {
+        return Arg::Value(ty);
+    };
+    let mutable = reference.mutability.is_some();
+
+    match &*reference.elem {
+        elem if is_str(elem) && !mutable => Arg::Str { owned: false },
+        Type::Slice(slice) => match &*slice.elem {
+            Type::Reference(inner) if !mutable && is_str(&inner.elem) => {
+                Arg::StrSlice { owned: false }
+            }
+            elem => Arg::Slice { elem, mutable },
+        },
+        elem => Arg::Required { elem, mutable },
+    }
+}
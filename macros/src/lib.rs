@@ -1,8 +1,14 @@
 use core::panic;
 
 use proc_macro::TokenStream;
-use quote::{quote, ToTokens};
-use syn::{parse_macro_input, parse_quote, DeriveInput, ImplItem, Item, ItemImpl, ItemMod};
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    parse_macro_input, parse_quote, DeriveInput, FnArg, ImplItem, Item, ItemFn, ItemImpl, ItemMod,
+    LitInt, Pat, ReturnType,
+};
+
+mod abi;
+use abi::Arg;
 
 /// takes a mod and puts a function called `test_main` in it which executes all of it is functions
 /// used by kernel for tests (test feature)
@@ -131,3 +137,197 @@ pub fn display_consts(_attr: TokenStream, item: TokenStream) -> TokenStream {
 
     results.into()
 }
+
+/// turns a rust function into the syscall with the number given to the attribute
+///
+/// the function itself is kept as is while an `extern "C"` function with the same name is generated around it
+/// the generated function takes the arguments in their ffi form, checks them and calls the original function
+/// - `&str` and `&[T]` are taken as a pointer and a length, see `Slice` and `SliceMut`, and copied out of userspace before the call
+/// - `&mut [T]` is taken the same way and copied back after the call
+/// - `&[&str]` is taken as a pointer to `Slice<u8>`s and a length, the strs are copied out of userspace before the call
+/// - `String` and `Vec<String>` are taken like `&str` and `&[&str]` but given to the function, for syscalls such as `exec` that never return to free them
+/// - `Option<&mut T>` is taken as an `Optional<T>`, copied out of userspace before the call and back after it
+/// - `&T` is taken as a `Required<T>` and copied out of userspace before the call so it cannot change while it is used
+/// - `&mut T` is taken as a `RequiredMut<T>`, copied out of userspace before the call and back after it
+/// - anything else is taken as is
+///
+/// the function only ever sees copies in kernel memory, userspace memory is only accessible while they are copied, see `arch::usercopy`
+///
+/// checking the arguments uses `?` so functions with arguments that need checking must return an `ErrorStatus`
+///
+/// calls made by traced processes are logged with their arguments and results, see `syscalls::trace`
+/// the time spent in the call is charged to the process as kernel time, see `threading::expose::enter_syscall`
+/// the number is only read by the kernel's build script which generates the syscall table, numbers are never reused
+/// example:
+/// ```rust
+/// #[syscall(3)]
+/// fn syswrite(fd: usize, buffer: &[u8]) -> ErrorStatus {
+///     ...
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn syscall(attr: TokenStream, item: TokenStream) -> TokenStream {
+    // the number is checked here so mistakes are reported at the attribute
+    let number = parse_macro_input!(attr as LitInt)
+        .base10_parse::<u16>()
+        .expect("expected the syscall number");
+
+    let mut func = parse_macro_input!(item as ItemFn);
+    let name = func.sig.ident.clone();
+    let output = func.sig.output.clone();
+    let attrs = core::mem::take(&mut func.attrs);
+
+    let trace_name = name.to_string();
+    let trace_name = trace_name.strip_prefix("sys").unwrap_or(&trace_name);
+    let ret = match &output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ty) => ty.to_token_stream(),
+    };
+
+    let mut params = Vec::new();
+    let mut checks = Vec::new();
+    // copies the arguments that were copied out of userspace back after the call
+    let mut writes = Vec::new();
+    let mut args = Vec::new();
+    // how each argument is shown in the trace, taken from the ffi arguments before they are checked
+    let mut trace_fmt = Vec::new();
+    let mut trace_args = Vec::new();
+
+    for input in &func.sig.inputs {
+        let FnArg::Typed(input) = input else {
+            panic!("syscalls cannot take self");
+        };
+
+        let Pat::Ident(ref ident) = *input.pat else {
+            panic!("syscall arguments must be identifiers");
+        };
+
+        let arg = &ident.ident;
+        let ptr = format_ident!("{}_ptr", arg);
+        let len = format_ident!("{}_len", arg);
+        let value = format_ident!("{}_value", arg);
+        let strs = format_ident!("{}_strs", arg);
+        let slice = format_ident!("{}_slice", arg);
+
+        let trace = quote! { crate::syscalls::trace };
+        trace_fmt.push(format!("{arg}: {{:?}}"));
+        trace_args.push(match abi::classify(&input.ty) {
+            Arg::Str { .. } => quote! { #trace::Str(#ptr, #len) },
+            Arg::StrSlice { .. } | Arg::Slice { .. } => {
+                quote! { #trace::Buffer(#ptr as usize, #len) }
+            }
+            Arg::Optional(_) | Arg::Required { .. } => {
+                quote! { #trace::Pointer(#ptr.as_ptr() as usize) }
+            }
+            Arg::Value(_) => quote! { #arg },
+        });
+
+        match abi::classify(&input.ty) {
+            Arg::Str { owned } => {
+                params.push(quote! { #ptr: *const u8, #len: usize });
+                checks.push(quote! {
+                    let #value = crate::utils::ffi::Slice::new(#ptr, #len)?.read_str()?;
+                });
+                checks.push(if owned {
+                    quote! { let #arg = #value; }
+                } else {
+                    quote! { let #arg = #value.as_str(); }
+                });
+            }
+            Arg::StrSlice { owned } => {
+                params.push(quote! { #ptr: *const crate::utils::ffi::Slice<u8>, #len: usize });
+                checks.push(quote! {
+                    let #value = crate::utils::ffi::Slice::new(#ptr, #len)?.read_str_slice()?;
+                });
+                checks.push(if owned {
+                    quote! { let #arg = #value; }
+                } else {
+                    quote! {
+                        let #strs: ::alloc::vec::Vec<&str> = #value.iter().map(|s| s.as_str()).collect();
+                        let #arg = &#strs[..];
+                    }
+                });
+            }
+            Arg::Slice {
+                elem,
+                mutable: false,
+            } => {
+                params.push(quote! { #ptr: *const #elem, #len: usize });
+                checks.push(quote! {
+                    let #value = crate::utils::ffi::Slice::new(#ptr, #len)?.read()?;
+                    let #arg = &#value[..];
+                });
+            }
+            Arg::Slice {
+                elem,
+                mutable: true,
+            } => {
+                params.push(quote! { #ptr: *mut #elem, #len: usize });
+                checks.push(quote! {
+                    let #slice = crate::utils::ffi::SliceMut::new(#ptr, #len)?;
+                    let mut #value = #slice.read()?;
+                    let #arg = &mut #value[..];
+                });
+                writes.push(quote! { #slice.write(&#value)?; });
+            }
+            Arg::Optional(elem) => {
+                params.push(quote! { #ptr: crate::utils::ffi::Optional<#elem> });
+                checks.push(quote! {
+                    let mut #value = #ptr.read()?;
+                    let #arg = #value.as_mut();
+                });
+                writes.push(quote! { #ptr.write(#value)?; });
+            }
+            Arg::Required {
+                elem,
+                mutable: false,
+            } => {
+                params.push(quote! { #ptr: crate::utils::ffi::Required<#elem> });
+                checks.push(quote! { let #arg = &#ptr.read()?; });
+            }
+            Arg::Required {
+                elem,
+                mutable: true,
+            } => {
+                params.push(quote! { #ptr: crate::utils::ffi::RequiredMut<#elem> });
+                checks.push(quote! {
+                    let mut #value = #ptr.read()?;
+                    let #arg = &mut #value;
+                });
+                writes.push(quote! { #ptr.write(#value)?; });
+            }
+            Arg::Value(ty) => params.push(quote! { #arg: #ty }),
+        }
+
+        args.push(arg.clone());
+    }
+    let trace_fmt = trace_fmt.join(", ");
+
+    TokenStream::from(quote! {
+        #(#attrs)*
+        #[no_mangle]
+        extern "C" fn #name(#(#params),*) #output {
+            #[inline(always)]
+            #func
+
+            crate::threading::expose::enter_syscall();
+            let trace = crate::syscalls::trace::Trace::begin(
+                #number,
+                #trace_name,
+                format_args!(#trace_fmt, #(#trace_args),*),
+            );
+            let result = (|| -> #ret {
+                #(#checks)*
+                let result = #name(#(#args),*);
+                #(#writes)*
+                result
+            })();
+
+            if let Some(trace) = trace {
+                trace.end(&result);
+            }
+            crate::threading::expose::leave_syscall();
+            result
+        }
+    })
+}